@@ -0,0 +1,525 @@
+use bcrypt::{hash, verify, DEFAULT_COST};
+use serde::Deserialize;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    service::nullable,
+    sessions::revoke_other_sessions,
+    structs::{FieldError, Profile, User},
+};
+
+/// The HTTP status codes a link can redirect with
+const REDIRECT_TYPES: [i16; 4] = [301, 302, 307, 308];
+
+/// Changes to apply to the settings of a user, following JSON Merge Patch semantics
+/// 
+/// Absent fields are left unchanged and fields set to `null` are cleared.
+#[derive(Deserialize, Default)]
+pub struct SettingsPatch {
+    /// The new default expiration time in seconds, new links never expiring by default if cleared
+    #[serde(default, deserialize_with = "nullable")]
+    pub default_expiration: Option<Option<i64>>,
+    /// The new default redirect type, which can't be cleared
+    #[serde(default, deserialize_with = "nullable")]
+    pub default_redirect_type: Option<Option<i16>>,
+}
+
+/// Validates a new password with the rules applied when registering
+/// 
+/// # Arguments
+/// * `password` - The new password
+/// * `confirm_password` - The new password entered a second time
+/// 
+/// # Returns
+/// Result that is an error naming the invalid field if the password is rejected
+pub fn validate_new_password(password: &str, confirm_password: &str) -> Result<(), std::io::Error> {
+    if password.is_empty() {
+        return Err(FieldError::invalid("password", "Password cannot be empty"));
+    }
+    if password.len() < 6 {
+        return Err(FieldError::invalid(
+            "password",
+            "Password must be at least 6 characters long",
+        ));
+    }
+    if password != confirm_password {
+        return Err(FieldError::invalid(
+            "confirm_password",
+            "Passwords do not match",
+        ));
+    }
+    Ok(())
+}
+
+/// Changes the password of a user and logs out their other devices
+/// 
+/// Every session except the current one is revoked, so whoever knew the old
+/// password is logged out.
+/// 
+/// # Arguments
+/// * `user` - The user changing their password
+/// * `current_password` - The password the user currently logs in with
+/// * `password` - The new password
+/// * `confirm_password` - The new password entered a second time
+/// * `current_session` - The session the request was made with, kept logged in
+/// * `pool` - Database connection pool
+/// 
+/// # Returns
+/// Result containing the number of revoked sessions
+pub async fn change_password(
+    user: &User,
+    current_password: &str,
+    password: &str,
+    confirm_password: &str,
+    current_session: Option<Uuid>,
+    pool: &PgPool,
+) -> Result<u64, std::io::Error> {
+    if !verify(current_password, &user.password).unwrap_or(false) {
+        return Err(FieldError::invalid(
+            "current_password",
+            "Current password is incorrect",
+        ));
+    }
+    validate_new_password(password, confirm_password)?;
+
+    let hashed = hash(password, DEFAULT_COST).map_err(|e| std::io::Error::other(e.to_string()))?;
+
+    let mut tx = pool
+        .begin()
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+    sqlx::query("UPDATE users SET password = $2 WHERE id = $1")
+        .bind(user.id)
+        .bind(&hashed)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+    let revoked = revoke_other_sessions(user, current_session, &mut *tx).await?;
+    tx.commit()
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+    Ok(revoked)
+}
+
+/// Retrieves the profile of a user
+/// 
+/// The link counts and clicks cover the user's personal workspace, trashed
+/// links still counting towards the clicks.
+/// 
+/// # Arguments
+/// * `user` - The user to retrieve the profile of
+/// * `pool` - Database connection pool
+/// 
+/// # Returns
+/// Result containing the profile with the link statistics and settings
+pub async fn get_profile(user: &User, pool: &PgPool) -> Result<Profile, std::io::Error> {
+    sqlx::query_as::<_, Profile>(
+        r#"
+      SELECT
+        u.id,
+        u.username,
+        COUNT(l.id) FILTER (WHERE l.deleted_at IS NULL) AS links,
+        COUNT(l.id) FILTER (WHERE l.deleted_at IS NOT NULL) AS trashed_links,
+        COALESCE(SUM(l.redirects), 0)::BIGINT AS total_clicks,
+        u.default_expiration,
+        u.default_redirect_type
+      FROM users u
+      LEFT JOIN shortened_urls l ON l.owner = u.id
+      WHERE u.id = $1
+      GROUP BY u.id
+      "#,
+    )
+    .bind(user.id)
+    .fetch_one(pool)
+    .await
+    .map_err(|e| std::io::Error::other(e.to_string()))
+}
+
+/// Updates the settings of a user, leaving the fields absent from the patch unchanged
+/// 
+/// The defaults apply to links the user creates afterwards, existing links are left unchanged.
+/// 
+/// # Arguments
+/// * `user` - The user updating their settings
+/// * `patch` - The settings to change
+/// * `pool` - Database connection pool
+/// 
+/// # Returns
+/// Result containing the updated profile
+pub async fn update_settings(
+    user: &User,
+    patch: &SettingsPatch,
+    pool: &PgPool,
+) -> Result<Profile, std::io::Error> {
+    if patch
+        .default_expiration
+        .flatten()
+        .is_some_and(|secs| secs <= 0)
+    {
+        return Err(FieldError::invalid(
+            "default_expiration",
+            "Default expiration must be a positive number of seconds",
+        ));
+    }
+    let redirect_type = match patch.default_redirect_type {
+        Some(None) => {
+            return Err(FieldError::invalid(
+                "default_redirect_type",
+                "Default redirect type cannot be cleared",
+            ))
+        }
+        Some(Some(code)) if !REDIRECT_TYPES.contains(&code) => {
+            return Err(FieldError::invalid(
+                "default_redirect_type",
+                "Default redirect type must be one of 301, 302, 307 or 308",
+            ))
+        }
+        Some(Some(code)) => Some(code),
+        None => None,
+    };
+
+    sqlx::query(
+        r#"
+      UPDATE users SET
+        default_expiration = CASE WHEN $2 THEN $3 ELSE default_expiration END,
+        default_redirect_type = COALESCE($4, default_redirect_type)
+      WHERE id = $1
+      "#,
+    )
+    .bind(user.id)
+    .bind(patch.default_expiration.is_some())
+    .bind(patch.default_expiration.flatten())
+    .bind(redirect_type)
+    .execute(pool)
+    .await
+    .map_err(|e| std::io::Error::other(e.to_string()))?;
+
+    get_profile(user, pool).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        service::{create_url, delete_url},
+        sessions::{create_session, is_session_active, ClientInfo},
+        utils::{create_test_user, delete_test_users, init_test_db},
+        workspaces::Member,
+    };
+    use std::io::ErrorKind;
+
+    /// Returns the field a rejected request was reported on
+    fn target_field(e: &std::io::Error) -> String {
+        e.get_ref()
+            .and_then(|inner| inner.downcast_ref::<FieldError>())
+            .map(|f| f.target_field.clone())
+            .unwrap()
+    }
+
+    /// Sets the password of a test user
+    /// 
+    /// # Arguments
+    /// * `user` - The user to set the password of
+    /// * `password` - The new password
+    /// * `pool` - Database connection pool
+    async fn set_password(user: &mut User, password: &str, pool: &PgPool) {
+        user.password = hash(password, DEFAULT_COST).unwrap();
+        sqlx::query("UPDATE users SET password = $2 WHERE id = $1")
+            .bind(user.id)
+            .bind(&user.password)
+            .execute(pool)
+            .await
+            .unwrap();
+    }
+
+    /// Starts a session of a user
+    /// 
+    /// # Arguments
+    /// * `user` - The user logging in
+    /// * `pool` - Database connection pool
+    /// 
+    /// # Returns
+    /// The ID of the session
+    async fn start_session(user: &User, pool: &PgPool) -> Uuid {
+        create_session(user, false, &ClientInfo::default(), pool)
+            .await
+            .unwrap()
+            .session_id
+    }
+
+    /// Parses a settings merge patch
+    fn settings_patch(json: &str) -> SettingsPatch {
+        serde_json::from_str(json).unwrap()
+    }
+
+    /// Tests that the current password is required, leaving the sessions alone when it's wrong
+    #[actix_rt::test]
+    async fn test_change_password_wrong_current() {
+        let pool = init_test_db().await;
+        let mut user = create_test_user(&pool, "account_wrong_password").await;
+        set_password(&mut user, "old_password", &pool).await;
+        let current = start_session(&user, &pool).await;
+        let other = start_session(&user, &pool).await;
+
+        let e = change_password(
+            &user,
+            "wrong_password",
+            "new_password",
+            "new_password",
+            Some(current),
+            &pool,
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidInput);
+        assert_eq!(target_field(&e), "current_password");
+        assert!(is_session_active(other, &user.username, &pool)
+            .await
+            .unwrap());
+
+        delete_test_users(&pool, &[user.id]).await;
+    }
+
+    /// Tests that new passwords follow the registration rules
+    #[actix_rt::test]
+    async fn test_change_password_rules() {
+        let pool = init_test_db().await;
+        let mut user = create_test_user(&pool, "account_password_rules").await;
+        set_password(&mut user, "old_password", &pool).await;
+
+        let cases = [
+            ("", "", "password"),
+            ("short", "short", "password"),
+            ("new_password", "other_password", "confirm_password"),
+        ];
+        for (password, confirm_password, field) in cases {
+            let e = change_password(
+                &user,
+                "old_password",
+                password,
+                confirm_password,
+                None,
+                &pool,
+            )
+            .await
+            .unwrap_err();
+            assert_eq!(e.kind(), ErrorKind::InvalidInput);
+            assert_eq!(target_field(&e), field);
+        }
+
+        delete_test_users(&pool, &[user.id]).await;
+    }
+
+    /// Tests that changing the password logs out the other devices only
+    #[actix_rt::test]
+    async fn test_change_password_revokes_other_sessions() {
+        let pool = init_test_db().await;
+        let mut user = create_test_user(&pool, "account_password_sessions").await;
+        set_password(&mut user, "old_password", &pool).await;
+        let current = start_session(&user, &pool).await;
+        let other = start_session(&user, &pool).await;
+
+        let revoked = change_password(
+            &user,
+            "old_password",
+            "new_password",
+            "new_password",
+            Some(current),
+            &pool,
+        )
+        .await
+        .unwrap();
+        assert_eq!(revoked, 1);
+        assert!(is_session_active(current, &user.username, &pool)
+            .await
+            .unwrap());
+        assert!(!is_session_active(other, &user.username, &pool)
+            .await
+            .unwrap());
+
+        delete_test_users(&pool, &[user.id]).await;
+    }
+
+    /// Tests that the hash of the new password is stored
+    #[actix_rt::test]
+    async fn test_change_password_stores_hash() {
+        let pool = init_test_db().await;
+        let mut user = create_test_user(&pool, "account_password_hash").await;
+        set_password(&mut user, "old_password", &pool).await;
+
+        change_password(
+            &user,
+            "old_password",
+            "new_password",
+            "new_password",
+            None,
+            &pool,
+        )
+        .await
+        .unwrap();
+        let (stored,): (String,) = sqlx::query_as("SELECT password FROM users WHERE id = $1")
+            .bind(user.id)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert!(verify("new_password", &stored).unwrap());
+
+        delete_test_users(&pool, &[user.id]).await;
+    }
+
+    /// Tests the profile of a new user
+    #[actix_rt::test]
+    async fn test_new_profile() {
+        let pool = init_test_db().await;
+        let user = create_test_user(&pool, "account_new_profile").await;
+
+        let profile = get_profile(&user, &pool).await.unwrap();
+        assert_eq!(profile.username, user.username);
+        assert_eq!(
+            (profile.links, profile.trashed_links, profile.total_clicks),
+            (0, 0, 0)
+        );
+        assert_eq!(profile.default_expiration, None);
+        assert_eq!(profile.default_redirect_type, 307);
+
+        delete_test_users(&pool, &[user.id]).await;
+    }
+
+    /// Tests that the profile counts trashed links apart but includes their clicks
+    #[actix_rt::test]
+    async fn test_profile_counts() {
+        let pool = init_test_db().await;
+        let user = create_test_user(&pool, "account_profile").await;
+        let member = Member::personal(&user);
+        let mut ids = Vec::new();
+        for url in ["https://example.com/a", "https://example.com/b"] {
+            let link = create_url(&member, url, None, None, None, &[], None, &pool)
+                .await
+                .unwrap();
+            ids.push(link.id);
+        }
+        sqlx::query("UPDATE shortened_urls SET redirects = 5 WHERE id = ANY($1)")
+            .bind(&ids)
+            .execute(&pool)
+            .await
+            .unwrap();
+        delete_url(&member, &ids[1].to_string(), &pool)
+            .await
+            .unwrap();
+
+        let profile = get_profile(&user, &pool).await.unwrap();
+        assert_eq!(
+            (profile.links, profile.trashed_links, profile.total_clicks),
+            (1, 1, 10)
+        );
+
+        delete_test_users(&pool, &[user.id]).await;
+    }
+
+    /// Tests that settings absent from a patch are kept
+    #[actix_rt::test]
+    async fn test_update_settings_keeps_absent() {
+        let pool = init_test_db().await;
+        let user = create_test_user(&pool, "account_settings_keep").await;
+
+        let patch = r#"{"default_expiration": 3600, "default_redirect_type": 301}"#;
+        let profile = update_settings(&user, &settings_patch(patch), &pool)
+            .await
+            .unwrap();
+        assert_eq!(profile.default_expiration, Some(3600));
+        assert_eq!(profile.default_redirect_type, 301);
+
+        let profile = update_settings(&user, &SettingsPatch::default(), &pool)
+            .await
+            .unwrap();
+        assert_eq!(profile.default_expiration, Some(3600));
+        assert_eq!(profile.default_redirect_type, 301);
+
+        delete_test_users(&pool, &[user.id]).await;
+    }
+
+    /// Tests that the default expiration is cleared with null
+    #[actix_rt::test]
+    async fn test_update_settings_clears_expiration() {
+        let pool = init_test_db().await;
+        let user = create_test_user(&pool, "account_settings_clear").await;
+        let patch = r#"{"default_expiration": 3600, "default_redirect_type": 301}"#;
+        update_settings(&user, &settings_patch(patch), &pool)
+            .await
+            .unwrap();
+
+        let patch = r#"{"default_expiration": null}"#;
+        let profile = update_settings(&user, &settings_patch(patch), &pool)
+            .await
+            .unwrap();
+        assert_eq!(profile.default_expiration, None);
+        assert_eq!(profile.default_redirect_type, 301);
+
+        delete_test_users(&pool, &[user.id]).await;
+    }
+
+    /// Tests that new links get the defaults unless their expiration is given
+    #[actix_rt::test]
+    async fn test_settings_apply_to_new_links() {
+        let pool = init_test_db().await;
+        let user = create_test_user(&pool, "account_settings_apply").await;
+        let member = Member::personal(&user);
+        let patch = r#"{"default_expiration": 3600, "default_redirect_type": 301}"#;
+        update_settings(&user, &settings_patch(patch), &pool)
+            .await
+            .unwrap();
+
+        let link = create_url(
+            &member,
+            "https://example.com/",
+            None,
+            None,
+            None,
+            &[],
+            None,
+            &pool,
+        )
+        .await
+        .unwrap();
+        let expires_in = link.expiry_date.unwrap() - link.created_at;
+        assert!((3599..=3600).contains(&expires_in.num_seconds()));
+        assert_eq!(link.redirect_type, 301);
+
+        let link = create_url(
+            &member,
+            "https://example.com/",
+            None,
+            None,
+            Some(60),
+            &[],
+            None,
+            &pool,
+        )
+        .await
+        .unwrap();
+        assert!(link.expiry_date.unwrap() - link.created_at <= chrono::Duration::seconds(60));
+
+        delete_test_users(&pool, &[user.id]).await;
+    }
+
+    /// Tests that invalid defaults are rejected
+    #[actix_rt::test]
+    async fn test_update_settings_invalid() {
+        let pool = init_test_db().await;
+        let user = create_test_user(&pool, "account_settings_invalid").await;
+
+        for patch in [
+            r#"{"default_expiration": 0}"#,
+            r#"{"default_redirect_type": 200}"#,
+            r#"{"default_redirect_type": null}"#,
+        ] {
+            let e = update_settings(&user, &settings_patch(patch), &pool)
+                .await
+                .unwrap_err();
+            assert_eq!(e.kind(), ErrorKind::InvalidInput);
+        }
+
+        delete_test_users(&pool, &[user.id]).await;
+    }
+}
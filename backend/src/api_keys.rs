@@ -0,0 +1,446 @@
+use actix_web::http::Method;
+use chrono::{Duration, Utc};
+use nanoid::nanoid;
+use serde::Deserialize;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    service::parse_uuid,
+    structs::{ApiKey, CreatedApiKey, FieldError, User},
+    utils::hash_secret,
+};
+
+/// The prefix of every API key, telling them apart from JWTs
+pub(crate) const API_KEY_PREFIX: &str = "nurl_";
+
+/// The number of characters of a key kept in plain text to recognize it by
+const DISPLAY_PREFIX_LENGTH: usize = 12;
+
+/// The maximum length (in characters) of an API key name
+const MAX_API_KEY_NAME_LENGTH: usize = 100;
+
+/// What an API key may be used for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+pub enum ApiKeyScope {
+    /// Listing URLs, tags, the trash and workspaces
+    #[serde(rename = "links:read")]
+    LinksRead,
+    /// Creating, editing, deleting and restoring URLs and tags
+    #[serde(rename = "links:write")]
+    LinksWrite,
+    /// Reading the link and click counts of the profile
+    #[serde(rename = "stats:read")]
+    StatsRead,
+}
+
+impl ApiKeyScope {
+    /// Returns the name the scope is stored under
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ApiKeyScope::LinksRead => "links:read",
+            ApiKeyScope::LinksWrite => "links:write",
+            ApiKeyScope::StatsRead => "stats:read",
+        }
+    }
+
+    /// Parses a scope as stored in the database, `None` for unknown scopes
+    fn parse(scope: &str) -> Option<Self> {
+        match scope {
+            "links:read" => Some(ApiKeyScope::LinksRead),
+            "links:write" => Some(ApiKeyScope::LinksWrite),
+            "stats:read" => Some(ApiKeyScope::StatsRead),
+            _ => None,
+        }
+    }
+}
+
+/// The user an API key authenticates, along with what the key may be used for
+#[derive(Debug)]
+pub(crate) struct ApiKeyIdentity {
+    /// Username of the user owning the key
+    pub username: String,
+    /// The scopes of the key
+    pub scopes: Vec<ApiKeyScope>,
+}
+
+/// Returns the scope an API key needs for a request
+/// 
+/// Only the URL, tag, trash and workspace listing endpoints, and reading the
+/// profile with its link and click counts, can be used with API keys. Managing
+/// the account (including API keys themselves) requires logging in.
+/// 
+/// # Arguments
+/// * `method` - The method of the request
+/// * `path` - The path of the request
+/// 
+/// # Returns
+/// Option containing the required scope, `None` if API keys can't be used
+pub(crate) fn required_scope(method: &Method, path: &str) -> Option<ApiKeyScope> {
+    let resource = path.strip_prefix("/api/")?.split('/').next()?;
+    let read = method == Method::GET || method == Method::HEAD;
+
+    match resource {
+        "me" if read && path == "/api/me" => Some(ApiKeyScope::StatsRead),
+        "shorten" | "tags" | "trash" if read => Some(ApiKeyScope::LinksRead),
+        "shorten" | "tags" | "trash" => Some(ApiKeyScope::LinksWrite),
+        "workspaces" if read => Some(ApiKeyScope::LinksRead),
+        _ => None,
+    }
+}
+
+/// Creates an API key for a user
+/// 
+/// # Arguments
+/// * `user` - The user owning the key
+/// * `name` - A name to recognize the key by
+/// * `scopes` - What the key may be used for
+/// * `expiration_sec` - Optional number of seconds until the key expires
+/// * `pool` - Database connection pool
+/// 
+/// # Returns
+/// Result containing the created key, the only time the key itself is returned
+pub async fn create_api_key(
+    user: &User,
+    name: &str,
+    scopes: &[ApiKeyScope],
+    expiration_sec: Option<i64>,
+    pool: &PgPool,
+) -> Result<CreatedApiKey, std::io::Error> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(FieldError::invalid("name", "Name cannot be empty"));
+    }
+    if name.chars().count() > MAX_API_KEY_NAME_LENGTH {
+        return Err(FieldError::invalid(
+            "name",
+            format!(
+                "Name cannot be longer than {} characters",
+                MAX_API_KEY_NAME_LENGTH
+            ),
+        ));
+    }
+    if scopes.is_empty() {
+        return Err(FieldError::invalid(
+            "scopes",
+            "An API key needs at least one scope",
+        ));
+    }
+    if expiration_sec.is_some_and(|secs| secs <= 0) {
+        return Err(FieldError::invalid(
+            "expiration",
+            "Expiration must be a positive number of seconds",
+        ));
+    }
+
+    let mut scopes = scopes.to_vec();
+    scopes.sort();
+    scopes.dedup();
+    let scopes: Vec<&str> = scopes.into_iter().map(ApiKeyScope::as_str).collect();
+
+    let key = format!("{}{}", API_KEY_PREFIX, nanoid!(40));
+    let now = Utc::now();
+    let api_key = sqlx::query_as(
+        r#"
+      INSERT INTO api_keys (id, user_id, name, prefix, key_hash, scopes, expires_at, created_at)
+      VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+      RETURNING id, name, prefix, scopes, expires_at, last_used_at, created_at
+      "#,
+    )
+    .bind(Uuid::new_v4())
+    .bind(user.id)
+    .bind(name)
+    .bind(&key[..DISPLAY_PREFIX_LENGTH])
+    .bind(hash_secret(&key))
+    .bind(scopes)
+    .bind(expiration_sec.map(|secs| now + Duration::seconds(secs)))
+    .bind(now)
+    .fetch_one(pool)
+    .await
+    .map_err(|e| std::io::Error::other(e.to_string()))?;
+
+    Ok(CreatedApiKey { api_key, key })
+}
+
+/// Lists the API keys of a user, newest first
+/// 
+/// # Arguments
+/// * `user` - The user owning the keys
+/// * `pool` - Database connection pool
+/// 
+/// # Returns
+/// Result containing the keys, including expired ones
+pub async fn list_api_keys(user: &User, pool: &PgPool) -> Result<Vec<ApiKey>, std::io::Error> {
+    sqlx::query_as(
+        r#"
+      SELECT id, name, prefix, scopes, expires_at, last_used_at, created_at
+      FROM api_keys
+      WHERE user_id = $1
+      ORDER BY created_at DESC, id
+      "#,
+    )
+    .bind(user.id)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| std::io::Error::other(e.to_string()))
+}
+
+/// Revokes an API key, which can't be used anymore afterwards
+/// 
+/// # Arguments
+/// * `user` - The user owning the key
+/// * `id` - The ID of the key
+/// * `pool` - Database connection pool
+/// 
+/// # Returns
+/// Result indicating success or failure
+pub async fn revoke_api_key(user: &User, id: &str, pool: &PgPool) -> Result<(), std::io::Error> {
+    let uuid = parse_uuid(id)?;
+
+    let result = sqlx::query("DELETE FROM api_keys WHERE id = $1 AND user_id = $2")
+        .bind(uuid)
+        .bind(user.id)
+        .execute(pool)
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+    if result.rows_affected() == 0 {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            "API key not found",
+        ));
+    }
+
+    Ok(())
+}
+
+/// Looks up the user an API key belongs to, recording that the key was used
+/// 
+/// # Arguments
+/// * `key` - The API key sent with a request
+/// * `pool` - Database connection pool
+/// 
+/// # Returns
+/// Result containing the identity, `None` if the key doesn't exist or expired
+pub(crate) async fn authenticate_api_key(
+    key: &str,
+    pool: &PgPool,
+) -> Result<Option<ApiKeyIdentity>, std::io::Error> {
+    let row: Option<(String, Vec<String>)> = sqlx::query_as(
+        r#"
+      UPDATE api_keys k SET last_used_at = $2
+      FROM users u
+      WHERE k.key_hash = $1 AND u.id = k.user_id AND (k.expires_at IS NULL OR k.expires_at > $2)
+      RETURNING u.username, k.scopes
+      "#,
+    )
+    .bind(hash_secret(key))
+    .bind(Utc::now())
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| std::io::Error::other(e.to_string()))?;
+
+    Ok(row.map(|(username, scopes)| ApiKeyIdentity {
+        username,
+        scopes: scopes
+            .iter()
+            .filter_map(|s| ApiKeyScope::parse(s))
+            .collect(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::{create_test_user, delete_test_users, init_test_db};
+
+    /// Tests which scope API keys need for each endpoint
+    #[test]
+    fn test_required_scope() {
+        assert_eq!(
+            required_scope(&Method::GET, "/api/shorten"),
+            Some(ApiKeyScope::LinksRead)
+        );
+        assert_eq!(
+            required_scope(&Method::GET, "/api/shorten/1234/history"),
+            Some(ApiKeyScope::LinksRead)
+        );
+        assert_eq!(
+            required_scope(&Method::POST, "/api/shorten/bulk"),
+            Some(ApiKeyScope::LinksWrite)
+        );
+        assert_eq!(
+            required_scope(&Method::DELETE, "/api/trash/1234"),
+            Some(ApiKeyScope::LinksWrite)
+        );
+        assert_eq!(
+            required_scope(&Method::GET, "/api/workspaces"),
+            Some(ApiKeyScope::LinksRead)
+        );
+        assert_eq!(
+            required_scope(&Method::GET, "/api/me"),
+            Some(ApiKeyScope::StatsRead)
+        );
+        assert_eq!(required_scope(&Method::PATCH, "/api/me"), None);
+        assert_eq!(required_scope(&Method::POST, "/api/me/password"), None);
+        assert_eq!(required_scope(&Method::POST, "/api/workspaces"), None);
+        assert_eq!(required_scope(&Method::GET, "/api/api-keys"), None);
+        assert_eq!(required_scope(&Method::POST, "/api/domains"), None);
+        assert_eq!(required_scope(&Method::GET, "/api/shortener"), None);
+    }
+
+    /// Creates an API key that can read links
+    /// 
+    /// # Arguments
+    /// * `user` - The user owning the key
+    /// * `expiration_sec` - Seconds until the key expires, `None` for never
+    /// * `pool` - Database connection pool
+    /// 
+    /// # Returns
+    /// The created key
+    async fn create_read_key(
+        user: &User,
+        expiration_sec: Option<i64>,
+        pool: &PgPool,
+    ) -> CreatedApiKey {
+        create_api_key(
+            user,
+            "script",
+            &[ApiKeyScope::LinksRead],
+            expiration_sec,
+            pool,
+        )
+        .await
+        .unwrap()
+    }
+
+    /// Tests that keys need at least one scope and a positive expiration
+    #[actix_rt::test]
+    async fn test_create_api_key_invalid() {
+        let pool = init_test_db().await;
+        let user = create_test_user(&pool, "api_keys_invalid").await;
+
+        let result = create_api_key(&user, "script", &[], None, &pool).await;
+        assert!(result.is_err_and(|e| e.kind() == std::io::ErrorKind::InvalidInput));
+        let result =
+            create_api_key(&user, "script", &[ApiKeyScope::LinksRead], Some(0), &pool).await;
+        assert!(result.is_err_and(|e| e.kind() == std::io::ErrorKind::InvalidInput));
+
+        delete_test_users(&pool, &[user.id]).await;
+    }
+
+    /// Tests that created keys have normalized names and scopes and only their hash is stored
+    #[actix_rt::test]
+    async fn test_create_api_key() {
+        let pool = init_test_db().await;
+        let user = create_test_user(&pool, "api_keys_create").await;
+
+        let created = create_api_key(
+            &user,
+            " script ",
+            &[
+                ApiKeyScope::LinksWrite,
+                ApiKeyScope::LinksRead,
+                ApiKeyScope::LinksRead,
+            ],
+            None,
+            &pool,
+        )
+        .await
+        .unwrap();
+        assert!(created.key.starts_with(API_KEY_PREFIX));
+        assert!(created.key.starts_with(&created.api_key.prefix));
+        assert_eq!(created.api_key.name, "script");
+        assert_eq!(created.api_key.scopes, vec!["links:read", "links:write"]);
+        assert_eq!(created.api_key.last_used_at, None);
+        let (stored,): (String,) = sqlx::query_as("SELECT key_hash FROM api_keys WHERE id = $1")
+            .bind(created.api_key.id)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_ne!(stored, created.key);
+
+        delete_test_users(&pool, &[user.id]).await;
+    }
+
+    /// Tests that authenticating with a key returns its user and records the use
+    #[actix_rt::test]
+    async fn test_authenticate_api_key() {
+        let pool = init_test_db().await;
+        let user = create_test_user(&pool, "api_keys_auth").await;
+        let created = create_read_key(&user, None, &pool).await;
+
+        let identity = authenticate_api_key(&created.key, &pool)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(identity.username, user.username);
+        assert_eq!(identity.scopes, vec![ApiKeyScope::LinksRead]);
+        let keys = list_api_keys(&user, &pool).await.unwrap();
+        assert_eq!(keys.len(), 1);
+        assert!(keys[0].last_used_at.is_some());
+
+        delete_test_users(&pool, &[user.id]).await;
+    }
+
+    /// Tests that unknown keys aren't accepted
+    #[actix_rt::test]
+    async fn test_authenticate_unknown_api_key() {
+        let pool = init_test_db().await;
+
+        let identity = authenticate_api_key("nurl_unknown", &pool).await.unwrap();
+        assert!(identity.is_none());
+    }
+
+    /// Tests that expired keys aren't accepted
+    #[actix_rt::test]
+    async fn test_expired_api_key() {
+        let pool = init_test_db().await;
+        let user = create_test_user(&pool, "api_keys_expired").await;
+        let expiring = create_read_key(&user, Some(60), &pool).await;
+        assert!(expiring.api_key.expires_at.is_some());
+
+        sqlx::query("UPDATE api_keys SET expires_at = $1 WHERE id = $2")
+            .bind(Utc::now() - Duration::seconds(1))
+            .bind(expiring.api_key.id)
+            .execute(&pool)
+            .await
+            .unwrap();
+        let identity = authenticate_api_key(&expiring.key, &pool).await.unwrap();
+        assert!(identity.is_none());
+
+        delete_test_users(&pool, &[user.id]).await;
+    }
+
+    /// Tests that revoked keys are removed and aren't accepted anymore
+    #[actix_rt::test]
+    async fn test_revoke_api_key() {
+        let pool = init_test_db().await;
+        let user = create_test_user(&pool, "api_keys_revoke").await;
+        let created = create_read_key(&user, None, &pool).await;
+
+        revoke_api_key(&user, &created.api_key.id.to_string(), &pool)
+            .await
+            .unwrap();
+        let identity = authenticate_api_key(&created.key, &pool).await.unwrap();
+        assert!(identity.is_none());
+        assert!(list_api_keys(&user, &pool).await.unwrap().is_empty());
+
+        delete_test_users(&pool, &[user.id]).await;
+    }
+
+    /// Tests that keys of other users can't be revoked
+    #[actix_rt::test]
+    async fn test_revoke_api_key_of_other_user() {
+        let pool = init_test_db().await;
+        let user = create_test_user(&pool, "api_keys_owner").await;
+        let other = create_test_user(&pool, "api_keys_other").await;
+        let created = create_read_key(&user, None, &pool).await;
+
+        let result = revoke_api_key(&other, &created.api_key.id.to_string(), &pool).await;
+        assert!(result.is_err_and(|e| e.kind() == std::io::ErrorKind::NotFound));
+        let identity = authenticate_api_key(&created.key, &pool).await.unwrap();
+        assert!(identity.is_some());
+
+        delete_test_users(&pool, &[user.id, other.id]).await;
+    }
+}
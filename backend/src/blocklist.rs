@@ -0,0 +1,342 @@
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::{collections::HashSet, path::PathBuf, sync::RwLock};
+use url::Url;
+
+use crate::constants::{BLOCKLIST_FILES, BLOCKLIST_RELOAD_INTERVAL};
+
+/// The currently loaded blocklist
+/// 
+/// This is replaced as a whole whenever the blocklist files are reloaded
+pub(crate) static BLOCKLIST: Lazy<RwLock<Blocklist>> =
+    Lazy::new(|| RwLock::new(Blocklist::default()));
+
+/// Host names that appear in hosts files but should never be blocked
+const HOSTS_FILE_IGNORED: [&str; 5] = [
+    "localhost",
+    "localhost.localdomain",
+    "local",
+    "broadcasthost",
+    "ip6-localhost",
+];
+
+/// A set of blocked domains and URL patterns
+/// 
+/// Domains block the domain itself and every subdomain. URL patterns may
+/// contain `*` wildcards and are matched against the URL without its scheme,
+/// unless the pattern itself contains a scheme.
+#[derive(Default)]
+pub(crate) struct Blocklist {
+    /// Blocked domains in lowercase
+    domains: HashSet<String>,
+    /// Blocked URL patterns in lowercase
+    patterns: Vec<String>,
+}
+
+/// Describes which blocklist rule a URL matched
+#[derive(Serialize, Debug, PartialEq)]
+pub(crate) struct BlocklistMatch {
+    /// The domain or URL pattern that matched
+    pub rule: String,
+}
+
+impl Blocklist {
+    /// Parses a blocklist file
+    /// 
+    /// Both hosts-format files (`0.0.0.0 example.com`) and plain lists with
+    /// one domain or URL pattern per line are accepted. Everything after a
+    /// `#` is treated as a comment.
+    /// 
+    /// # Arguments
+    /// * `contents` - The contents of the blocklist file
+    pub fn parse(&mut self, contents: &str) {
+        for line in contents.lines() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            let mut tokens = line.split_whitespace().peekable();
+
+            // Hosts-format lines start with the address the domains resolve to
+            if tokens
+                .peek()
+                .is_some_and(|t| t.parse::<std::net::IpAddr>().is_ok())
+            {
+                tokens.next();
+                for domain in tokens {
+                    let domain = domain.trim_end_matches('.').to_lowercase();
+                    if !HOSTS_FILE_IGNORED.contains(&domain.as_str())
+                        && domain.parse::<std::net::IpAddr>().is_err()
+                    {
+                        self.domains.insert(domain);
+                    }
+                }
+                continue;
+            }
+
+            for token in tokens {
+                let token = token.to_lowercase();
+                if token.contains('/') || token.trim_start_matches("*.").contains('*') {
+                    self.patterns.push(token);
+                } else {
+                    let domain = token.trim_start_matches("*.").trim_end_matches('.');
+                    self.domains.insert(domain.to_string());
+                }
+            }
+        }
+    }
+
+    /// Loads and merges all blocklist files
+    /// 
+    /// # Arguments
+    /// * `paths` - The blocklist files to load
+    /// 
+    /// # Returns
+    /// Result containing the merged blocklist
+    pub fn load(paths: &[PathBuf]) -> Result<Blocklist, std::io::Error> {
+        let mut blocklist = Blocklist::default();
+        for path in paths {
+            let contents = std::fs::read_to_string(path)
+                .map_err(|e| std::io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+            blocklist.parse(&contents);
+        }
+        Ok(blocklist)
+    }
+
+    /// Returns the number of rules in the blocklist
+    pub fn len(&self) -> usize {
+        self.domains.len() + self.patterns.len()
+    }
+
+    /// Checks a URL against the blocklist
+    /// 
+    /// # Arguments
+    /// * `url` - The URL to check
+    /// 
+    /// # Returns
+    /// Option containing the matched rule if the URL is blocked
+    pub fn check(&self, url: &str) -> Option<BlocklistMatch> {
+        let parsed = Url::parse(url).ok()?;
+
+        if let Some(host) = parsed.host_str() {
+            let host = host.trim_end_matches('.').to_lowercase();
+            let mut candidate = host.as_str();
+            loop {
+                if self.domains.contains(candidate) {
+                    return Some(BlocklistMatch {
+                        rule: candidate.to_string(),
+                    });
+                }
+                match candidate.split_once('.') {
+                    Some((_, parent)) => candidate = parent,
+                    None => break,
+                }
+            }
+        }
+
+        let full = parsed.as_str().to_lowercase();
+        let without_scheme = full
+            .split_once("://")
+            .map(|(_, rest)| rest.to_string())
+            .unwrap_or(full.clone());
+
+        self.patterns
+            .iter()
+            .find(|pattern| {
+                let target = if pattern.contains("://") {
+                    &full
+                } else {
+                    &without_scheme
+                };
+                wildcard_match(pattern, target)
+            })
+            .map(|pattern| BlocklistMatch {
+                rule: pattern.clone(),
+            })
+    }
+}
+
+/// Matches a string against a pattern where `*` matches any run of characters
+/// 
+/// Patterns without a trailing `*` also match any URL that continues past
+/// the end of the pattern, so `example.com/phish` matches `example.com/phish/login`.
+/// 
+/// # Arguments
+/// * `pattern` - The pattern to match with
+/// * `target` - The string to match against
+/// 
+/// # Returns
+/// Boolean indicating if the target matches
+fn wildcard_match(pattern: &str, target: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or("");
+    let Some(mut rest) = target.strip_prefix(first) else {
+        return false;
+    };
+
+    for part in parts {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    true
+}
+
+/// Checks a URL against the currently loaded blocklist
+/// 
+/// # Arguments
+/// * `url` - The URL to check
+/// 
+/// # Returns
+/// Option containing the matched rule if the URL is blocked
+pub(crate) fn check_url(url: &str) -> Option<BlocklistMatch> {
+    BLOCKLIST
+        .read()
+        .expect("Blocklist lock poisoned")
+        .check(url)
+}
+
+/// Reloads the blocklist from the configured files
+/// 
+/// The files are read and parsed on the blocking thread pool, so the runtime
+/// keeps serving requests meanwhile. The currently loaded blocklist is kept if
+/// any file fails to load.
+/// 
+/// # Returns
+/// Result containing the number of loaded rules
+pub(crate) async fn reload() -> Result<usize, std::io::Error> {
+    let blocklist = tokio::task::spawn_blocking(|| Blocklist::load(&BLOCKLIST_FILES))
+        .await
+        .map_err(std::io::Error::other)??;
+    let len = blocklist.len();
+    *BLOCKLIST.write().expect("Blocklist lock poisoned") = blocklist;
+    Ok(len)
+}
+
+/// Reloads the blocklist and logs the outcome
+async fn reload_and_log() {
+    match reload().await {
+        Ok(len) => println!("Loaded {} blocklist rules", len),
+        Err(e) => println!("Could not load blocklist: {}", e),
+    }
+}
+
+/// Loads the blocklist and keeps it up to date in the background
+/// 
+/// The blocklist is reloaded whenever the process receives SIGHUP and every
+/// `BLOCKLIST_RELOAD_INTERVAL` seconds (unless the interval is 0).
+pub(crate) async fn spawn_reloader() {
+    reload_and_log().await;
+
+    if BLOCKLIST_FILES.is_empty() {
+        return;
+    }
+
+    #[cfg(unix)]
+    tokio::spawn(async {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut hangup = match signal(SignalKind::hangup()) {
+            Ok(s) => s,
+            Err(e) => {
+                println!("Could not listen for SIGHUP: {}", e);
+                return;
+            }
+        };
+        while hangup.recv().await.is_some() {
+            reload_and_log().await;
+        }
+    });
+
+    if *BLOCKLIST_RELOAD_INTERVAL > 0 {
+        tokio::spawn(async {
+            let period = std::time::Duration::from_secs(*BLOCKLIST_RELOAD_INTERVAL);
+            let mut interval =
+                tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+            loop {
+                interval.tick().await;
+                reload_and_log().await;
+            }
+        });
+    }
+}
+
+/// Escapes text for safe inclusion in HTML
+/// 
+/// # Arguments
+/// * `text` - The text to escape
+/// 
+/// # Returns
+/// The escaped text
+pub(crate) fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_hosts_format() {
+        let mut blocklist = Blocklist::default();
+        blocklist.parse(
+            "# comment line\n\
+             127.0.0.1 localhost\n\
+             0.0.0.0 phish.example evil.example # trailing comment\n\
+             ::1 ip6-localhost\n",
+        );
+
+        assert_eq!(blocklist.len(), 2);
+        assert!(blocklist.check("https://phish.example/login").is_some());
+        assert!(blocklist.check("https://evil.example/").is_some());
+        assert!(blocklist.check("http://localhost/").is_none());
+    }
+
+    #[test]
+    fn test_parse_domain_list() {
+        let mut blocklist = Blocklist::default();
+        blocklist.parse("bad.example\n*.worse.example\n\nexample.org/phish/*\n");
+
+        // Domains match themselves and their subdomains
+        assert_eq!(
+            blocklist.check("https://login.bad.example/"),
+            Some(BlocklistMatch {
+                rule: "bad.example".to_string()
+            })
+        );
+        assert!(blocklist.check("https://BAD.example/").is_some());
+        assert!(blocklist.check("https://a.worse.example/").is_some());
+        assert!(blocklist.check("https://notbad.example/").is_none());
+
+        // URL patterns match against the URL without its scheme
+        assert_eq!(
+            blocklist.check("http://example.org/phish/login"),
+            Some(BlocklistMatch {
+                rule: "example.org/phish/*".to_string()
+            })
+        );
+        assert!(blocklist.check("https://example.org/safe").is_none());
+    }
+
+    #[test]
+    fn test_wildcard_match() {
+        assert!(wildcard_match("example.com/a/*/b", "example.com/a/x/y/b"));
+        assert!(wildcard_match(
+            "example.com/phish",
+            "example.com/phish/login"
+        ));
+        assert!(wildcard_match("*.example.com/*", "a.example.com/x"));
+        assert!(!wildcard_match("example.com/a/*/b", "example.com/a/x"));
+        assert!(!wildcard_match("example.com/a", "other.com/example.com/a"));
+    }
+
+    #[test]
+    fn test_escape_html() {
+        assert_eq!(
+            escape_html("<a href=\"x\">'&'</a>"),
+            "&lt;a href=&quot;x&quot;&gt;&#39;&amp;&#39;&lt;/a&gt;"
+        );
+    }
+}
@@ -0,0 +1,1075 @@
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use sqlx::{Acquire, PgConnection, PgPool, Postgres, QueryBuilder, Transaction};
+use std::collections::HashSet;
+use url::Url;
+use uuid::Uuid;
+
+use crate::{
+    history::{record_revision, RevisionAction},
+    metadata::spawn_metadata_refresh,
+    service::{
+        calculate_expiry_date, change_destination, insert_prepared_url, load_tags, lock_owned_url,
+        normalize_domain, normalize_tag, normalize_tags, prepare_url, push_link_filters,
+        set_url_tags, trash_owned_url, validate_destination, LinkFilter,
+    },
+    structs::{
+        BulkItemFailure, BulkResult, BulkRowResult, BulkUpdateResult, FieldError, ShortenedUrl,
+    },
+    workspaces::{Member, WorkspaceRole},
+};
+
+/// The maximum number of rows accepted in a single bulk request
+const MAX_BULK_ROWS: usize = 1000;
+
+/// The number of rows saved per transaction
+const BULK_BATCH_SIZE: usize = 100;
+
+/// A row of a bulk creation request
+#[derive(Deserialize)]
+pub struct BulkUrlRow {
+    /// The original URL to be shortened
+    pub original_url: String,
+    /// Optional custom path for the shortened URL
+    pub custom_path: Option<String>,
+    /// Optional custom domain to serve the URL on
+    pub domain: Option<String>,
+    /// Optional expiration time in seconds, the user's default expiration if omitted
+    pub expiration: Option<i64>,
+    /// Optional tags to attach to the URL
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Optional notes about the URL
+    pub notes: Option<String>,
+}
+
+/// A row of a bulk creation CSV upload
+/// 
+/// Tags are given as a single comma separated column.
+#[derive(Deserialize)]
+struct CsvUrlRow {
+    original_url: String,
+    custom_path: Option<String>,
+    domain: Option<String>,
+    expiration: Option<i64>,
+    tags: Option<String>,
+    notes: Option<String>,
+}
+
+/// Parses the rows of a bulk creation JSON array
+/// 
+/// Each element is parsed on its own so that a malformed row doesn't reject
+/// the whole request.
+/// 
+/// # Arguments
+/// * `data` - The request body
+/// 
+/// # Returns
+/// Result containing every row, or the error that row couldn't be parsed with
+pub fn parse_json_rows(
+    data: &[u8],
+) -> Result<Vec<Result<BulkUrlRow, std::io::Error>>, std::io::Error> {
+    let values: Vec<serde_json::Value> = serde_json::from_slice(data).map_err(|e| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("Expected a JSON array of URLs: {}", e),
+        )
+    })?;
+
+    Ok(values
+        .into_iter()
+        .map(|value| {
+            serde_json::from_value(value)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e.to_string()))
+        })
+        .collect())
+}
+
+/// Parses the rows of a bulk creation CSV upload
+/// 
+/// The CSV must have a header row with an `original_url` column, and may
+/// have `custom_path`, `domain`, `expiration`, `tags` and `notes` columns.
+/// 
+/// # Arguments
+/// * `data` - The uploaded CSV
+/// 
+/// # Returns
+/// Result containing every row, or the error that row couldn't be parsed with
+pub fn parse_csv_rows(
+    data: &[u8],
+) -> Result<Vec<Result<BulkUrlRow, std::io::Error>>, std::io::Error> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(data);
+
+    let headers = reader
+        .headers()
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e.to_string()))?;
+    if !headers.iter().any(|h| h == "original_url") {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "The CSV must have an original_url column",
+        ));
+    }
+
+    Ok(reader
+        .deserialize::<CsvUrlRow>()
+        .map(|row| {
+            row.map(|row| BulkUrlRow {
+                original_url: row.original_url,
+                custom_path: row.custom_path,
+                domain: row.domain,
+                expiration: row.expiration,
+                tags: row
+                    .tags
+                    .unwrap_or_default()
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|t| !t.is_empty())
+                    .map(str::to_string)
+                    .collect(),
+                notes: row.notes,
+            })
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e.to_string()))
+        })
+        .collect())
+}
+
+/// Returns the input field a service error was caused by, if any
+fn target_field(e: &std::io::Error) -> Option<String> {
+    e.get_ref()
+        .and_then(|inner| inner.downcast_ref::<FieldError>())
+        .map(|f| f.target_field.clone())
+}
+
+impl BulkRowResult {
+    /// Creates the result of a row from the outcome of saving it
+    /// 
+    /// # Arguments
+    /// * `row` - The 1-based number of the row
+    /// * `result` - The created URL or the error the row failed with
+    fn new(row: usize, result: Result<ShortenedUrl, std::io::Error>) -> Self {
+        match result {
+            Ok(link) => Self {
+                row,
+                link: Some(link),
+                error: None,
+                target_field: None,
+            },
+            Err(e) => Self {
+                row,
+                link: None,
+                target_field: target_field(&e),
+                error: Some(e.to_string()),
+            },
+        }
+    }
+}
+
+/// Runs an operation in a savepoint so that only this operation is rolled back if it fails
+/// 
+/// # Arguments
+/// * `tx` - The transaction of the batch
+/// * `operation` - The operation to run on the savepoint's connection
+/// 
+/// # Returns
+/// Result of the operation
+async fn in_savepoint<T>(
+    tx: &mut Transaction<'_, Postgres>,
+    operation: impl AsyncFnOnce(&mut PgConnection) -> Result<T, std::io::Error>,
+) -> Result<T, std::io::Error> {
+    let mut savepoint = tx
+        .begin()
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+
+    match operation(&mut savepoint).await {
+        Ok(value) => {
+            savepoint
+                .commit()
+                .await
+                .map_err(|e| std::io::Error::other(e.to_string()))?;
+            Ok(value)
+        }
+        Err(e) => {
+            savepoint
+                .rollback()
+                .await
+                .map_err(|e| std::io::Error::other(e.to_string()))?;
+            Err(e)
+        }
+    }
+}
+
+/// Creates shortened URLs in bulk
+/// 
+/// Every row is validated the same way as a single URL. Rows are saved in
+/// batches of `BULK_BATCH_SIZE`, each in its own transaction, with every row
+/// in a savepoint so that a failing row doesn't affect the rest of its batch.
+/// 
+/// # Arguments
+/// * `member` - The member creating the URLs, at least an editor of the workspace
+/// * `rows` - The parsed rows, or the errors they failed to parse with
+/// * `pool` - Database connection pool
+/// 
+/// # Returns
+/// Result containing the outcome of every row
+pub async fn bulk_create_urls(
+    member: &Member,
+    rows: Vec<Result<BulkUrlRow, std::io::Error>>,
+    pool: &PgPool,
+) -> Result<BulkResult, std::io::Error> {
+    member.require(WorkspaceRole::Editor)?;
+    if rows.is_empty() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "No URLs to create",
+        ));
+    }
+    if rows.len() > MAX_BULK_ROWS {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("At most {} URLs can be created at once", MAX_BULK_ROWS),
+        ));
+    }
+
+    let mut results = Vec::with_capacity(rows.len());
+    let mut rows = rows.into_iter().enumerate().peekable();
+
+    while rows.peek().is_some() {
+        // Validate the batch before opening the transaction since validation may resolve DNS
+        let mut prepared = Vec::with_capacity(BULK_BATCH_SIZE);
+        for (index, row) in rows.by_ref().take(BULK_BATCH_SIZE) {
+            let url = match row {
+                Ok(row) => {
+                    prepare_url(
+                        member,
+                        &row.original_url,
+                        row.custom_path,
+                        row.domain.as_deref(),
+                        row.expiration,
+                        &row.tags,
+                        row.notes.as_deref(),
+                        pool,
+                    )
+                    .await
+                }
+                Err(e) => Err(e),
+            };
+            prepared.push((index + 1, url));
+        }
+
+        let mut tx = pool
+            .begin()
+            .await
+            .map_err(|e| std::io::Error::other(e.to_string()))?;
+
+        let mut batch = Vec::with_capacity(prepared.len());
+        for (row, url) in prepared {
+            let result = match url {
+                Ok(url) => in_savepoint(&mut tx, async |conn| {
+                    insert_prepared_url(&url, member.user.id, conn).await
+                })
+                .await
+                .map(|_| url),
+                Err(e) => Err(e),
+            };
+            batch.push(BulkRowResult::new(row, result));
+        }
+
+        if let Err(e) = tx.commit().await {
+            // Nothing in the batch was saved
+            for result in batch.iter_mut().filter(|r| r.link.is_some()) {
+                result.link = None;
+                result.error = Some(format!("The batch could not be saved: {}", e));
+            }
+        }
+
+        for link in batch.iter().filter_map(|r| r.link.as_ref()) {
+            spawn_metadata_refresh(link.id, link.original_url.clone(), pool.clone());
+        }
+        results.extend(batch);
+    }
+
+    let created = results.iter().filter(|r| r.link.is_some()).count();
+    Ok(BulkResult {
+        succeeded: created,
+        failed: results.len() - created,
+        rows: results,
+    })
+}
+
+/// An operation applied to many shortened URLs at once
+#[derive(Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum BulkAction {
+    /// Move the URLs to the trash
+    Delete,
+    /// Make the URLs expire in the given number of seconds, or never if omitted
+    SetExpiry { expiration: Option<i64> },
+    /// Move destinations on a host, or one of its subdomains, to another host
+    ChangeHost { from: String, to: String },
+    /// Add tags to the URLs
+    AddTags { tags: Vec<String> },
+    /// Remove tags from the URLs
+    RemoveTags { tags: Vec<String> },
+    /// Stop the URLs from redirecting
+    Disable,
+    /// Let the URLs redirect again
+    Enable,
+}
+
+/// Request body of a bulk operation on existing URLs
+/// 
+/// URLs are selected either by their IDs or by a filter.
+#[derive(Deserialize)]
+pub struct BulkUpdateRequest {
+    /// IDs of the URLs to apply the operation to
+    pub ids: Option<Vec<String>>,
+    /// Filter selecting the URLs to apply the operation to
+    pub filter: Option<LinkFilter>,
+    /// The operation to apply
+    #[serde(flatten)]
+    pub action: BulkAction,
+}
+
+/// The change a bulk operation makes to a single URL
+enum Change {
+    Delete,
+    Expiry(Option<DateTime<Utc>>),
+    Destination(String),
+    Tags(Vec<String>),
+    Enabled(bool),
+}
+
+impl BulkItemFailure {
+    /// Creates a failure from the error an operation failed with
+    /// 
+    /// # Arguments
+    /// * `id` - The ID of the URL
+    /// * `e` - The error the operation failed with
+    fn new(id: String, e: std::io::Error) -> Self {
+        Self {
+            id,
+            target_field: target_field(&e),
+            error: e.to_string(),
+        }
+    }
+}
+
+/// Selects the URLs a bulk operation applies to
+/// 
+/// Only URLs owned by the member's workspace are selected. IDs that are invalid
+/// or don't belong to one of the workspace's URLs are reported as failures.
+/// 
+/// # Arguments
+/// * `member` - The member applying the operation
+/// * `request` - The bulk operation request
+/// * `pool` - Database connection pool
+/// 
+/// # Returns
+/// Result containing the selected URLs and the IDs that couldn't be selected
+async fn select_urls(
+    member: &Member,
+    request: &BulkUpdateRequest,
+    pool: &PgPool,
+) -> Result<(Vec<ShortenedUrl>, Vec<BulkItemFailure>), std::io::Error> {
+    let mut failures = Vec::new();
+    let mut query = QueryBuilder::<Postgres>::new("SELECT * FROM shortened_urls WHERE owner = ");
+    query.push_bind(member.workspace_id);
+
+    let requested = match (&request.ids, &request.filter) {
+        (Some(ids), None) => {
+            if ids.len() > MAX_BULK_ROWS {
+                return Err(FieldError::invalid(
+                    "ids",
+                    format!("At most {} URLs can be selected at once", MAX_BULK_ROWS),
+                ));
+            }
+
+            let mut uuids = Vec::with_capacity(ids.len());
+            for id in ids.iter().collect::<HashSet<_>>() {
+                match Uuid::parse_str(id) {
+                    Ok(uuid) => uuids.push(uuid),
+                    Err(e) => failures.push(BulkItemFailure::new(
+                        id.clone(),
+                        FieldError::invalid("ids", e.to_string()),
+                    )),
+                }
+            }
+            query
+                .push(" AND deleted_at IS NULL AND id = ANY(")
+                .push_bind(uuids.clone())
+                .push(")");
+            Some(uuids)
+        }
+        (None, Some(filter)) => {
+            push_link_filters(&mut query, member.workspace_id, filter)?;
+            None
+        }
+        _ => {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Select the URLs either by ids or by a filter",
+            ));
+        }
+    };
+    query.push(" ORDER BY created_at");
+
+    let mut urls: Vec<ShortenedUrl> = query
+        .build_query_as()
+        .fetch_all(pool)
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+    load_tags(&mut urls, pool).await?;
+
+    // Enforce ownership on every requested ID
+    if let Some(requested) = requested {
+        let found: HashSet<Uuid> = urls.iter().map(|u| u.id).collect();
+        for id in requested.into_iter().filter(|id| !found.contains(id)) {
+            failures.push(BulkItemFailure::new(
+                id.to_string(),
+                std::io::Error::new(
+                    std::io::ErrorKind::NotFound,
+                    "URL not found or you don't have permission to modify it",
+                ),
+            ));
+        }
+    }
+
+    Ok((urls, failures))
+}
+
+/// Moves a URL to another host, keeping the rest of the URL
+/// 
+/// # Arguments
+/// * `url` - The URL to move
+/// * `from` - The host to move from, subdomains of it keep their subdomain
+/// * `to` - The host to move to
+/// 
+/// # Returns
+/// Result containing the moved URL
+fn replace_host(url: &str, from: &str, to: &str) -> Result<String, std::io::Error> {
+    let mut parsed = Url::parse(url).map_err(|e| FieldError::invalid("from", e.to_string()))?;
+    let host = parsed.host_str().unwrap_or_default();
+
+    let new_host = if host == from {
+        to.to_string()
+    } else if let Some(subdomain) = host.strip_suffix(&format!(".{}", from)) {
+        format!("{}.{}", subdomain, to)
+    } else {
+        return Err(FieldError::invalid(
+            "from",
+            format!("The destination isn't on {}", from),
+        ));
+    };
+
+    parsed
+        .set_host(Some(&new_host))
+        .map_err(|e| FieldError::invalid("to", e.to_string()))?;
+    Ok(parsed.to_string())
+}
+
+/// Works out the change a bulk operation makes to a URL
+/// 
+/// # Arguments
+/// * `url` - The URL the operation is applied to
+/// * `action` - The operation, with normalized tags and hosts
+/// * `expiry_date` - The expiry date for `SetExpiry` operations
+/// * `pool` - Database connection pool
+/// 
+/// # Returns
+/// Result containing the change to save
+async fn plan_change(
+    url: &ShortenedUrl,
+    action: &BulkAction,
+    expiry_date: Option<DateTime<Utc>>,
+    pool: &PgPool,
+) -> Result<Change, std::io::Error> {
+    Ok(match action {
+        BulkAction::Delete => Change::Delete,
+        BulkAction::SetExpiry { .. } => Change::Expiry(expiry_date),
+        BulkAction::ChangeHost { from, to } => {
+            let moved = replace_host(&url.original_url, from, to)?;
+            Change::Destination(validate_destination(&moved, pool).await?)
+        }
+        BulkAction::AddTags { tags } => {
+            let mut combined = url.tags.clone();
+            combined.extend(tags.iter().cloned());
+            Change::Tags(normalize_tags(&combined)?)
+        }
+        BulkAction::RemoveTags { tags } => Change::Tags(
+            url.tags
+                .iter()
+                .filter(|t| !tags.contains(t))
+                .cloned()
+                .collect(),
+        ),
+        BulkAction::Disable => Change::Enabled(false),
+        BulkAction::Enable => Change::Enabled(true),
+    })
+}
+
+/// Saves the change a bulk operation makes to one of a workspace's URLs
+/// 
+/// Every change except a deletion is recorded in the URL's revision history.
+/// 
+/// # Arguments
+/// * `owner` - The ID of the workspace owning the URL
+/// * `changed_by` - The ID of the user applying the operation
+/// * `id` - The ID of the URL
+/// * `change` - The change to save
+/// * `conn` - Database connection
+/// 
+/// # Returns
+/// Result indicating success or failure
+async fn apply_change(
+    owner: Uuid,
+    changed_by: Uuid,
+    id: Uuid,
+    change: &Change,
+    conn: &mut PgConnection,
+) -> Result<(), std::io::Error> {
+    // Checks ownership and keeps the previous state for the revision history
+    let previous = lock_owned_url(owner, id, conn).await?;
+
+    match change {
+        Change::Delete => return trash_owned_url(owner, id, conn).await,
+        Change::Destination(original_url) => {
+            change_destination(owner, id, original_url, conn).await?;
+        }
+        Change::Tags(tags) => {
+            set_url_tags(owner, id, tags, conn).await?;
+            sqlx::query(
+                "UPDATE shortened_urls SET updated_at = $1, version = version + 1 WHERE id = $2",
+            )
+            .bind(Utc::now())
+            .bind(id)
+            .execute(&mut *conn)
+            .await
+            .map_err(|e| std::io::Error::other(e.to_string()))?;
+        }
+        Change::Expiry(expiry_date) => {
+            sqlx::query(
+                "UPDATE shortened_urls SET expiry_date = $1, updated_at = $2, version = version + 1 WHERE id = $3",
+            )
+            .bind(expiry_date)
+            .bind(Utc::now())
+            .bind(id)
+            .execute(&mut *conn)
+            .await
+            .map_err(|e| std::io::Error::other(e.to_string()))?;
+        }
+        Change::Enabled(enabled) => {
+            sqlx::query(
+                "UPDATE shortened_urls SET enabled = $1, updated_at = $2, version = version + 1 WHERE id = $3",
+            )
+                .bind(enabled)
+                .bind(Utc::now())
+                .bind(id)
+                .execute(&mut *conn)
+                .await
+                .map_err(|e| std::io::Error::other(e.to_string()))?;
+        }
+    }
+
+    let updated = lock_owned_url(owner, id, conn).await?;
+    record_revision(
+        changed_by,
+        RevisionAction::Bulk,
+        Some(&previous),
+        &updated,
+        conn,
+    )
+    .await
+}
+
+/// Applies an operation to many of a workspace's shortened URLs
+/// 
+/// URLs are processed in batches of `BULK_BATCH_SIZE`, each in its own
+/// transaction, with every URL in a savepoint so that a URL the operation
+/// fails for doesn't affect the rest of its batch.
+/// 
+/// # Arguments
+/// * `member` - The member applying the operation, at least an editor of the workspace
+/// * `request` - The URLs to select and the operation to apply
+/// * `pool` - Database connection pool
+/// 
+/// # Returns
+/// Result containing the number of affected URLs and the failures
+pub async fn bulk_update_urls(
+    member: &Member,
+    mut request: BulkUpdateRequest,
+    pool: &PgPool,
+) -> Result<BulkUpdateResult, std::io::Error> {
+    member.require(WorkspaceRole::Editor)?;
+
+    // Validate the operation before touching any URL
+    let mut expiry_date = None;
+    match &mut request.action {
+        BulkAction::SetExpiry { expiration } => expiry_date = calculate_expiry_date(*expiration),
+        BulkAction::ChangeHost { from, to } => {
+            *from = normalize_domain(from, "from")?;
+            *to = normalize_domain(to, "to")?;
+        }
+        BulkAction::AddTags { tags } | BulkAction::RemoveTags { tags } => {
+            if tags.is_empty() {
+                return Err(FieldError::invalid("tags", "No tags given"));
+            }
+            *tags = tags
+                .iter()
+                .map(|t| normalize_tag(t, "tags"))
+                .collect::<Result<_, _>>()?;
+        }
+        BulkAction::Delete | BulkAction::Disable | BulkAction::Enable => {}
+    }
+
+    let (urls, mut failures) = select_urls(member, &request, pool).await?;
+    let mut affected = 0;
+
+    for batch in urls.chunks(BULK_BATCH_SIZE) {
+        // Work out the changes before opening the transaction since validation may resolve DNS
+        let mut planned = Vec::with_capacity(batch.len());
+        for url in batch {
+            planned.push((
+                url,
+                plan_change(url, &request.action, expiry_date, pool).await,
+            ));
+        }
+
+        let mut tx = pool
+            .begin()
+            .await
+            .map_err(|e| std::io::Error::other(e.to_string()))?;
+
+        let mut applied = Vec::with_capacity(batch.len());
+        for (url, change) in planned {
+            let result = match change {
+                Ok(change) => in_savepoint(&mut tx, async |conn| {
+                    apply_change(member.workspace_id, member.user.id, url.id, &change, conn).await
+                })
+                .await
+                .map(|_| change),
+                Err(e) => Err(e),
+            };
+            match result {
+                Ok(change) => applied.push((url, change)),
+                Err(e) => failures.push(BulkItemFailure::new(url.id.to_string(), e)),
+            }
+        }
+
+        if let Err(e) = tx.commit().await {
+            // Nothing in the batch was saved
+            for (url, _) in applied {
+                failures.push(BulkItemFailure::new(
+                    url.id.to_string(),
+                    std::io::Error::other(format!("The batch could not be saved: {}", e)),
+                ));
+            }
+            continue;
+        }
+
+        affected += applied.len();
+        for (url, change) in applied {
+            if let Change::Destination(original_url) = change {
+                spawn_metadata_refresh(url.id, original_url, pool.clone());
+            }
+        }
+    }
+
+    Ok(BulkUpdateResult {
+        affected,
+        failed: failures.len(),
+        failures,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::{create_test_user, delete_test_users, init_test_db};
+
+    #[test]
+    fn test_parse_csv_rows() {
+        let csv = "original_url,custom_path,expiration,tags\n\
+                   https://example.com/a,,3600,\"news, tech\"\n\
+                   https://example.com/b,my-slug,,\n\
+                   https://example.com/c,,not-a-number,\n";
+        let rows = parse_csv_rows(csv.as_bytes()).unwrap();
+        assert_eq!(rows.len(), 3);
+
+        let row = rows[0].as_ref().unwrap();
+        assert_eq!(row.original_url, "https://example.com/a");
+        assert_eq!(row.custom_path, None);
+        assert_eq!(row.expiration, Some(3600));
+        assert_eq!(row.tags, vec!["news".to_string(), "tech".to_string()]);
+
+        let row = rows[1].as_ref().unwrap();
+        assert_eq!(row.custom_path, Some("my-slug".to_string()));
+        assert!(row.tags.is_empty());
+
+        assert!(rows[2].is_err());
+
+        // Test the original_url column is required
+        let result = parse_csv_rows("url\nhttps://example.com/\n".as_bytes());
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_parse_json_rows() {
+        let json = r#"[
+            {"original_url": "https://example.com/", "tags": ["a"]},
+            {"custom_path": "missing-url"}
+        ]"#;
+        let rows = parse_json_rows(json.as_bytes()).unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].as_ref().unwrap().tags, vec!["a".to_string()]);
+        assert!(rows[1].is_err());
+
+        assert!(parse_json_rows(b"{}").is_err());
+    }
+
+    /// Builds a valid row tagged `campaign`
+    fn row(original_url: &str, custom_path: Option<&str>) -> Result<BulkUrlRow, std::io::Error> {
+        Ok(BulkUrlRow {
+            original_url: original_url.to_string(),
+            custom_path: custom_path.map(str::to_string),
+            domain: None,
+            expiration: None,
+            tags: vec!["campaign".to_string()],
+            notes: None,
+        })
+    }
+
+    /// Tests that valid rows are saved and each row gets its own result in order
+    #[actix_rt::test]
+    async fn test_bulk_create_saves_valid_rows() {
+        let pool = init_test_db().await;
+        let user = create_test_user(&pool, "bulk").await;
+        let member = Member::personal(&user);
+
+        let rows = vec![
+            row("https://example.com/1", None),
+            row("javascript:alert(1)", None),
+            row("https://example.com/2", None),
+        ];
+        let result = bulk_create_urls(&member, rows, &pool).await.unwrap();
+        assert_eq!((result.succeeded, result.failed), (2, 1));
+
+        let rows: Vec<usize> = result.rows.iter().map(|r| r.row).collect();
+        assert_eq!(rows, vec![1, 2, 3]);
+        assert!(result.rows[0].link.is_some());
+        assert!(result.rows[2].link.is_some());
+
+        let (saved,): (i64,) =
+            sqlx::query_as("SELECT COUNT(*) FROM shortened_urls WHERE owner = $1")
+                .bind(user.id)
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert_eq!(saved, 2);
+
+        delete_test_users(&pool, &[user.id]).await;
+    }
+
+    /// Tests that an invalid row reports the field it was rejected on
+    #[actix_rt::test]
+    async fn test_bulk_create_reports_invalid_rows() {
+        let pool = init_test_db().await;
+        let user = create_test_user(&pool, "bulk_invalid").await;
+        let member = Member::personal(&user);
+
+        let rows = vec![
+            row("javascript:alert(1)", None),
+            Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Malformed row",
+            )),
+        ];
+        let result = bulk_create_urls(&member, rows, &pool).await.unwrap();
+        assert_eq!((result.succeeded, result.failed), (0, 2));
+        assert_eq!(
+            result.rows[0].target_field,
+            Some("original_url".to_string())
+        );
+        assert_eq!(result.rows[1].error, Some("Malformed row".to_string()));
+
+        delete_test_users(&pool, &[user.id]).await;
+    }
+
+    /// Tests that a custom path used twice only saves the first row
+    #[actix_rt::test]
+    async fn test_bulk_create_duplicate_custom_path() {
+        let pool = init_test_db().await;
+        let user = create_test_user(&pool, "bulk_duplicate").await;
+        let member = Member::personal(&user);
+        let slug = format!("bulk_{}", &uuid::Uuid::new_v4().to_string()[..8]);
+
+        let rows = vec![
+            row("https://example.com/2", Some(&slug)),
+            row("https://example.com/3", Some(&slug)),
+        ];
+        let result = bulk_create_urls(&member, rows, &pool).await.unwrap();
+        assert_eq!((result.succeeded, result.failed), (1, 1));
+        assert_eq!(
+            result.rows[0].link.as_ref().map(|l| l.short_url.as_str()),
+            Some(slug.as_str())
+        );
+        assert!(result.rows[1].error.is_some());
+
+        delete_test_users(&pool, &[user.id]).await;
+    }
+
+    /// Creates three URLs tagged `batch`, two of them on `example.com`
+    /// 
+    /// # Arguments
+    /// * `member` - The member creating the URLs
+    /// * `pool` - Database connection pool
+    /// 
+    /// # Returns
+    /// The IDs of the created URLs
+    async fn create_batch(member: &Member, pool: &PgPool) -> Vec<String> {
+        let rows = [
+            "https://example.com/a",
+            "https://www.example.com/b",
+            "https://example.org/c",
+        ]
+        .iter()
+        .map(|u| {
+            Ok(BulkUrlRow {
+                original_url: u.to_string(),
+                custom_path: None,
+                domain: None,
+                expiration: None,
+                tags: vec!["batch".to_string()],
+                notes: None,
+            })
+        })
+        .collect();
+        let created = bulk_create_urls(member, rows, pool).await.unwrap();
+        created
+            .rows
+            .iter()
+            .map(|r| r.link.as_ref().unwrap().id.to_string())
+            .collect()
+    }
+
+    fn request(body: serde_json::Value) -> BulkUpdateRequest {
+        serde_json::from_value(body).unwrap()
+    }
+
+    /// Loads a URL along with its tags
+    async fn load(id: &str, pool: &PgPool) -> ShortenedUrl {
+        let mut url: ShortenedUrl = sqlx::query_as("SELECT * FROM shortened_urls WHERE id = $1")
+            .bind(Uuid::parse_str(id).unwrap())
+            .fetch_one(pool)
+            .await
+            .unwrap();
+        load_tags(std::slice::from_mut(&mut url), pool)
+            .await
+            .unwrap();
+        url
+    }
+
+    /// Tests that selecting by IDs reports unknown and invalid IDs
+    #[actix_rt::test]
+    async fn test_bulk_update_by_ids() {
+        let pool = init_test_db().await;
+        let user = create_test_user(&pool, "bulkupd").await;
+        let member = Member::personal(&user);
+        let ids = create_batch(&member, &pool).await;
+
+        let unknown = Uuid::new_v4().to_string();
+        let result = bulk_update_urls(
+            &member,
+            request(serde_json::json!({
+                "ids": [ids[0], ids[1], unknown, "not-a-uuid"],
+                "action": "disable"
+            })),
+            &pool,
+        )
+        .await
+        .unwrap();
+        assert_eq!(result.affected, 2);
+        assert_eq!(result.failed, 2);
+        assert!(!load(&ids[0], &pool).await.enabled);
+        assert!(load(&ids[2], &pool).await.enabled);
+
+        delete_test_users(&pool, &[user.id]).await;
+    }
+
+    /// Tests that URLs of another workspace are reported as failures and left alone
+    #[actix_rt::test]
+    async fn test_bulk_update_other_workspace() {
+        let pool = init_test_db().await;
+        let user = create_test_user(&pool, "bulkupd_owner").await;
+        let other = create_test_user(&pool, "bulkupd_other").await;
+        let ids = create_batch(&Member::personal(&user), &pool).await;
+
+        let result = bulk_update_urls(
+            &Member::personal(&other),
+            request(serde_json::json!({"ids": [ids[0]], "action": "delete"})),
+            &pool,
+        )
+        .await
+        .unwrap();
+        assert_eq!(result.affected, 0);
+        assert_eq!(result.failures[0].id, ids[0]);
+        assert!(load(&ids[0], &pool).await.deleted_at.is_none());
+
+        delete_test_users(&pool, &[user.id, other.id]).await;
+    }
+
+    /// Tests moving the destinations selected by a domain filter to another host
+    #[actix_rt::test]
+    async fn test_bulk_change_host() {
+        let pool = init_test_db().await;
+        let user = create_test_user(&pool, "bulkupd_host").await;
+        let member = Member::personal(&user);
+        let ids = create_batch(&member, &pool).await;
+
+        let result = bulk_update_urls(
+            &member,
+            request(serde_json::json!({
+                "filter": {"domain": "example.com"},
+                "action": "change_host",
+                "from": "example.com",
+                "to": "example.net"
+            })),
+            &pool,
+        )
+        .await
+        .unwrap();
+        assert_eq!(result.affected, 2);
+        assert_eq!(
+            load(&ids[0], &pool).await.original_url,
+            "https://example.net/a"
+        );
+        assert_eq!(
+            load(&ids[1], &pool).await.original_url,
+            "https://www.example.net/b"
+        );
+
+        delete_test_users(&pool, &[user.id]).await;
+    }
+
+    /// Tests that changing the host of a destination on another host fails
+    #[actix_rt::test]
+    async fn test_bulk_change_host_mismatch() {
+        let pool = init_test_db().await;
+        let user = create_test_user(&pool, "bulkupd_mismatch").await;
+        let member = Member::personal(&user);
+        let ids = create_batch(&member, &pool).await;
+
+        let result = bulk_update_urls(
+            &member,
+            request(serde_json::json!({
+                "ids": [ids[2]],
+                "action": "change_host",
+                "from": "example.com",
+                "to": "example.net"
+            })),
+            &pool,
+        )
+        .await
+        .unwrap();
+        assert_eq!(result.failures[0].target_field, Some("from".to_string()));
+        assert_eq!(
+            load(&ids[2], &pool).await.original_url,
+            "https://example.org/c"
+        );
+
+        delete_test_users(&pool, &[user.id]).await;
+    }
+
+    /// Tests setting the expiry of the URLs selected by a tag filter
+    #[actix_rt::test]
+    async fn test_bulk_set_expiry() {
+        let pool = init_test_db().await;
+        let user = create_test_user(&pool, "bulkupd_expiry").await;
+        let member = Member::personal(&user);
+        let ids = create_batch(&member, &pool).await;
+
+        let result = bulk_update_urls(
+            &member,
+            request(serde_json::json!({
+                "filter": {"tags": "batch"},
+                "action": "set_expiry",
+                "expiration": 3600
+            })),
+            &pool,
+        )
+        .await
+        .unwrap();
+        assert_eq!(result.affected, 3);
+        assert!(load(&ids[2], &pool).await.expiry_date.is_some());
+
+        delete_test_users(&pool, &[user.id]).await;
+    }
+
+    /// Tests that added tags are normalized and kept alongside the existing ones
+    #[actix_rt::test]
+    async fn test_bulk_add_tags() {
+        let pool = init_test_db().await;
+        let user = create_test_user(&pool, "bulkupd_tags").await;
+        let member = Member::personal(&user);
+        let ids = create_batch(&member, &pool).await;
+
+        bulk_update_urls(
+            &member,
+            request(serde_json::json!({"ids": [ids[0]], "action": "add_tags", "tags": ["Extra"]})),
+            &pool,
+        )
+        .await
+        .unwrap();
+        assert_eq!(load(&ids[0], &pool).await.tags, vec!["batch", "extra"]);
+
+        delete_test_users(&pool, &[user.id]).await;
+    }
+
+    /// Tests that a request must select URLs by exactly one of IDs and filter
+    #[actix_rt::test]
+    async fn test_bulk_update_requires_selection() {
+        let pool = init_test_db().await;
+        let user = create_test_user(&pool, "bulkupd_select").await;
+
+        let result = bulk_update_urls(
+            &Member::personal(&user),
+            request(serde_json::json!({"action": "enable"})),
+            &pool,
+        )
+        .await;
+        assert!(result.is_err());
+
+        delete_test_users(&pool, &[user.id]).await;
+    }
+
+    /// Tests deleting the URLs selected by a tag filter
+    #[actix_rt::test]
+    async fn test_bulk_delete() {
+        let pool = init_test_db().await;
+        let user = create_test_user(&pool, "bulkupd_delete").await;
+        let member = Member::personal(&user);
+        create_batch(&member, &pool).await;
+
+        let result = bulk_update_urls(
+            &member,
+            request(serde_json::json!({"filter": {"tags": "batch"}, "action": "delete"})),
+            &pool,
+        )
+        .await
+        .unwrap();
+        assert_eq!(result.affected, 3);
+
+        let (remaining,): (i64,) = sqlx::query_as(
+            "SELECT COUNT(*) FROM shortened_urls WHERE owner = $1 AND deleted_at IS NULL",
+        )
+        .bind(user.id)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        assert_eq!(remaining, 0);
+
+        delete_test_users(&pool, &[user.id]).await;
+    }
+}
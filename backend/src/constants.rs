@@ -3,6 +3,20 @@ use rand::Rng;
 
 use std::path::PathBuf;
 
+/// Splits a comma separated environment value into a list
+fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+/// Splits a comma separated environment value into a lowercase list
+fn parse_list(value: &str) -> Vec<String> {
+    split_list(&value.to_lowercase())
+}
+
 /// The port number the server will listen on
 /// Defaults to 8080 if not specified in environment variables
 pub(crate) static PORT: Lazy<u16> = Lazy::new(|| {
@@ -37,6 +51,281 @@ pub(crate) static FRONTEND_DIST: Lazy<PathBuf> = Lazy::new(|| {
 pub(crate) static APP_DOMAIN: Lazy<String> =
     Lazy::new(|| std::env::var("DOMAIN").unwrap_or(format!("localhost:{}", *PORT)));
 
+/// Additional domain names the application is served on besides `APP_DOMAIN`
+/// Used to prevent redirect loops through any domain nurl answers on
+/// Defaults to none if not specified in environment variables
+pub(crate) static ADDITIONAL_DOMAINS: Lazy<Vec<String>> =
+    Lazy::new(|| parse_list(&std::env::var("ADDITIONAL_DOMAINS").unwrap_or_default()));
+
+/// The scheme of the full short URLs included in link responses
+/// Defaults to "https" in production and "http" otherwise if not specified in environment variables
+pub(crate) static LINK_SCHEME: Lazy<String> = Lazy::new(|| {
+    std::env::var("LINK_SCHEME").unwrap_or(if *ENVIRONMENT == PRODUCTION_ENV {
+        "https".to_string()
+    } else {
+        "http".to_string()
+    })
+});
+
+/// Whether destinations on other known URL shorteners are rejected
+/// Defaults to false if not specified in environment variables
+pub(crate) static BLOCK_SHORTENER_CHAINS: Lazy<bool> = Lazy::new(|| {
+    std::env::var("BLOCK_SHORTENER_CHAINS")
+        .map(|v| v == "true" || v == "1")
+        .unwrap_or(false)
+});
+
+/// Domains of other URL shorteners, used when `BLOCK_SHORTENER_CHAINS` is enabled
+/// Defaults to a list of popular shorteners if not specified in environment variables
+pub(crate) static SHORTENER_DOMAINS: Lazy<Vec<String>> = Lazy::new(|| {
+    let defaults =
+        "bit.ly,tinyurl.com,t.co,goo.gl,ow.ly,is.gd,buff.ly,rebrand.ly,cutt.ly,shorturl.at";
+    parse_list(&std::env::var("SHORTENER_DOMAINS").unwrap_or(defaults.to_string()))
+});
+
+/// The URL schemes that shortened URLs are allowed to point to
+/// Defaults to "http,https" if not specified in environment variables
+pub(crate) static ALLOWED_URL_SCHEMES: Lazy<Vec<String>> = Lazy::new(|| {
+    parse_list(&std::env::var("ALLOWED_URL_SCHEMES").unwrap_or("http,https".to_string()))
+});
+
+/// The maximum length (in bytes) of a destination URL
+/// Defaults to 2048 if not specified in environment variables
+pub(crate) static MAX_URL_LENGTH: Lazy<usize> = Lazy::new(|| {
+    std::env::var("MAX_URL_LENGTH")
+        .unwrap_or("2048".to_string())
+        .parse::<usize>()
+        .expect("MAX_URL_LENGTH must be a valid unsigned integer")
+});
+
+/// Files containing blocked domains and URL patterns
+/// Accepts a comma separated list of hosts-format or plain domain list files
+/// Defaults to none if not specified in environment variables
+pub(crate) static BLOCKLIST_FILES: Lazy<Vec<PathBuf>> = Lazy::new(|| {
+    split_list(&std::env::var("BLOCKLIST_FILES").unwrap_or_default())
+        .into_iter()
+        .map(PathBuf::from)
+        .collect()
+});
+
+/// How often (in seconds) the blocklist files are reloaded, 0 disables periodic reloads
+/// The blocklist is also reloaded on SIGHUP
+/// Defaults to 3600 if not specified in environment variables
+pub(crate) static BLOCKLIST_RELOAD_INTERVAL: Lazy<u64> = Lazy::new(|| {
+    std::env::var("BLOCKLIST_RELOAD_INTERVAL")
+        .unwrap_or("3600".to_string())
+        .parse::<u64>()
+        .expect("BLOCKLIST_RELOAD_INTERVAL must be a valid unsigned integer")
+});
+
+/// What to do when a visited link points to a blocklisted destination
+/// Either "block" to refuse the redirect or "warn" to show a warning interstitial
+/// Defaults to "block" if not specified in environment variables
+pub(crate) static BLOCKLIST_ACTION: Lazy<String> = Lazy::new(|| {
+    let action = std::env::var("BLOCKLIST_ACTION").unwrap_or("block".to_string());
+    if action != "block" && action != "warn" {
+        panic!("BLOCKLIST_ACTION must be either \"block\" or \"warn\"");
+    }
+    action
+});
+
+/// Where visitors of a disabled link are sent instead of being shown `DISABLED_LINK_MESSAGE`
+/// Defaults to none if not specified in environment variables
+pub(crate) static DISABLED_LINK_FALLBACK_URL: Lazy<Option<String>> = Lazy::new(|| {
+    std::env::var("DISABLED_LINK_FALLBACK_URL")
+        .ok()
+        .filter(|v| !v.trim().is_empty())
+        .inspect(|v| {
+            url::Url::parse(v).expect("DISABLED_LINK_FALLBACK_URL must be a valid URL");
+        })
+});
+
+/// The message shown to visitors of a disabled link
+/// Defaults to "This link has been temporarily disabled." if not specified in environment variables
+pub(crate) static DISABLED_LINK_MESSAGE: Lazy<String> = Lazy::new(|| {
+    std::env::var("DISABLED_LINK_MESSAGE")
+        .unwrap_or("This link has been temporarily disabled.".to_string())
+});
+
+/// How often (in seconds) the destinations of active links are health checked, 0 disables checks
+/// Defaults to 86400 (once a day) if not specified in environment variables
+pub(crate) static LINK_CHECK_INTERVAL: Lazy<u64> = Lazy::new(|| {
+    std::env::var("LINK_CHECK_INTERVAL")
+        .unwrap_or("86400".to_string())
+        .parse::<u64>()
+        .expect("LINK_CHECK_INTERVAL must be a valid unsigned integer")
+});
+
+/// The maximum number of destinations health checked at the same time
+/// Defaults to 8 if not specified in environment variables
+pub(crate) static LINK_CHECK_CONCURRENCY: Lazy<usize> = Lazy::new(|| {
+    std::env::var("LINK_CHECK_CONCURRENCY")
+        .unwrap_or("8".to_string())
+        .parse::<usize>()
+        .expect("LINK_CHECK_CONCURRENCY must be a valid unsigned integer")
+});
+
+/// How long (in seconds) a single destination health check may take
+/// Defaults to 10 if not specified in environment variables
+pub(crate) static LINK_CHECK_TIMEOUT: Lazy<u64> = Lazy::new(|| {
+    std::env::var("LINK_CHECK_TIMEOUT")
+        .unwrap_or("10".to_string())
+        .parse::<u64>()
+        .expect("LINK_CHECK_TIMEOUT must be a valid unsigned integer")
+});
+
+/// The minimum delay (in milliseconds) between two health checks against the same host
+/// Defaults to 1000 if not specified in environment variables
+pub(crate) static LINK_CHECK_HOST_DELAY: Lazy<u64> = Lazy::new(|| {
+    std::env::var("LINK_CHECK_HOST_DELAY")
+        .unwrap_or("1000".to_string())
+        .parse::<u64>()
+        .expect("LINK_CHECK_HOST_DELAY must be a valid unsigned integer")
+});
+
+/// Whether destinations on private, loopback and link-local addresses may be health checked
+/// Defaults to false if not specified in environment variables
+pub(crate) static LINK_CHECK_ALLOW_PRIVATE: Lazy<bool> = Lazy::new(|| {
+    std::env::var("LINK_CHECK_ALLOW_PRIVATE")
+        .map(|v| v == "true" || v == "1")
+        .unwrap_or(false)
+});
+
+/// Whether the title, description, favicon and Open Graph image of destinations are fetched
+/// Defaults to true if not specified in environment variables
+pub(crate) static FETCH_METADATA: Lazy<bool> = Lazy::new(|| {
+    std::env::var("FETCH_METADATA")
+        .map(|v| v == "true" || v == "1")
+        .unwrap_or(true)
+});
+
+/// How long (in seconds) fetching the metadata of a destination may take
+/// Defaults to 5 if not specified in environment variables
+pub(crate) static METADATA_TIMEOUT: Lazy<u64> = Lazy::new(|| {
+    std::env::var("METADATA_TIMEOUT")
+        .unwrap_or("5".to_string())
+        .parse::<u64>()
+        .expect("METADATA_TIMEOUT must be a valid unsigned integer")
+});
+
+/// The maximum number of bytes read from a destination page when fetching its metadata
+/// Defaults to 524288 (512 KiB) if not specified in environment variables
+pub(crate) static METADATA_MAX_BYTES: Lazy<usize> = Lazy::new(|| {
+    std::env::var("METADATA_MAX_BYTES")
+        .unwrap_or("524288".to_string())
+        .parse::<usize>()
+        .expect("METADATA_MAX_BYTES must be a valid unsigned integer")
+});
+
+/// Whether metadata may be fetched from private, loopback and link-local addresses
+/// Defaults to false if not specified in environment variables
+pub(crate) static METADATA_ALLOW_PRIVATE: Lazy<bool> = Lazy::new(|| {
+    std::env::var("METADATA_ALLOW_PRIVATE")
+        .map(|v| v == "true" || v == "1")
+        .unwrap_or(false)
+});
+
+/// How many days deleted links stay in the trash before they are purged for good
+/// Defaults to 30 if not specified in environment variables
+pub(crate) static TRASH_RETENTION_DAYS: Lazy<i64> = Lazy::new(|| {
+    std::env::var("TRASH_RETENTION_DAYS")
+        .unwrap_or("30".to_string())
+        .parse::<u32>()
+        .map(i64::from)
+        .expect("TRASH_RETENTION_DAYS must be a valid unsigned integer")
+});
+
+/// How often (in seconds) links past their trash retention are purged, 0 disables purging
+/// Defaults to 3600 if not specified in environment variables
+pub(crate) static TRASH_PURGE_INTERVAL: Lazy<u64> = Lazy::new(|| {
+    std::env::var("TRASH_PURGE_INTERVAL")
+        .unwrap_or("3600".to_string())
+        .parse::<u64>()
+        .expect("TRASH_PURGE_INTERVAL must be a valid unsigned integer")
+});
+
+/// How many days the short code of a permanently deleted link stays reserved for its owner
+/// Defaults to 365 if not specified in environment variables
+pub(crate) static TOMBSTONE_DAYS: Lazy<i64> = Lazy::new(|| {
+    std::env::var("TOMBSTONE_DAYS")
+        .unwrap_or("365".to_string())
+        .parse::<u32>()
+        .map(i64::from)
+        .expect("TOMBSTONE_DAYS must be a valid unsigned integer")
+});
+
+/// The number of clicks above which a permanently deleted link's short code is reserved forever, 0 disables this
+/// Defaults to 1000 if not specified in environment variables
+pub(crate) static TOMBSTONE_PERMANENT_CLICKS: Lazy<i64> = Lazy::new(|| {
+    std::env::var("TOMBSTONE_PERMANENT_CLICKS")
+        .unwrap_or("1000".to_string())
+        .parse::<u32>()
+        .map(i64::from)
+        .expect("TOMBSTONE_PERMANENT_CLICKS must be a valid unsigned integer")
+});
+
+/// How many hours an Idempotency-Key is remembered after the link creation it was sent with
+/// Defaults to 24 if not specified in environment variables
+pub(crate) static IDEMPOTENCY_KEY_HOURS: Lazy<i64> = Lazy::new(|| {
+    std::env::var("IDEMPOTENCY_KEY_HOURS")
+        .unwrap_or("24".to_string())
+        .parse::<u32>()
+        .map(i64::from)
+        .expect("IDEMPOTENCY_KEY_HOURS must be a valid unsigned integer")
+});
+
+/// How often (in seconds) forgotten Idempotency-Keys are removed, 0 disables removal
+/// Defaults to 3600 if not specified in environment variables
+pub(crate) static IDEMPOTENCY_PURGE_INTERVAL: Lazy<u64> = Lazy::new(|| {
+    std::env::var("IDEMPOTENCY_PURGE_INTERVAL")
+        .unwrap_or("3600".to_string())
+        .parse::<u64>()
+        .expect("IDEMPOTENCY_PURGE_INTERVAL must be a valid unsigned integer")
+});
+
+/// How often (in seconds) custom domains are verified again, 0 disables re-verification
+/// Defaults to 3600 if not specified in environment variables
+pub(crate) static DOMAIN_VERIFY_INTERVAL: Lazy<u64> = Lazy::new(|| {
+    std::env::var("DOMAIN_VERIFY_INTERVAL")
+        .unwrap_or("3600".to_string())
+        .parse::<u64>()
+        .expect("DOMAIN_VERIFY_INTERVAL must be a valid unsigned integer")
+});
+
+/// How many days an unverified custom domain stays with the user who added it before anyone else can add it
+/// Defaults to 7 if not specified in environment variables
+pub(crate) static DOMAIN_CLAIM_DAYS: Lazy<i64> = Lazy::new(|| {
+    std::env::var("DOMAIN_CLAIM_DAYS")
+        .unwrap_or("7".to_string())
+        .parse::<u32>()
+        .map(i64::from)
+        .expect("DOMAIN_CLAIM_DAYS must be a valid unsigned integer")
+});
+
+/// How long (in seconds) fetching the verification file of a custom domain may take
+/// Defaults to 10 if not specified in environment variables
+pub(crate) static DOMAIN_VERIFY_TIMEOUT: Lazy<u64> = Lazy::new(|| {
+    std::env::var("DOMAIN_VERIFY_TIMEOUT")
+        .unwrap_or("10".to_string())
+        .parse::<u64>()
+        .expect("DOMAIN_VERIFY_TIMEOUT must be a valid unsigned integer")
+});
+
+/// How many minutes an access token is valid before it has to be refreshed
+/// Defaults to 15 if not specified in environment variables
+pub(crate) static ACCESS_TOKEN_MINUTES: Lazy<i64> = Lazy::new(|| {
+    std::env::var("ACCESS_TOKEN_MINUTES")
+        .unwrap_or("15".to_string())
+        .parse::<u32>()
+        .map(i64::from)
+        .expect("ACCESS_TOKEN_MINUTES must be a valid unsigned integer")
+});
+
+/// Usernames of the users allowed to use the admin endpoints
+/// Defaults to none if not specified in environment variables
+pub(crate) static ADMIN_USERNAMES: Lazy<Vec<String>> =
+    Lazy::new(|| split_list(&std::env::var("ADMIN_USERNAMES").unwrap_or_default()));
+
 /// The secret key used for JWT token generation
 /// In development, uses a fixed key for convenience
 /// In production, generates a random 32-character string if not specified
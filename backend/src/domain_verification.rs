@@ -0,0 +1,527 @@
+use chrono::{DateTime, Utc};
+use hickory_resolver::{
+    config::ResolverConfig, name_server::TokioConnectionProvider, Resolver, TokioResolver,
+};
+use sqlx::PgPool;
+use std::{sync::Arc, time::Duration};
+use uuid::Uuid;
+
+use crate::{
+    constants::{DOMAIN_VERIFY_INTERVAL, DOMAIN_VERIFY_TIMEOUT},
+    service::parse_uuid,
+    structs::{Domain, User},
+};
+
+/// The prefix of the DNS name holding the verification TXT record of a domain
+const VERIFICATION_RECORD_PREFIX: &str = "_nurl-verification";
+
+/// The path of the verification file on a domain
+const VERIFICATION_FILE_PATH: &str = "/.well-known/nurl-verification.txt";
+
+/// The largest verification file (in bytes) that is read
+const MAX_VERIFICATION_FILE_BYTES: usize = 1024;
+
+/// Looks up the records users publish to prove they control a domain
+pub(crate) trait DomainResolver {
+    /// Looks up the TXT records of a DNS name
+    /// 
+    /// # Arguments
+    /// * `name` - The DNS name to look up
+    /// 
+    /// # Returns
+    /// Result containing the records, empty if the name has none
+    async fn txt_records(&self, name: &str) -> Result<Vec<String>, std::io::Error>;
+
+    /// Fetches the verification file served on a domain
+    /// 
+    /// # Arguments
+    /// * `hostname` - The hostname of the domain
+    /// 
+    /// # Returns
+    /// Result containing the contents of the file, `None` if the domain doesn't serve it
+    async fn well_known_file(&self, hostname: &str) -> Result<Option<String>, std::io::Error>;
+}
+
+/// Resolves verification records using the system's DNS configuration and HTTP
+pub(crate) struct SystemResolver {
+    /// The DNS resolver
+    dns: TokioResolver,
+    /// The HTTP client used for verification files
+    http: reqwest::Client,
+}
+
+impl SystemResolver {
+    /// Builds the resolver, falling back to the default DNS servers if the
+    /// system configuration can't be read
+    pub fn new() -> Result<Self, std::io::Error> {
+        let dns = match Resolver::builder_tokio() {
+            Ok(builder) => builder.build(),
+            Err(_) => Resolver::builder_with_config(
+                ResolverConfig::default(),
+                TokioConnectionProvider::default(),
+            )
+            .build(),
+        };
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_secs(*DOMAIN_VERIFY_TIMEOUT))
+            .redirect(reqwest::redirect::Policy::limited(5))
+            .user_agent(concat!("nurl-domain-verifier/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .map_err(|e| std::io::Error::other(e.to_string()))?;
+        Ok(Self { dns, http })
+    }
+}
+
+impl DomainResolver for SystemResolver {
+    async fn txt_records(&self, name: &str) -> Result<Vec<String>, std::io::Error> {
+        match self.dns.txt_lookup(name).await {
+            Ok(lookup) => Ok(lookup
+                .iter()
+                .map(|txt| {
+                    txt.txt_data()
+                        .iter()
+                        .map(|part| String::from_utf8_lossy(part))
+                        .collect()
+                })
+                .collect()),
+            Err(e) if e.is_no_records_found() || e.is_nx_domain() => Ok(Vec::new()),
+            Err(e) => Err(std::io::Error::other(e.to_string())),
+        }
+    }
+
+    async fn well_known_file(&self, hostname: &str) -> Result<Option<String>, std::io::Error> {
+        let response = self
+            .http
+            .get(format!("http://{}{}", hostname, VERIFICATION_FILE_PATH))
+            .send()
+            .await
+            .map_err(|e| std::io::Error::other(e.to_string()))?;
+
+        let status = response.status();
+        if status.is_server_error() {
+            return Err(std::io::Error::other(format!(
+                "The verification file request failed with status {}",
+                status
+            )));
+        }
+        if !status.is_success() {
+            return Ok(None);
+        }
+
+        let body = response
+            .bytes()
+            .await
+            .map_err(|e| std::io::Error::other(e.to_string()))?;
+        if body.len() > MAX_VERIFICATION_FILE_BYTES {
+            return Ok(None);
+        }
+        Ok(Some(String::from_utf8_lossy(&body).into_owned()))
+    }
+}
+
+/// The outcome of checking a domain for its verification token
+#[derive(Debug, PartialEq)]
+pub(crate) enum VerificationOutcome {
+    /// The token was found
+    Verified,
+    /// Both checks completed without finding the token
+    NotFound(String),
+    /// The token wasn't found but a check failed, so the domain keeps its state
+    Inconclusive(String),
+}
+
+/// Returns the DNS name the verification TXT record of a domain is published on
+pub(crate) fn verification_record_name(hostname: &str) -> String {
+    format!("{}.{}", VERIFICATION_RECORD_PREFIX, hostname)
+}
+
+/// Checks whether a domain publishes its verification token
+/// 
+/// The token is accepted in a TXT record `nurl-verification=<token>` on
+/// `_nurl-verification.<hostname>`, or as the contents of
+/// `http://<hostname>/.well-known/nurl-verification.txt`.
+/// 
+/// # Arguments
+/// * `resolver` - The resolver to look the records up with
+/// * `hostname` - The hostname of the domain
+/// * `token` - The verification token of the domain
+/// 
+/// # Returns
+/// The outcome of the check
+pub(crate) async fn check_domain(
+    resolver: &impl DomainResolver,
+    hostname: &str,
+    token: &str,
+) -> VerificationOutcome {
+    let mut errors = Vec::new();
+
+    let expected = format!("nurl-verification={}", token);
+    match resolver
+        .txt_records(&verification_record_name(hostname))
+        .await
+    {
+        Ok(records) if records.iter().any(|r| r.trim() == expected) => {
+            return VerificationOutcome::Verified;
+        }
+        Ok(_) => {}
+        Err(e) => errors.push(format!("DNS lookup failed: {}", e)),
+    }
+
+    match resolver.well_known_file(hostname).await {
+        Ok(Some(contents)) if contents.trim() == token => return VerificationOutcome::Verified,
+        Ok(_) => {}
+        Err(e) => errors.push(format!("Fetching the verification file failed: {}", e)),
+    }
+
+    if errors.is_empty() {
+        VerificationOutcome::NotFound(
+            "The verification token wasn't found in DNS or the verification file".to_string(),
+        )
+    } else {
+        VerificationOutcome::Inconclusive(errors.join("; "))
+    }
+}
+
+/// Stores the outcome of checking a domain
+/// 
+/// # Arguments
+/// * `id` - The ID of the checked domain
+/// * `outcome` - The outcome of the check
+/// * `now` - When the check was made
+/// * `pool` - Database connection pool
+/// 
+/// # Returns
+/// Result containing the domain after the check
+async fn record_outcome(
+    id: Uuid,
+    outcome: &VerificationOutcome,
+    now: DateTime<Utc>,
+    pool: &PgPool,
+) -> Result<Domain, std::io::Error> {
+    let (verified_at, error) = match outcome {
+        VerificationOutcome::Verified => ("COALESCE(verified_at, $1)", None),
+        VerificationOutcome::NotFound(e) => ("NULL", Some(e)),
+        VerificationOutcome::Inconclusive(e) => ("verified_at", Some(e)),
+    };
+
+    sqlx::query_as::<_, Domain>(&format!(
+        r#"
+      UPDATE domains
+      SET
+          verified_at = {},
+          verification_checked_at = $1,
+          verification_error = $2
+      WHERE id = $3
+      RETURNING *
+      "#,
+        verified_at
+    ))
+    .bind(now)
+    .bind(error)
+    .bind(id)
+    .fetch_one(pool)
+    .await
+    .map_err(|e| std::io::Error::other(e.to_string()))
+}
+
+/// Checks a domain of a user for its verification token right away
+/// 
+/// # Arguments
+/// * `user` - The user owning the domain
+/// * `id` - The ID of the domain to verify
+/// * `resolver` - The resolver to look the records up with
+/// * `pool` - Database connection pool
+/// 
+/// # Returns
+/// Result containing the domain after the check, with the reason it isn't
+/// verified if it isn't
+pub async fn verify_domain(
+    user: &User,
+    id: &str,
+    resolver: &impl DomainResolver,
+    pool: &PgPool,
+) -> Result<Domain, std::io::Error> {
+    let uuid = parse_uuid(id)?;
+
+    let domain = sqlx::query_as::<_, Domain>("SELECT * FROM domains WHERE id = $1 AND owner = $2")
+        .bind(uuid)
+        .bind(user.id)
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))?
+        .ok_or(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            "Domain not found or you don't have permission to verify it",
+        ))?;
+
+    let outcome = check_domain(resolver, &domain.hostname, &domain.verification_token).await;
+    record_outcome(domain.id, &outcome, Utc::now(), pool).await
+}
+
+/// Checks every domain that wasn't checked, or added if it never was, since the given time
+/// 
+/// Unverified domains become verified once their token is found, and verified
+/// domains lose their verification once it is gone.
+/// 
+/// # Arguments
+/// * `checked_before` - Domains last checked or added before this time are checked
+/// * `resolver` - The resolver to look the records up with
+/// * `pool` - Database connection pool
+/// 
+/// # Returns
+/// Result containing the number of domains that lost their verification
+pub(crate) async fn reverify_domains(
+    checked_before: DateTime<Utc>,
+    resolver: &impl DomainResolver,
+    pool: &PgPool,
+) -> Result<usize, std::io::Error> {
+    let domains = sqlx::query_as::<_, Domain>(
+        "SELECT * FROM domains WHERE COALESCE(verification_checked_at, created_at) < $1",
+    )
+    .bind(checked_before)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| std::io::Error::other(e.to_string()))?;
+
+    let mut lost = 0;
+    for domain in domains {
+        let outcome = check_domain(resolver, &domain.hostname, &domain.verification_token).await;
+        let checked = record_outcome(domain.id, &outcome, Utc::now(), pool).await?;
+        if domain.verified_at.is_some() && checked.verified_at.is_none() {
+            lost += 1;
+        }
+    }
+
+    Ok(lost)
+}
+
+/// Starts the background task that periodically verifies custom domains again
+/// 
+/// The first pass runs one interval after startup. Nothing is started if
+/// `DOMAIN_VERIFY_INTERVAL` is 0.
+/// 
+/// # Arguments
+/// * `resolver` - The resolver to look the records up with
+/// * `pool` - Database connection pool
+pub(crate) fn spawn_domain_verifier(resolver: Arc<SystemResolver>, pool: PgPool) {
+    if *DOMAIN_VERIFY_INTERVAL == 0 {
+        return;
+    }
+
+    tokio::spawn(async move {
+        let period = Duration::from_secs(*DOMAIN_VERIFY_INTERVAL);
+        let mut interval = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+        loop {
+            interval.tick().await;
+            match reverify_domains(Utc::now(), resolver.as_ref(), &pool).await {
+                Ok(lost) => println!(
+                    "Domain verification finished, {} domains lost their verification",
+                    lost
+                ),
+                Err(e) => println!("Domain verification failed: {}", e),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        domains::list_domains,
+        utils::{add_test_domain, create_test_user, delete_test_users, init_test_db},
+    };
+    use std::{collections::HashMap, sync::Mutex};
+
+    /// An in-memory stand-in for DNS and the verification files
+    #[derive(Default)]
+    struct StaticResolver {
+        /// TXT records by DNS name
+        txt: Mutex<HashMap<String, Vec<String>>>,
+        /// Verification files by hostname
+        files: Mutex<HashMap<String, String>>,
+        /// Whether every lookup fails, like an unreachable DNS server
+        failing: Mutex<bool>,
+    }
+
+    impl DomainResolver for StaticResolver {
+        async fn txt_records(&self, name: &str) -> Result<Vec<String>, std::io::Error> {
+            if *self.failing.lock().unwrap() {
+                return Err(std::io::Error::other("timed out"));
+            }
+            Ok(self
+                .txt
+                .lock()
+                .unwrap()
+                .get(name)
+                .cloned()
+                .unwrap_or_default())
+        }
+
+        async fn well_known_file(&self, hostname: &str) -> Result<Option<String>, std::io::Error> {
+            if *self.failing.lock().unwrap() {
+                return Err(std::io::Error::other("timed out"));
+            }
+            Ok(self.files.lock().unwrap().get(hostname).cloned())
+        }
+    }
+
+    /// Publishes the verification token of a domain in a TXT record
+    /// 
+    /// # Arguments
+    /// * `resolver` - The resolver to add the record to
+    /// * `domain` - The domain to publish the token of
+    fn publish_txt_record(resolver: &StaticResolver, domain: &Domain) {
+        resolver.txt.lock().unwrap().insert(
+            verification_record_name(&domain.hostname),
+            vec![
+                "v=spf1 -all".to_string(),
+                format!("nurl-verification={}", domain.verification_token),
+            ],
+        );
+    }
+
+    /// Tests that a domain without the token stays unverified
+    #[actix_rt::test]
+    async fn test_verify_domain_without_token() {
+        let pool = init_test_db().await;
+        let user = create_test_user(&pool, "verify_missing").await;
+        let domain = add_test_domain(&pool, &user, false).await;
+
+        let resolver = StaticResolver::default();
+        let checked = verify_domain(&user, &domain.id.to_string(), &resolver, &pool)
+            .await
+            .unwrap();
+        assert!(checked.verified_at.is_none());
+        assert!(checked.verification_error.is_some());
+
+        delete_test_users(&pool, &[user.id]).await;
+    }
+
+    /// Tests that domains of other users can't be verified
+    #[actix_rt::test]
+    async fn test_verify_domain_of_other_user() {
+        let pool = init_test_db().await;
+        let user = create_test_user(&pool, "verify_owner").await;
+        let other = create_test_user(&pool, "verify_other").await;
+        let domain = add_test_domain(&pool, &user, false).await;
+
+        let resolver = StaticResolver::default();
+        publish_txt_record(&resolver, &domain);
+        let result = verify_domain(&other, &domain.id.to_string(), &resolver, &pool).await;
+        assert!(result.is_err_and(|e| e.kind() == std::io::ErrorKind::NotFound));
+
+        delete_test_users(&pool, &[user.id, other.id]).await;
+    }
+
+    /// Tests that the token in a TXT record verifies the domain
+    #[actix_rt::test]
+    async fn test_verify_domain_by_txt_record() {
+        let pool = init_test_db().await;
+        let user = create_test_user(&pool, "verify_txt").await;
+        let domain = add_test_domain(&pool, &user, false).await;
+
+        let resolver = StaticResolver::default();
+        publish_txt_record(&resolver, &domain);
+        let checked = verify_domain(&user, &domain.id.to_string(), &resolver, &pool)
+            .await
+            .unwrap();
+        assert!(checked.verified_at.is_some());
+        assert!(checked.verification_error.is_none());
+
+        delete_test_users(&pool, &[user.id]).await;
+    }
+
+    /// Tests that the token in the verification file verifies the domain
+    #[actix_rt::test]
+    async fn test_verify_domain_by_file() {
+        let pool = init_test_db().await;
+        let user = create_test_user(&pool, "verify_file").await;
+        let domain = add_test_domain(&pool, &user, false).await;
+
+        let resolver = StaticResolver::default();
+        resolver.files.lock().unwrap().insert(
+            domain.hostname.clone(),
+            format!("{}\n", domain.verification_token),
+        );
+        let checked = verify_domain(&user, &domain.id.to_string(), &resolver, &pool)
+            .await
+            .unwrap();
+        assert!(checked.verified_at.is_some());
+        assert!(checked.verification_error.is_none());
+
+        delete_test_users(&pool, &[user.id]).await;
+    }
+
+    /// Tests that verifying a verified domain again keeps the time it was verified at
+    #[actix_rt::test]
+    async fn test_verify_domain_keeps_verification_time() {
+        let pool = init_test_db().await;
+        let user = create_test_user(&pool, "verify_again").await;
+        let domain = add_test_domain(&pool, &user, false).await;
+        let id = domain.id.to_string();
+
+        let resolver = StaticResolver::default();
+        publish_txt_record(&resolver, &domain);
+        let verified_at = verify_domain(&user, &id, &resolver, &pool)
+            .await
+            .unwrap()
+            .verified_at
+            .unwrap();
+
+        let checked = verify_domain(&user, &id, &resolver, &pool).await.unwrap();
+        assert_eq!(
+            checked.verified_at.map(|t| t.timestamp_micros()),
+            Some(verified_at.timestamp_micros())
+        );
+
+        delete_test_users(&pool, &[user.id]).await;
+    }
+
+    /// Tests that re-verification survives failed lookups and removes the verification once the token is gone
+    #[actix_rt::test]
+    async fn test_reverify_domains() {
+        let pool = init_test_db().await;
+        let user = create_test_user(&pool, "reverify").await;
+        let domain = add_test_domain(&pool, &user, false).await;
+        let resolver = StaticResolver::default();
+        publish_txt_record(&resolver, &domain);
+        verify_domain(&user, &domain.id.to_string(), &resolver, &pool)
+            .await
+            .unwrap();
+        resolver.txt.lock().unwrap().clear();
+
+        // Only this domain is due, so domains of other tests aren't re-verified
+        let long_ago = Utc::now() - chrono::Duration::days(365 * 30);
+        let make_due = async || {
+            sqlx::query("UPDATE domains SET verification_checked_at = $1 WHERE id = $2")
+                .bind(long_ago)
+                .bind(domain.id)
+                .execute(&pool)
+                .await
+                .unwrap();
+        };
+        let checked_before = long_ago + chrono::Duration::seconds(1);
+
+        *resolver.failing.lock().unwrap() = true;
+        make_due().await;
+        let lost = reverify_domains(checked_before, &resolver, &pool)
+            .await
+            .unwrap();
+        assert_eq!(lost, 0);
+        let checked = &list_domains(&user, &pool).await.unwrap()[0];
+        assert!(checked.verified_at.is_some());
+        assert!(checked.verification_error.is_some());
+
+        *resolver.failing.lock().unwrap() = false;
+        make_due().await;
+        let lost = reverify_domains(checked_before, &resolver, &pool)
+            .await
+            .unwrap();
+        assert_eq!(lost, 1);
+        let checked = &list_domains(&user, &pool).await.unwrap()[0];
+        assert!(checked.verified_at.is_none());
+
+        delete_test_users(&pool, &[user.id]).await;
+    }
+}
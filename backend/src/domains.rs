@@ -0,0 +1,506 @@
+use chrono::{DateTime, Duration, Utc};
+use sqlx::{PgExecutor, PgPool};
+use url::{Host, Url};
+use uuid::Uuid;
+
+use crate::{
+    constants::{ADDITIONAL_DOMAINS, APP_DOMAIN, DOMAIN_CLAIM_DAYS, LINK_SCHEME},
+    service::parse_uuid,
+    structs::{Domain, FieldError, User},
+};
+
+/// Extracts the lowercase hostname from a domain or `Host` header, ignoring any port
+/// 
+/// # Arguments
+/// * `host` - The domain, optionally followed by a port
+/// 
+/// # Returns
+/// Option containing the hostname if it could be parsed
+pub(crate) fn hostname(host: &str) -> Option<String> {
+    Url::parse(&format!("http://{}", host.trim()))
+        .ok()
+        .and_then(|u| u.host_str().map(|h| h.trim_end_matches('.').to_string()))
+}
+
+/// Builds the full short URL of a link
+/// 
+/// # Arguments
+/// * `domain` - Hostname of the custom domain of the link, `None` for `APP_DOMAIN`
+/// * `short_url` - The short code of the link
+/// 
+/// # Returns
+/// The URL visitors open to be redirected
+pub(crate) fn short_link(domain: Option<&str>, short_url: &str) -> String {
+    format!(
+        "{}://{}/{}",
+        *LINK_SCHEME,
+        domain.unwrap_or(APP_DOMAIN.as_str()),
+        short_url
+    )
+}
+
+/// Checks whether a hostname is one nurl itself is configured to be served on
+fn is_app_hostname(hostname: &str) -> bool {
+    std::iter::once(APP_DOMAIN.as_str())
+        .chain(ADDITIONAL_DOMAINS.iter().map(|d| d.as_str()))
+        .filter_map(self::hostname)
+        .any(|h| h == hostname)
+}
+
+/// Normalizes the hostname of a custom domain
+/// 
+/// # Arguments
+/// * `hostname` - The hostname given by the user
+/// 
+/// # Returns
+/// Result containing the lowercase (punycode) hostname
+fn normalize_hostname(hostname: &str) -> Result<String, std::io::Error> {
+    match Host::parse(hostname.trim().trim_end_matches('.')) {
+        Ok(Host::Domain(name)) if name.contains('.') => Ok(name),
+        _ => Err(FieldError::invalid(
+            "hostname",
+            "Domain must be a hostname such as go.example.com, without a scheme or port",
+        )),
+    }
+}
+
+/// Adds a custom domain for a user
+/// 
+/// A domain another user added but never verified, and that has no links, is
+/// handed over once it has been pending for `DOMAIN_CLAIM_DAYS`.
+/// 
+/// # Arguments
+/// * `user` - The user adding the domain
+/// * `hostname` - The hostname of the domain
+/// * `pool` - Database connection pool
+/// 
+/// # Returns
+/// Result containing the added domain
+pub async fn add_domain(
+    user: &User,
+    hostname: &str,
+    pool: &PgPool,
+) -> Result<Domain, std::io::Error> {
+    let hostname = normalize_hostname(hostname)?;
+    if is_app_hostname(&hostname) {
+        return Err(FieldError::invalid(
+            "hostname",
+            "This domain is already served by nurl",
+        ));
+    }
+
+    let now = Utc::now();
+    let mut tx = pool
+        .begin()
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+    sqlx::query(
+        r#"
+      DELETE FROM domains
+      WHERE hostname = $1
+          AND owner != $2
+          AND verified_at IS NULL
+          AND created_at < $3
+          AND NOT EXISTS(SELECT 1 FROM shortened_urls WHERE domain = $1)
+      "#,
+    )
+    .bind(&hostname)
+    .bind(user.id)
+    .bind(now - Duration::days(*DOMAIN_CLAIM_DAYS))
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| std::io::Error::other(e.to_string()))?;
+
+    let domain = sqlx::query_as::<_, Domain>(
+        r#"
+      INSERT INTO domains (id, hostname, owner, created_at)
+      VALUES ($1, $2, $3, $4)
+      ON CONFLICT (hostname) DO NOTHING
+      RETURNING *
+      "#,
+    )
+    .bind(Uuid::new_v4())
+    .bind(&hostname)
+    .bind(user.id)
+    .bind(now)
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|e| std::io::Error::other(e.to_string()))?
+    .ok_or(std::io::Error::new(
+        std::io::ErrorKind::AlreadyExists,
+        "This domain has already been added",
+    ))?;
+    tx.commit()
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+    Ok(domain)
+}
+
+/// Lists the custom domains of a user
+/// 
+/// # Arguments
+/// * `user` - The user whose domains to list
+/// * `pool` - Database connection pool
+/// 
+/// # Returns
+/// Result containing the domains, sorted by hostname
+pub async fn list_domains(user: &User, pool: &PgPool) -> Result<Vec<Domain>, std::io::Error> {
+    sqlx::query_as::<_, Domain>("SELECT * FROM domains WHERE owner = $1 ORDER BY hostname")
+        .bind(user.id)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))
+}
+
+/// Removes a custom domain of a user
+/// 
+/// Domains still serving links, including links in the trash, can't be removed.
+/// The short codes tombstoned on the domain are removed along with it.
+/// 
+/// # Arguments
+/// * `user` - The user owning the domain
+/// * `id` - The ID of the domain to remove
+/// * `pool` - Database connection pool
+/// 
+/// # Returns
+/// Result indicating success or failure
+pub async fn delete_domain(user: &User, id: &str, pool: &PgPool) -> Result<(), std::io::Error> {
+    let uuid = parse_uuid(id)?;
+
+    let mut tx = pool
+        .begin()
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+    let (hostname,): (String,) =
+        sqlx::query_as("SELECT hostname FROM domains WHERE id = $1 AND owner = $2 FOR UPDATE")
+            .bind(uuid)
+            .bind(user.id)
+            .fetch_optional(&mut *tx)
+            .await
+            .map_err(|e| std::io::Error::other(e.to_string()))?
+            .ok_or(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                "Domain not found or you don't have permission to remove it",
+            ))?;
+
+    let (in_use,): (bool,) =
+        sqlx::query_as("SELECT EXISTS(SELECT 1 FROM shortened_urls WHERE domain = $1)")
+            .bind(&hostname)
+            .fetch_one(&mut *tx)
+            .await
+            .map_err(|e| std::io::Error::other(e.to_string()))?;
+    if in_use {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "This domain still has links, delete or purge them before removing it",
+        ));
+    }
+
+    sqlx::query("DELETE FROM domains WHERE id = $1")
+        .bind(uuid)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+    tx.commit()
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))
+}
+
+/// Resolves the domain a user wants to create a link on
+/// 
+/// Links can only be created on custom domains that are verified.
+/// 
+/// # Arguments
+/// * `user` - The user creating the link
+/// * `domain` - The requested domain, `APP_DOMAIN` if `None`, empty or served by nurl itself
+/// * `executor` - Database connection or pool
+/// 
+/// # Returns
+/// Result containing the hostname of the custom domain, `None` for `APP_DOMAIN`
+pub(crate) async fn resolve_link_domain<'e>(
+    user: &User,
+    domain: Option<&str>,
+    executor: impl PgExecutor<'e>,
+) -> Result<Option<String>, std::io::Error> {
+    let Some(domain) = domain.map(str::trim).filter(|d| !d.is_empty()) else {
+        return Ok(None);
+    };
+    let hostname =
+        self::hostname(domain).ok_or_else(|| FieldError::invalid("domain", "Invalid domain"))?;
+    if is_app_hostname(&hostname) {
+        return Ok(None);
+    }
+
+    let verified_at: Option<(Option<DateTime<Utc>>,)> =
+        sqlx::query_as("SELECT verified_at FROM domains WHERE hostname = $1 AND owner = $2")
+            .bind(&hostname)
+            .bind(user.id)
+            .fetch_optional(executor)
+            .await
+            .map_err(|e| std::io::Error::other(e.to_string()))?;
+    match verified_at {
+        Some((Some(_),)) => Ok(Some(hostname)),
+        Some((None,)) => Err(FieldError::invalid(
+            "domain",
+            "This domain hasn't been verified yet",
+        )),
+        None => Err(FieldError::invalid(
+            "domain",
+            "This domain hasn't been added to your account",
+        )),
+    }
+}
+
+/// Lists the hostnames of every custom domain
+/// 
+/// # Arguments
+/// * `pool` - Database connection pool
+/// 
+/// # Returns
+/// Result containing the hostnames
+pub(crate) async fn custom_hostnames(pool: &PgPool) -> Result<Vec<String>, std::io::Error> {
+    sqlx::query_scalar("SELECT hostname FROM domains")
+        .fetch_all(pool)
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        service::{create_url, delete_url},
+        structs::ShortenedUrl,
+        trash::purge_url,
+        utils::{add_test_domain, create_test_user, delete_test_users, init_test_db},
+        workspaces::Member,
+    };
+
+    fn unique_hostname() -> String {
+        format!(
+            "go-{}.example.test",
+            &Uuid::new_v4().simple().to_string()[..8]
+        )
+    }
+
+    async fn create_on(
+        member: &Member,
+        slug: &str,
+        domain: Option<&str>,
+        pool: &PgPool,
+    ) -> Result<ShortenedUrl, std::io::Error> {
+        create_url(
+            member,
+            "https://example.com/",
+            Some(slug.to_string()),
+            domain,
+            None,
+            &[],
+            None,
+            pool,
+        )
+        .await
+    }
+
+    fn unique_slug() -> String {
+        format!("dom_{}", &Uuid::new_v4().simple().to_string()[..8])
+    }
+
+    /// Tests that added hostnames are normalized and listed
+    #[actix_rt::test]
+    async fn test_add_domain() {
+        let pool = init_test_db().await;
+        let user = create_test_user(&pool, "domains").await;
+        let hostname = unique_hostname();
+
+        let domain = add_domain(&user, &format!(" {}. ", hostname.to_uppercase()), &pool)
+            .await
+            .unwrap();
+        assert_eq!(domain.hostname, hostname);
+        assert_eq!(list_domains(&user, &pool).await.unwrap().len(), 1);
+
+        delete_test_users(&pool, &[user.id]).await;
+    }
+
+    /// Tests that a domain can only be added by one user
+    #[actix_rt::test]
+    async fn test_add_domain_taken() {
+        let pool = init_test_db().await;
+        let user = create_test_user(&pool, "domains_taken").await;
+        let other = create_test_user(&pool, "domains_other").await;
+        let domain = add_test_domain(&pool, &user, false).await;
+
+        let result = add_domain(&other, &domain.hostname, &pool).await;
+        assert!(result.is_err_and(|e| e.kind() == std::io::ErrorKind::AlreadyExists));
+
+        delete_test_users(&pool, &[user.id, other.id]).await;
+    }
+
+    /// Tests that another user can take over a domain left unverified past the claim period
+    #[actix_rt::test]
+    async fn test_add_domain_expired_claim() {
+        let pool = init_test_db().await;
+        let user = create_test_user(&pool, "domains_claim").await;
+        let other = create_test_user(&pool, "domains_claimer").await;
+        let pending = add_test_domain(&pool, &user, false).await;
+        let verified = add_test_domain(&pool, &user, true).await;
+        sqlx::query("UPDATE domains SET created_at = $1 WHERE owner = $2")
+            .bind(Utc::now() - Duration::days(*DOMAIN_CLAIM_DAYS + 1))
+            .bind(user.id)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let result = add_domain(&other, &verified.hostname, &pool).await;
+        assert!(result.is_err_and(|e| e.kind() == std::io::ErrorKind::AlreadyExists));
+
+        let claimed = add_domain(&other, &pending.hostname, &pool).await.unwrap();
+        assert_eq!(claimed.owner, other.id);
+        assert_eq!(list_domains(&user, &pool).await.unwrap().len(), 1);
+
+        delete_test_users(&pool, &[user.id, other.id]).await;
+    }
+
+    /// Tests that URLs, ports, single labels and the served domain are rejected
+    #[actix_rt::test]
+    async fn test_add_invalid_domain() {
+        let pool = init_test_db().await;
+        let user = create_test_user(&pool, "domains_invalid").await;
+
+        for invalid in [
+            "https://go.example.test",
+            "go.example.test:8080",
+            "localhost",
+            APP_DOMAIN.as_str(),
+        ] {
+            let result = add_domain(&user, invalid, &pool).await;
+            assert!(
+                result.is_err_and(|e| e.kind() == std::io::ErrorKind::InvalidInput),
+                "{}",
+                invalid
+            );
+        }
+
+        delete_test_users(&pool, &[user.id]).await;
+    }
+
+    /// Tests that links can only be created on a domain once it is verified
+    #[actix_rt::test]
+    async fn test_unverified_domain() {
+        let pool = init_test_db().await;
+        let user = create_test_user(&pool, "domains_unverified").await;
+        let domain = add_test_domain(&pool, &user, false).await;
+
+        let result = create_on(
+            &Member::personal(&user),
+            &unique_slug(),
+            Some(&domain.hostname),
+            &pool,
+        )
+        .await;
+        assert!(result.is_err_and(|e| e.kind() == std::io::ErrorKind::InvalidInput));
+
+        delete_test_users(&pool, &[user.id]).await;
+    }
+
+    /// Tests that short codes are unique per domain and links carry their full short URL
+    #[actix_rt::test]
+    async fn test_same_code_on_each_domain() {
+        let pool = init_test_db().await;
+        let user = create_test_user(&pool, "domains_codes").await;
+        let member = Member::personal(&user);
+        let hostname = add_test_domain(&pool, &user, true).await.hostname;
+        let slug = unique_slug();
+
+        let default = create_on(&member, &slug, None, &pool).await.unwrap();
+        assert_eq!(default.domain, None);
+        assert_eq!(default.link, short_link(None, &slug));
+
+        let branded = create_on(&member, &slug, Some(&hostname), &pool)
+            .await
+            .unwrap();
+        assert_eq!(branded.domain.as_deref(), Some(hostname.as_str()));
+        assert_eq!(
+            branded.link,
+            format!("{}://{}/{}", *LINK_SCHEME, hostname, slug)
+        );
+        assert!(create_on(&member, &slug, Some(&hostname), &pool)
+            .await
+            .is_err());
+
+        delete_test_users(&pool, &[user.id]).await;
+    }
+
+    /// Tests that other users can't create links on a domain
+    #[actix_rt::test]
+    async fn test_domain_of_other_user() {
+        let pool = init_test_db().await;
+        let user = create_test_user(&pool, "domains_owner").await;
+        let other = create_test_user(&pool, "domains_stranger").await;
+        let hostname = add_test_domain(&pool, &user, true).await.hostname;
+
+        let result = create_on(
+            &Member::personal(&other),
+            &unique_slug(),
+            Some(&hostname),
+            &pool,
+        )
+        .await;
+        assert!(result.is_err_and(|e| e.kind() == std::io::ErrorKind::InvalidInput));
+
+        delete_test_users(&pool, &[user.id, other.id]).await;
+    }
+
+    /// Tests that links can't point back at a custom domain
+    #[actix_rt::test]
+    async fn test_link_to_custom_domain() {
+        let pool = init_test_db().await;
+        let user = create_test_user(&pool, "domains_loop").await;
+        let hostname = add_test_domain(&pool, &user, true).await.hostname;
+
+        let result = create_url(
+            &Member::personal(&user),
+            &format!("https://{}/{}", hostname, unique_slug()),
+            None,
+            None,
+            None,
+            &[],
+            None,
+            &pool,
+        )
+        .await;
+        assert!(result.is_err_and(|e| e.kind() == std::io::ErrorKind::InvalidInput));
+
+        delete_test_users(&pool, &[user.id]).await;
+    }
+
+    /// Tests that a domain can only be removed by its owner once it has no links
+    #[actix_rt::test]
+    async fn test_delete_domain() {
+        let pool = init_test_db().await;
+        let user = create_test_user(&pool, "domains_delete").await;
+        let member = Member::personal(&user);
+        let other = create_test_user(&pool, "domains_delete_other").await;
+        let domain = add_test_domain(&pool, &user, true).await;
+        let id = domain.id.to_string();
+        let branded = create_on(&member, &unique_slug(), Some(&domain.hostname), &pool)
+            .await
+            .unwrap();
+
+        let result = delete_domain(&user, &id, &pool).await;
+        assert!(result.is_err_and(|e| e.kind() == std::io::ErrorKind::InvalidInput));
+        let result = delete_domain(&other, &id, &pool).await;
+        assert!(result.is_err_and(|e| e.kind() == std::io::ErrorKind::NotFound));
+
+        delete_url(&member, &branded.id.to_string(), &pool)
+            .await
+            .unwrap();
+        purge_url(&member, &branded.id.to_string(), &pool)
+            .await
+            .unwrap();
+        delete_domain(&user, &id, &pool).await.unwrap();
+        assert!(list_domains(&user, &pool).await.unwrap().is_empty());
+
+        delete_test_users(&pool, &[user.id, other.id]).await;
+    }
+}
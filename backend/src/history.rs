@@ -0,0 +1,400 @@
+use chrono::Utc;
+use sqlx::{types::Json, PgConnection, PgPool};
+use uuid::Uuid;
+
+use crate::{
+    metadata::spawn_metadata_refresh,
+    service::{
+        load_tags, lock_owned_url, parse_uuid, set_url_tags, validate_destination,
+        RESET_DESTINATION_STATE,
+    },
+    structs::{LinkRevision, LinkSnapshot, ShortenedUrl},
+    tombstones::ensure_claimable,
+    workspaces::{Member, WorkspaceRole},
+};
+
+/// What kind of change a revision records
+#[derive(Clone, Copy)]
+pub(crate) enum RevisionAction {
+    /// The URL was created
+    Create,
+    /// The URL was edited
+    Update,
+    /// The URL was changed by a bulk operation
+    Bulk,
+    /// The URL was restored to a previous revision
+    Restore,
+    /// The URL was transferred to another workspace
+    Transfer,
+}
+
+impl RevisionAction {
+    /// Returns the name the action is stored under
+    fn as_str(self) -> &'static str {
+        match self {
+            RevisionAction::Create => "create",
+            RevisionAction::Update => "update",
+            RevisionAction::Bulk => "bulk",
+            RevisionAction::Restore => "restore",
+            RevisionAction::Transfer => "transfer",
+        }
+    }
+}
+
+/// Records a change to a shortened URL in its revision history
+/// 
+/// Nothing is recorded if none of the destination, short code, expiry,
+/// enabled state, tags, notes or owner changed.
+/// 
+/// # Arguments
+/// * `changed_by` - The ID of the user who made the change
+/// * `action` - What kind of change was made
+/// * `old` - The URL before the change, `None` if it was just created
+/// * `new` - The URL after the change, with its tags loaded
+/// * `conn` - Database connection, within the transaction making the change
+/// 
+/// # Returns
+/// Result indicating success or failure
+pub(crate) async fn record_revision(
+    changed_by: Uuid,
+    action: RevisionAction,
+    old: Option<&ShortenedUrl>,
+    new: &ShortenedUrl,
+    conn: &mut PgConnection,
+) -> Result<(), std::io::Error> {
+    let old_values = old.map(LinkSnapshot::from);
+    let new_values = LinkSnapshot::from(new);
+    if old_values.as_ref() == Some(&new_values) {
+        return Ok(());
+    }
+
+    sqlx::query(
+        r#"
+      INSERT INTO link_revisions (id, link_id, changed_by, changed_at, action, old_values, new_values)
+      VALUES ($1, $2, $3, $4, $5, $6, $7)
+      "#,
+    )
+    .bind(Uuid::new_v4())
+    .bind(new.id)
+    .bind(changed_by)
+    .bind(Utc::now())
+    .bind(action.as_str())
+    .bind(old_values.map(Json))
+    .bind(Json(new_values))
+    .execute(conn)
+    .await
+    .map_err(|e| std::io::Error::other(e.to_string()))?;
+
+    Ok(())
+}
+
+/// Lists the revision history of a workspace's shortened URL, newest first
+/// 
+/// # Arguments
+/// * `member` - A member of the workspace owning the URL
+/// * `id` - The ID of the URL
+/// * `pool` - Database connection pool
+/// 
+/// # Returns
+/// Result containing the revisions of the URL
+pub async fn list_revisions(
+    member: &Member,
+    id: &str,
+    pool: &PgPool,
+) -> Result<Vec<LinkRevision>, std::io::Error> {
+    let uuid = parse_uuid(id)?;
+
+    let (owned,): (bool,) =
+        sqlx::query_as("SELECT EXISTS(SELECT 1 FROM shortened_urls WHERE id = $1 AND owner = $2)")
+            .bind(uuid)
+            .bind(member.workspace_id)
+            .fetch_one(pool)
+            .await
+            .map_err(|e| std::io::Error::other(e.to_string()))?;
+    if !owned {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            "URL not found or you don't have permission to view it",
+        ));
+    }
+
+    sqlx::query_as(
+        r#"
+      SELECT r.*, u.username AS changed_by_username
+      FROM link_revisions r
+      LEFT JOIN users u ON u.id = r.changed_by
+      WHERE r.link_id = $1
+      ORDER BY r.changed_at DESC
+      "#,
+    )
+    .bind(uuid)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| std::io::Error::other(e.to_string()))
+}
+
+/// Restores a workspace's shortened URL to the state recorded by one of its revisions
+/// 
+/// The destination, short code, expiry, enabled state, tags and notes are restored,
+/// and the restore itself is recorded as a new revision. The owner is never
+/// restored, since ownership only changes through transfers.
+/// 
+/// # Arguments
+/// * `member` - The member restoring the URL, at least an editor of the workspace owning it
+/// * `id` - The ID of the URL
+/// * `revision_id` - The ID of the revision to restore
+/// * `pool` - Database connection pool
+/// 
+/// # Returns
+/// Result containing the restored ShortenedUrl
+pub async fn restore_revision(
+    member: &Member,
+    id: &str,
+    revision_id: &str,
+    pool: &PgPool,
+) -> Result<ShortenedUrl, std::io::Error> {
+    member.require(WorkspaceRole::Editor)?;
+    let uuid = parse_uuid(id)?;
+    let revision_uuid = parse_uuid(revision_id)?;
+
+    let (Json(target),): (Json<LinkSnapshot>,) = sqlx::query_as(
+        r#"
+      SELECT r.new_values
+      FROM link_revisions r
+      JOIN shortened_urls s ON s.id = r.link_id
+      WHERE r.id = $1 AND r.link_id = $2 AND s.owner = $3
+      "#,
+    )
+    .bind(revision_uuid)
+    .bind(uuid)
+    .bind(member.workspace_id)
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| std::io::Error::other(e.to_string()))?
+    .ok_or(std::io::Error::new(
+        std::io::ErrorKind::NotFound,
+        "Revision not found or you don't have permission to restore it",
+    ))?;
+
+    // The destination may have been blocklisted since the revision was made
+    let original_url = validate_destination(&target.original_url, pool).await?;
+
+    let mut tx = pool
+        .begin()
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+    let previous = lock_owned_url(member.workspace_id, uuid, &mut tx).await?;
+
+    // Another URL may have taken the short code since
+    if previous.short_url != target.short_url {
+        let (taken,): (bool,) = sqlx::query_as(
+            "SELECT EXISTS(SELECT 1 FROM shortened_urls WHERE short_url = $1 AND domain IS NOT DISTINCT FROM $2 AND id <> $3)",
+        )
+        .bind(&target.short_url)
+        .bind(&previous.domain)
+        .bind(uuid)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+        if taken {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::AlreadyExists,
+                "The short URL of this revision is now used by another link",
+            ));
+        }
+        ensure_claimable(
+            &target.short_url,
+            previous.domain.as_deref(),
+            member.workspace_id,
+            &mut tx,
+        )
+        .await?;
+    }
+
+    let mut url = sqlx::query_as::<_, ShortenedUrl>(&format!(
+        r#"
+      UPDATE shortened_urls
+      SET
+          short_url = $1,
+          original_url = $2,
+          updated_at = $3,
+          expiry_date = $4,
+          enabled = $5,
+          notes = $6,
+          version = version + 1,
+          {}
+      WHERE id = $7
+      RETURNING *
+      "#,
+        RESET_DESTINATION_STATE
+    ))
+    .bind(&target.short_url)
+    .bind(&original_url)
+    .bind(Utc::now())
+    .bind(target.expiry_date)
+    .bind(target.enabled)
+    .bind(&target.notes)
+    .bind(uuid)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|e| std::io::Error::other(e.to_string()))?;
+
+    set_url_tags(member.workspace_id, uuid, &target.tags, &mut tx).await?;
+    load_tags(std::slice::from_mut(&mut url), &mut *tx).await?;
+    record_revision(
+        member.user.id,
+        RevisionAction::Restore,
+        Some(&previous),
+        &url,
+        &mut tx,
+    )
+    .await?;
+    tx.commit()
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+
+    // The metadata is cleared when the destination changes, so fetch it again
+    if url.metadata_fetched_at.is_none() {
+        spawn_metadata_refresh(url.id, url.original_url.clone(), pool.clone());
+    }
+
+    Ok(url)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        service::{create_url, update_url},
+        utils::{create_test_user, delete_test_users, init_test_db},
+    };
+
+    /// Creates a URL tagged `print` and updates it twice
+    /// 
+    /// The first update changes the destination and sets an expiry, the second
+    /// one clears the tags.
+    /// 
+    /// # Arguments
+    /// * `member` - The member creating and updating the URL
+    /// * `pool` - Database connection pool
+    /// 
+    /// # Returns
+    /// The ID and custom path of the URL
+    async fn create_revised_url(member: &Member, pool: &PgPool) -> (String, String) {
+        let slug = format!("hist_{}", &Uuid::new_v4().to_string()[..8]);
+        let url = create_url(
+            member,
+            "https://example.com/right",
+            Some(slug.clone()),
+            None,
+            None,
+            &["print".to_string()],
+            None,
+            pool,
+        )
+        .await
+        .unwrap();
+        let id = url.id.to_string();
+
+        update_url(
+            member,
+            &id,
+            pool,
+            "https://example.com/wrong",
+            Some(&slug),
+            Some(3600),
+            None,
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+        update_url(
+            member,
+            &id,
+            pool,
+            "https://example.com/wrong",
+            Some(&slug),
+            Some(3600),
+            Some(&[]),
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+        (id, slug)
+    }
+
+    /// Tests that every change is recorded with its author and old and new values
+    #[actix_rt::test]
+    async fn test_revisions_recorded() {
+        let pool = init_test_db().await;
+        let user = create_test_user(&pool, "history").await;
+        let member = Member::personal(&user);
+        let (id, _) = create_revised_url(&member, &pool).await;
+
+        let revisions = list_revisions(&member, &id, &pool).await.unwrap();
+        let actions: Vec<&str> = revisions.iter().map(|r| r.action.as_str()).collect();
+        assert_eq!(actions, vec!["update", "update", "create"]);
+        assert_eq!(
+            revisions[1].changed_by_username.as_deref(),
+            Some(user.username.as_str())
+        );
+        let first_update = &revisions[1];
+        let old = first_update.old_values.as_ref().unwrap();
+        assert_eq!(old.original_url, "https://example.com/right");
+        assert_eq!(
+            first_update.new_values.original_url,
+            "https://example.com/wrong"
+        );
+        assert!(first_update.new_values.expiry_date.is_some());
+        assert!(revisions[2].old_values.is_none());
+
+        delete_test_users(&pool, &[user.id]).await;
+    }
+
+    /// Tests that restoring a revision brings back its values and is recorded itself
+    #[actix_rt::test]
+    async fn test_restore_revision() {
+        let pool = init_test_db().await;
+        let user = create_test_user(&pool, "history_restore").await;
+        let member = Member::personal(&user);
+        let (id, slug) = create_revised_url(&member, &pool).await;
+
+        let revisions = list_revisions(&member, &id, &pool).await.unwrap();
+        let created = revisions[2].id.to_string();
+        let restored = restore_revision(&member, &id, &created, &pool)
+            .await
+            .unwrap();
+        assert_eq!(restored.original_url, "https://example.com/right");
+        assert_eq!(restored.short_url, slug);
+        assert_eq!(restored.expiry_date, None);
+        assert_eq!(restored.tags, vec!["print"]);
+
+        let revisions = list_revisions(&member, &id, &pool).await.unwrap();
+        assert_eq!(revisions.len(), 4);
+        assert_eq!(revisions[0].action, "restore");
+
+        delete_test_users(&pool, &[user.id]).await;
+    }
+
+    /// Tests that other workspaces can't see or restore the history
+    #[actix_rt::test]
+    async fn test_revisions_of_other_workspace() {
+        let pool = init_test_db().await;
+        let user = create_test_user(&pool, "history_owner").await;
+        let member = Member::personal(&user);
+        let other = create_test_user(&pool, "history_other").await;
+        let other_member = Member::personal(&other);
+        let (id, _) = create_revised_url(&member, &pool).await;
+
+        let revisions = list_revisions(&member, &id, &pool).await.unwrap();
+        let created = revisions[2].id.to_string();
+        let result = list_revisions(&other_member, &id, &pool).await;
+        assert!(result.is_err_and(|e| e.kind() == std::io::ErrorKind::NotFound));
+        let result = restore_revision(&other_member, &id, &created, &pool).await;
+        assert!(result.is_err_and(|e| e.kind() == std::io::ErrorKind::NotFound));
+
+        delete_test_users(&pool, &[user.id, other.id]).await;
+    }
+}
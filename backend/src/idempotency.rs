@@ -0,0 +1,329 @@
+use chrono::{DateTime, Duration, Utc};
+use serde_json::{json, Value};
+use sqlx::{types::Json, PgPool};
+
+use crate::{
+    constants::{IDEMPOTENCY_KEY_HOURS, IDEMPOTENCY_PURGE_INTERVAL},
+    metadata::spawn_metadata_refresh,
+    service::{insert_prepared_url, prepare_url},
+    structs::ShortenedUrl,
+    workspaces::Member,
+};
+
+/// The maximum length (in characters) of an Idempotency-Key
+const MAX_IDEMPOTENCY_KEY_LENGTH: usize = 255;
+
+/// Returns how long Idempotency-Keys are remembered
+fn key_lifetime() -> Duration {
+    Duration::hours(*IDEMPOTENCY_KEY_HOURS)
+}
+
+/// The result of creating a shortened URL with an Idempotency-Key
+pub enum IdempotentCreation {
+    /// The key was new and the URL was created
+    Created(Box<ShortenedUrl>),
+    /// The key was already used with the same request, holding the URL it created
+    Replayed(Value),
+}
+
+/// Creates a new shortened URL unless the same request was already made with the same key
+/// 
+/// The first request made with a key creates the URL and remembers the
+/// response for `IDEMPOTENCY_KEY_HOURS`. Repeating the request with the same
+/// key returns that response instead of creating another URL, while reusing
+/// the key for a different request fails with `InvalidData`. Keys of requests
+/// that failed aren't remembered, so those requests can be retried.
+/// 
+/// # Arguments
+/// * `member` - The member creating the URL, whose user the key belongs to
+/// * `key` - The Idempotency-Key sent with the request
+/// * `original_url` - The original URL to shorten
+/// * `custom_url` - Optional custom short URL
+/// * `domain` - Optional custom domain to serve the URL on, `APP_DOMAIN` if `None`
+/// * `expiration_sec` - Optional number of seconds until expiration
+/// * `tags` - Tags to attach to the URL
+/// * `notes` - Optional notes about the URL
+/// * `pool` - Database connection pool
+/// 
+/// # Returns
+/// Result containing the created URL or the response to replay
+#[allow(clippy::too_many_arguments)]
+pub async fn create_url_idempotent(
+    member: &Member,
+    key: &str,
+    original_url: &str,
+    custom_url: Option<String>,
+    domain: Option<&str>,
+    expiration_sec: Option<i64>,
+    tags: &[String],
+    notes: Option<&str>,
+    pool: &PgPool,
+) -> Result<IdempotentCreation, std::io::Error> {
+    if key.is_empty() || key.len() > MAX_IDEMPOTENCY_KEY_LENGTH {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!(
+                "Idempotency-Key must be between 1 and {} characters",
+                MAX_IDEMPOTENCY_KEY_LENGTH
+            ),
+        ));
+    }
+
+    let request = json!({
+        "workspace": member.workspace_id,
+        "original_url": original_url,
+        "custom_path": custom_url,
+        "domain": domain,
+        "expiration": expiration_sec,
+        "tags": tags,
+        "notes": notes,
+    });
+    let now = Utc::now();
+
+    // Claim the key, waiting for any request in progress with the same key to finish
+    let mut tx = pool
+        .begin()
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+    let claimed = sqlx::query(
+        r#"
+      INSERT INTO idempotency_keys AS k (owner, idempotency_key, request, created_at)
+      VALUES ($1, $2, $3, $4)
+      ON CONFLICT (owner, idempotency_key) DO UPDATE SET
+          request = EXCLUDED.request,
+          response = NULL,
+          created_at = EXCLUDED.created_at
+      WHERE k.created_at <= $5
+      "#,
+    )
+    .bind(member.user.id)
+    .bind(key)
+    .bind(Json(&request))
+    .bind(now)
+    .bind(now - key_lifetime())
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| std::io::Error::other(e.to_string()))?
+    .rows_affected()
+        > 0;
+
+    if !claimed {
+        let (Json(previous), response): (Json<Value>, Option<Json<Value>>) = sqlx::query_as(
+            "SELECT request, response FROM idempotency_keys WHERE owner = $1 AND idempotency_key = $2",
+        )
+        .bind(member.user.id)
+        .bind(key)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+
+        if previous != request {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "This Idempotency-Key was already used with a different request",
+            ));
+        }
+        return response
+            .map(|Json(response)| IdempotentCreation::Replayed(response))
+            .ok_or(std::io::Error::other(
+                "The request with this Idempotency-Key has no recorded response",
+            ));
+    }
+
+    let short_url = prepare_url(
+        member,
+        original_url,
+        custom_url,
+        domain,
+        expiration_sec,
+        tags,
+        notes,
+        pool,
+    )
+    .await?;
+    insert_prepared_url(&short_url, member.user.id, &mut tx).await?;
+    sqlx::query(
+        "UPDATE idempotency_keys SET response = $1 WHERE owner = $2 AND idempotency_key = $3",
+    )
+    .bind(Json(&short_url))
+    .bind(member.user.id)
+    .bind(key)
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| std::io::Error::other(e.to_string()))?;
+    tx.commit()
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+
+    // Fetch the destination's title, description and images in the background
+    spawn_metadata_refresh(short_url.id, short_url.original_url.clone(), pool.clone());
+
+    Ok(IdempotentCreation::Created(Box::new(short_url)))
+}
+
+/// Removes the Idempotency-Keys that are no longer remembered
+/// 
+/// # Arguments
+/// * `now` - The current time
+/// * `pool` - Database connection pool
+/// 
+/// # Returns
+/// Result containing the number of removed keys
+pub(crate) async fn purge_expired_keys(
+    now: DateTime<Utc>,
+    pool: &PgPool,
+) -> Result<u64, std::io::Error> {
+    sqlx::query("DELETE FROM idempotency_keys WHERE created_at <= $1")
+        .bind(now - key_lifetime())
+        .execute(pool)
+        .await
+        .map(|r| r.rows_affected())
+        .map_err(|e| std::io::Error::other(e.to_string()))
+}
+
+/// Starts the background task that periodically removes forgotten Idempotency-Keys
+/// 
+/// Nothing is started if `IDEMPOTENCY_PURGE_INTERVAL` is 0.
+/// 
+/// # Arguments
+/// * `pool` - Database connection pool
+pub(crate) fn spawn_idempotency_key_purger(pool: PgPool) {
+    if *IDEMPOTENCY_PURGE_INTERVAL == 0 {
+        return;
+    }
+
+    tokio::spawn(async move {
+        let mut interval =
+            tokio::time::interval(std::time::Duration::from_secs(*IDEMPOTENCY_PURGE_INTERVAL));
+        loop {
+            interval.tick().await;
+            if let Err(e) = purge_expired_keys(Utc::now(), &pool).await {
+                println!("Idempotency key purge failed: {}", e);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        structs::User,
+        utils::{create_test_user, delete_test_users, init_test_db},
+    };
+
+    async fn create(
+        member: &Member,
+        key: &str,
+        original_url: &str,
+        pool: &PgPool,
+    ) -> Result<IdempotentCreation, std::io::Error> {
+        create_url_idempotent(member, key, original_url, None, None, None, &[], None, pool).await
+    }
+
+    /// Makes the keys of a user older than the given age
+    async fn age_keys(user: &User, age: Duration, pool: &PgPool) {
+        sqlx::query("UPDATE idempotency_keys SET created_at = $1 WHERE owner = $2")
+            .bind(Utc::now() - age)
+            .bind(user.id)
+            .execute(pool)
+            .await
+            .unwrap();
+    }
+
+    /// Tests that retrying with the same key returns the first response without creating another URL
+    #[actix_rt::test]
+    async fn test_idempotent_retry() {
+        let pool = init_test_db().await;
+        let user = create_test_user(&pool, "idempotency").await;
+        let member = Member::personal(&user);
+
+        let Ok(IdempotentCreation::Created(url)) =
+            create(&member, "retry", "https://example.com/", &pool).await
+        else {
+            panic!("the first request should create the URL");
+        };
+        let Ok(IdempotentCreation::Replayed(replayed)) =
+            create(&member, "retry", "https://example.com/", &pool).await
+        else {
+            panic!("the retry should replay the response");
+        };
+        assert_eq!(replayed["id"], url.id.to_string());
+        assert_eq!(replayed["short_url"], url.short_url);
+
+        let (count,): (i64,) =
+            sqlx::query_as("SELECT COUNT(*) FROM shortened_urls WHERE owner = $1")
+                .bind(user.id)
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert_eq!(count, 1);
+
+        delete_test_users(&pool, &[user.id]).await;
+    }
+
+    /// Tests that reusing a key for a different request fails
+    #[actix_rt::test]
+    async fn test_idempotency_key_reused() {
+        let pool = init_test_db().await;
+        let user = create_test_user(&pool, "idempotency_reuse").await;
+        let member = Member::personal(&user);
+
+        create(&member, "retry", "https://example.com/", &pool)
+            .await
+            .unwrap();
+        let result = create(&member, "retry", "https://example.com/other", &pool).await;
+        assert!(result.is_err_and(|e| e.kind() == std::io::ErrorKind::InvalidData));
+
+        delete_test_users(&pool, &[user.id]).await;
+    }
+
+    /// Tests that a forgotten key creates a new URL
+    #[actix_rt::test]
+    async fn test_forgotten_idempotency_key() {
+        let pool = init_test_db().await;
+        let user = create_test_user(&pool, "idempotency_forgotten").await;
+        let member = Member::personal(&user);
+
+        let Ok(IdempotentCreation::Created(url)) =
+            create(&member, "retry", "https://example.com/", &pool).await
+        else {
+            panic!("the first request should create the URL");
+        };
+        age_keys(&user, key_lifetime(), &pool).await;
+
+        let Ok(IdempotentCreation::Created(recreated)) =
+            create(&member, "retry", "https://example.com/other", &pool).await
+        else {
+            panic!("a forgotten key should create a new URL");
+        };
+        assert_ne!(recreated.id, url.id);
+
+        delete_test_users(&pool, &[user.id]).await;
+    }
+
+    /// Tests that keys past their lifetime are purged
+    #[actix_rt::test]
+    async fn test_purge_expired_keys() {
+        let pool = init_test_db().await;
+        let user = create_test_user(&pool, "idempotency_purge").await;
+        let member = Member::personal(&user);
+
+        create(&member, "retry", "https://example.com/", &pool)
+            .await
+            .unwrap();
+        age_keys(&user, key_lifetime() + Duration::minutes(1), &pool).await;
+
+        let purged = purge_expired_keys(Utc::now(), &pool).await.unwrap();
+        assert!(purged >= 1);
+        let (remaining,): (i64,) =
+            sqlx::query_as("SELECT COUNT(*) FROM idempotency_keys WHERE owner = $1")
+                .bind(user.id)
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert_eq!(remaining, 0);
+
+        delete_test_users(&pool, &[user.id]).await;
+    }
+}
@@ -0,0 +1,473 @@
+use chrono::Utc;
+use futures::{stream, StreamExt};
+use reqwest::Method;
+use sqlx::PgPool;
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+use tokio::sync::Mutex;
+use url::Url;
+use uuid::Uuid;
+
+use crate::{
+    constants::{
+        LINK_CHECK_ALLOW_PRIVATE, LINK_CHECK_CONCURRENCY, LINK_CHECK_HOST_DELAY,
+        LINK_CHECK_INTERVAL, LINK_CHECK_TIMEOUT,
+    },
+    metadata::{send_guarded, GuardedRequest},
+};
+
+/// The maximum number of redirects followed when checking a destination
+const MAX_REDIRECTS: usize = 10;
+
+/// Settings controlling how destinations are health checked
+pub(crate) struct CheckerConfig {
+    /// The maximum number of destinations checked at the same time
+    pub concurrency: usize,
+    /// How long a single request may take
+    pub timeout: Duration,
+    /// The minimum delay between two requests to the same host
+    pub host_delay: Duration,
+    /// Whether private, loopback and link-local addresses may be requested
+    pub allow_private: bool,
+}
+
+impl CheckerConfig {
+    /// Builds the checker settings from the environment
+    pub fn from_env() -> Self {
+        Self {
+            concurrency: (*LINK_CHECK_CONCURRENCY).max(1),
+            timeout: Duration::from_secs(*LINK_CHECK_TIMEOUT),
+            host_delay: Duration::from_millis(*LINK_CHECK_HOST_DELAY),
+            allow_private: *LINK_CHECK_ALLOW_PRIVATE,
+        }
+    }
+}
+
+/// The outcome of health checking a single destination
+#[derive(Debug)]
+pub(crate) struct CheckResult {
+    /// The HTTP status code of the final response
+    pub status_code: Option<i32>,
+    /// The URL of the final response after following redirects
+    pub final_url: Option<String>,
+    /// The error that prevented getting a response
+    pub error: Option<String>,
+}
+
+impl CheckResult {
+    /// Whether the destination should be flagged as broken
+    pub fn is_broken(&self) -> bool {
+        self.error.is_some() || self.status_code.is_none_or(|code| code >= 400)
+    }
+}
+
+/// Spaces out requests to the same host
+/// 
+/// Each host gets a reserved time slot; callers sleep until their slot so
+/// that no two requests to a host are closer together than the delay.
+struct HostRateLimiter {
+    /// The minimum delay between two requests to the same host
+    delay: Duration,
+    /// The last reserved time slot for each host
+    next_slot: Mutex<HashMap<String, Instant>>,
+}
+
+impl HostRateLimiter {
+    fn new(delay: Duration) -> Self {
+        Self {
+            delay,
+            next_slot: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Waits until a request to the given host is allowed
+    /// 
+    /// # Arguments
+    /// * `host` - The host about to be requested
+    async fn wait(&self, host: &str) {
+        let slot = {
+            let mut slots = self.next_slot.lock().await;
+            let now = Instant::now();
+            let slot = slots
+                .get(host)
+                .map(|last| (*last + self.delay).max(now))
+                .unwrap_or(now);
+            slots.insert(host.to_string(), slot);
+            slot
+        };
+        tokio::time::sleep_until(slot.into()).await;
+    }
+}
+
+/// Health checks a single destination
+/// 
+/// A HEAD request is tried first. If it fails or returns an error status,
+/// the destination is requested again with GET since many servers don't
+/// implement HEAD properly. Every hop is checked against the private address
+/// rules of the metadata fetcher, so links can't be used to probe internal hosts.
+/// 
+/// # Arguments
+/// * `url` - The destination to check
+/// * `config` - Settings controlling how destinations are checked
+/// 
+/// # Returns
+/// The outcome of the check
+pub(crate) async fn check_destination(url: &str, config: &CheckerConfig) -> CheckResult {
+    let request = GuardedRequest {
+        timeout: config.timeout,
+        allow_private: config.allow_private,
+        max_redirects: MAX_REDIRECTS,
+        user_agent: concat!("nurl-link-checker/", env!("CARGO_PKG_VERSION")),
+        accept: None,
+    };
+    let send = |method| async {
+        tokio::time::timeout(config.timeout, send_guarded(method, url, &request))
+            .await
+            .map_err(|_| std::io::Error::new(std::io::ErrorKind::TimedOut, "Check timed out"))?
+    };
+
+    if let Ok(response) = send(Method::HEAD).await
+        && response.status().as_u16() < 400
+    {
+        return CheckResult {
+            status_code: Some(response.status().as_u16() as i32),
+            final_url: Some(response.url().to_string()),
+            error: None,
+        };
+    }
+
+    match send(Method::GET).await {
+        Ok(response) => CheckResult {
+            status_code: Some(response.status().as_u16() as i32),
+            final_url: Some(response.url().to_string()),
+            error: None,
+        },
+        Err(e) => CheckResult {
+            status_code: None,
+            final_url: None,
+            error: Some(e.to_string()),
+        },
+    }
+}
+
+/// Stores the outcome of a health check on a link
+/// 
+/// # Arguments
+/// * `id` - The ID of the checked link
+/// * `result` - The outcome of the check
+/// * `pool` - Database connection pool
+/// 
+/// # Returns
+/// Result indicating success or failure
+async fn record_result(
+    id: Uuid,
+    result: &CheckResult,
+    pool: &PgPool,
+) -> Result<(), std::io::Error> {
+    sqlx::query(
+        r#"
+      UPDATE shortened_urls
+      SET
+          last_status_code = $1,
+          final_url = $2,
+          last_check_error = $3,
+          last_checked_at = $4,
+          is_broken = $5
+      WHERE id = $6
+      "#,
+    )
+    .bind(result.status_code)
+    .bind(&result.final_url)
+    .bind(&result.error)
+    .bind(Utc::now())
+    .bind(result.is_broken())
+    .bind(id)
+    .execute(pool)
+    .await
+    .map_err(|e| std::io::Error::other(e.to_string()))?;
+
+    Ok(())
+}
+
+/// Health checks the given links and records the outcomes
+/// 
+/// # Arguments
+/// * `links` - The IDs and destinations of the links to check
+/// * `config` - Settings controlling how destinations are checked
+/// * `pool` - Database connection pool
+/// 
+/// # Returns
+/// Result containing the number of links found broken
+pub(crate) async fn check_links(
+    links: Vec<(Uuid, String)>,
+    config: &CheckerConfig,
+    pool: &PgPool,
+) -> Result<usize, std::io::Error> {
+    let limiter = HostRateLimiter::new(config.host_delay);
+
+    let results: Vec<Result<bool, std::io::Error>> = stream::iter(links)
+        .map(|(id, url)| {
+            let limiter = &limiter;
+            async move {
+                let host = Url::parse(&url)
+                    .ok()
+                    .and_then(|u| u.host_str().map(|h| h.to_string()))
+                    .unwrap_or_default();
+                limiter.wait(&host).await;
+
+                let result = check_destination(&url, config).await;
+                record_result(id, &result, pool).await?;
+                Ok(result.is_broken())
+            }
+        })
+        .buffer_unordered(config.concurrency)
+        .collect()
+        .await;
+
+    let mut broken = 0;
+    for result in results {
+        if result? {
+            broken += 1;
+        }
+    }
+    Ok(broken)
+}
+
+/// Health checks every active (non-expired, non-trashed) link
+/// 
+/// # Arguments
+/// * `config` - Settings controlling how destinations are checked
+/// * `pool` - Database connection pool
+/// 
+/// # Returns
+/// Result containing the number of links found broken
+pub(crate) async fn check_active_links(
+    config: &CheckerConfig,
+    pool: &PgPool,
+) -> Result<usize, std::io::Error> {
+    let links: Vec<(Uuid, String)> = sqlx::query_as(
+        "SELECT id, original_url FROM shortened_urls WHERE deleted_at IS NULL AND (expiry_date IS NULL OR expiry_date > NOW())",
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| std::io::Error::other(e.to_string()))?;
+
+    check_links(links, config, pool).await
+}
+
+/// Starts the background task that periodically health checks active links
+/// 
+/// The first check runs one interval after startup. Nothing is started if
+/// `LINK_CHECK_INTERVAL` is 0.
+/// 
+/// # Arguments
+/// * `pool` - Database connection pool
+pub(crate) fn spawn_link_checker(pool: PgPool) {
+    if *LINK_CHECK_INTERVAL == 0 {
+        return;
+    }
+
+    tokio::spawn(async move {
+        let config = CheckerConfig::from_env();
+        let period = Duration::from_secs(*LINK_CHECK_INTERVAL);
+        let mut interval = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+        loop {
+            interval.tick().await;
+            match check_active_links(&config, &pool).await {
+                Ok(broken) => println!("Link health check finished, {} broken links", broken),
+                Err(e) => println!("Link health check failed: {}", e),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::{create_test_user, delete_test_users, init_test_db};
+    use actix_web::{web, App, HttpResponse, HttpServer};
+
+    /// Starts a local stand-in for destination servers
+    /// 
+    /// Routes:
+    /// - `/ok` returns 200
+    /// - `/missing` returns 404
+    /// - `/moved` redirects to `/ok`
+    /// - `/no-head` returns 405 for HEAD and 200 for GET
+    /// 
+    /// # Returns
+    /// The base URL of the server
+    fn start_destination_server() -> String {
+        let server = HttpServer::new(|| {
+            App::new()
+                .route("/ok", web::route().to(HttpResponse::Ok))
+                .route("/missing", web::route().to(HttpResponse::NotFound))
+                .route(
+                    "/moved",
+                    web::route().to(|| async {
+                        HttpResponse::Found()
+                            .append_header(("Location", "/ok"))
+                            .finish()
+                    }),
+                )
+                .route("/no-head", web::head().to(HttpResponse::MethodNotAllowed))
+                .route("/no-head", web::get().to(HttpResponse::Ok))
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .expect("Failed to bind destination server");
+
+        let addr = server.addrs()[0];
+        actix_rt::spawn(server.run());
+        format!("http://{}", addr)
+    }
+
+    fn test_config() -> CheckerConfig {
+        CheckerConfig {
+            concurrency: 4,
+            timeout: Duration::from_secs(5),
+            host_delay: Duration::from_millis(10),
+            allow_private: true,
+        }
+    }
+
+    /// Tests that a destination answering with 200 is healthy
+    #[actix_rt::test]
+    async fn test_check_destination_ok() {
+        let base = start_destination_server();
+
+        let result = check_destination(&format!("{}/ok", base), &test_config()).await;
+        assert_eq!(result.status_code, Some(200));
+        assert!(!result.is_broken());
+    }
+
+    /// Tests that a destination answering with 404 is broken
+    #[actix_rt::test]
+    async fn test_check_destination_missing() {
+        let base = start_destination_server();
+
+        let result = check_destination(&format!("{}/missing", base), &test_config()).await;
+        assert_eq!(result.status_code, Some(404));
+        assert!(result.is_broken());
+    }
+
+    /// Tests that redirects are followed and the final URL is recorded
+    #[actix_rt::test]
+    async fn test_check_destination_follows_redirects() {
+        let base = start_destination_server();
+
+        let result = check_destination(&format!("{}/moved", base), &test_config()).await;
+        assert_eq!(result.status_code, Some(200));
+        assert_eq!(result.final_url, Some(format!("{}/ok", base)));
+    }
+
+    /// Tests that a GET request is made when the server doesn't support HEAD
+    #[actix_rt::test]
+    async fn test_check_destination_falls_back_to_get() {
+        let base = start_destination_server();
+
+        let result = check_destination(&format!("{}/no-head", base), &test_config()).await;
+        assert_eq!(result.status_code, Some(200));
+        assert!(!result.is_broken());
+    }
+
+    /// Tests that an unreachable destination is broken and keeps the error
+    #[actix_rt::test]
+    async fn test_check_destination_unreachable() {
+        // Nothing listens on port 9 of the loopback address
+        let result = check_destination("http://127.0.0.1:9/", &test_config()).await;
+        assert!(result.status_code.is_none());
+        assert!(result.error.is_some());
+        assert!(result.is_broken());
+    }
+
+    /// Tests that destinations on private addresses, or redirecting to them, aren't requested
+    #[actix_rt::test]
+    async fn test_check_destination_refuses_private_addresses() {
+        let base = start_destination_server();
+        let config = CheckerConfig {
+            allow_private: false,
+            ..test_config()
+        };
+
+        for url in [
+            format!("{}/ok", base),
+            "http://169.254.169.254/latest/meta-data/".to_string(),
+            "http://[::ffff:10.0.0.1]/".to_string(),
+        ] {
+            let result = check_destination(&url, &config).await;
+            assert!(result.status_code.is_none(), "{} was requested", url);
+            assert!(result.error.is_some_and(|e| e.contains("private")));
+        }
+    }
+
+    /// Tests that checking links records the outcome on each link
+    #[actix_rt::test]
+    async fn test_check_links_records_results() {
+        let base = start_destination_server();
+        let pool = init_test_db().await;
+        let test_user = create_test_user(&pool, "link_checker").await;
+
+        let ok_id = Uuid::new_v4();
+        let missing_id = Uuid::new_v4();
+        for (id, path) in [(ok_id, "ok"), (missing_id, "missing")] {
+            sqlx::query(
+                "INSERT INTO shortened_urls (id, short_url, original_url, redirects, created_at, updated_at, owner)
+               VALUES ($1, $2, $3, 0, CURRENT_TIMESTAMP, CURRENT_TIMESTAMP, $4)",
+            )
+            .bind(id)
+            .bind(format!("check_{}", id))
+            .bind(format!("{}/{}", base, path))
+            .bind(test_user.id)
+            .execute(&pool)
+            .await
+            .expect("Failed to insert test data");
+        }
+
+        let links = vec![
+            (ok_id, format!("{}/ok", base)),
+            (missing_id, format!("{}/missing", base)),
+        ];
+        let broken = check_links(links, &test_config(), &pool).await.unwrap();
+        assert_eq!(broken, 1);
+
+        let rows: Vec<(Uuid, Option<i32>, bool, bool)> = sqlx::query_as(
+            "SELECT id, last_status_code, is_broken, last_checked_at IS NOT NULL FROM shortened_urls WHERE id = ANY($1)",
+        )
+        .bind(vec![ok_id, missing_id])
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+
+        assert_eq!(rows.len(), 2);
+        for (id, status, is_broken, checked) in rows {
+            assert!(checked);
+            if id == ok_id {
+                assert_eq!(status, Some(200));
+                assert!(!is_broken);
+            } else {
+                assert_eq!(status, Some(404));
+                assert!(is_broken);
+            }
+        }
+
+        delete_test_users(&pool, &[test_user.id]).await;
+    }
+
+    /// Tests that requests to the same host are spaced out
+    #[actix_rt::test]
+    async fn test_host_rate_limiter() {
+        let limiter = HostRateLimiter::new(Duration::from_millis(100));
+        let start = Instant::now();
+
+        limiter.wait("example.com").await;
+        limiter.wait("other.example").await;
+        assert!(start.elapsed() < Duration::from_millis(100));
+
+        limiter.wait("example.com").await;
+        limiter.wait("example.com").await;
+        assert!(start.elapsed() >= Duration::from_millis(200));
+    }
+}
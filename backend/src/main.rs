@@ -1,23 +1,57 @@
 /// Module declarations for the application
+mod account;
+mod api_keys;
+mod blocklist;
+mod bulk;
 mod constants;
+mod domain_verification;
+mod domains;
+mod history;
+mod idempotency;
+mod link_checker;
+mod metadata;
 mod middleware;
 mod routes;
 mod service;
+mod sessions;
 mod structs;
+mod tombstones;
+mod transfers;
+mod trash;
 mod utils;
+mod workspaces;
 use actix_cors::Cors;
 use actix_files as fs;
 use actix_web::{get, web, App, HttpResponse, HttpServer, Responder};
 use constants::{FRONTEND_DIST, HOST, PORT};
 use dotenv::dotenv;
 use middleware::ExtractUsernameJWT;
-use routes::auth::is_authenticated;
+use routes::account::{get_account, update_account, update_password};
+use routes::admin::{get_blocklist_matches, reload_blocklist};
+use routes::api_keys::{create_new_api_key, get_api_keys, remove_api_key};
+use routes::auth::{is_authenticated, logout, refresh};
+use routes::domains::{create_domain, get_domains, remove_domain, verify_custom_domain};
 use routes::redirect::redirect_to_original_url;
 use routes::register::register;
+use routes::sessions::{get_sessions, remove_other_sessions, remove_session};
 use routes::shorten::{
-    delete_shortened_url, get_shortened_urls, shorten_url, update_shortened_url,
+    bulk_shorten_urls, bulk_update_shortened_urls, delete_shortened_url, get_shortened_url_history,
+    get_shortened_urls, patch_shortened_url, refresh_shortened_url_metadata,
+    restore_shortened_url_revision, set_shortened_url_enabled, shorten_url, update_shortened_url,
+};
+use routes::tags::{get_tags, merge_tags_into, rename_tag};
+use routes::transfers::{
+    accept_link_transfer, cancel_shortened_url_transfer, decline_link_transfer, get_transfers,
+    offer_shortened_url_transfer,
+};
+use routes::trash::{get_trash, purge_trashed_url, restore_trashed_url};
+use routes::workspaces::{
+    accept_workspace_invitation, create_new_workspace, decline_workspace_invitation,
+    get_invitations, get_workspace_members, get_workspaces, invite_workspace_member,
+    remove_workspace, remove_workspace_member, set_workspace_member_role,
 };
 use routes::{auth::login, health::health};
+use std::sync::Arc;
 use utils::{init_db, is_production};
 
 /// Development mode endpoint that informs users about the separate frontend application
@@ -31,7 +65,7 @@ async fn development() -> impl Responder {
 async fn serve_index() -> impl Responder {
     let path = format!(
         "{}/client/index.html",
-        FRONTEND_DIST.to_string_lossy().to_string(),
+        FRONTEND_DIST.to_string_lossy(),
     );
     fs::NamedFile::open(path)
 }
@@ -41,7 +75,7 @@ async fn serve_index() -> impl Responder {
 async fn serve_auth() -> impl Responder {
     let path = format!(
         "{}/client/auth/index.html",
-        FRONTEND_DIST.to_string_lossy().to_string(),
+        FRONTEND_DIST.to_string_lossy(),
     );
     fs::NamedFile::open(path)
 }
@@ -51,7 +85,7 @@ async fn serve_auth() -> impl Responder {
 async fn serve_auth_register() -> impl Responder {
     let path = format!(
         "{}/client/auth/register/index.html",
-        FRONTEND_DIST.to_string_lossy().to_string(),
+        FRONTEND_DIST.to_string_lossy(),
     );
     fs::NamedFile::open(path)
 }
@@ -62,7 +96,7 @@ async fn serve_assets(path: actix_web::web::Path<String>) -> impl Responder {
     let filename = path.into_inner();
     let path = format!(
         "{}/client/assets/{}",
-        FRONTEND_DIST.to_string_lossy().to_string(),
+        FRONTEND_DIST.to_string_lossy(),
         filename
     );
     fs::NamedFile::open(path)
@@ -73,15 +107,25 @@ async fn serve_assets(path: actix_web::web::Path<String>) -> impl Responder {
 /// This function:
 /// 1. Initializes environment variables
 /// 2. Sets up the database connection pool
-/// 3. Configures CORS settings
-/// 4. Sets up the HTTP server with all routes
-/// 5. Handles static file serving in production mode
-/// 6. Starts the server on the configured host and port
+/// 3. Starts the background tasks (blocklist reloading, link health checks,
+///    domain re-verification)
+/// 4. Configures CORS settings
+/// 5. Sets up the HTTP server with all routes
+/// 6. Handles static file serving in production mode
+/// 7. Starts the server on the configured host and port
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     dotenv().ok();
 
-    let pool = init_db().await.map(|p| web::Data::new(p))?;
+    let pool = init_db().await.map(web::Data::new)?;
+
+    blocklist::spawn_reloader().await;
+    link_checker::spawn_link_checker(pool.get_ref().clone());
+    trash::spawn_trash_purger(pool.get_ref().clone());
+    idempotency::spawn_idempotency_key_purger(pool.get_ref().clone());
+
+    let resolver = Arc::new(domain_verification::SystemResolver::new()?);
+    domain_verification::spawn_domain_verifier(resolver.clone(), pool.get_ref().clone());
 
     let server = HttpServer::new(move || {
         let cors = Cors::default()
@@ -97,18 +141,64 @@ async fn main() -> std::io::Result<()> {
                 web::scope("/api")
                     .service(register)
                     .service(login)
+                    .service(refresh)
+                    .service(logout)
                     .service(is_authenticated)
                     .service(
                         web::scope("")
                             .wrap(ExtractUsernameJWT)
                             .service(shorten_url)
+                            .service(bulk_shorten_urls)
+                            .service(bulk_update_shortened_urls)
                             .service(delete_shortened_url)
                             .service(get_shortened_urls)
-                            .service(update_shortened_url),
+                            .service(update_shortened_url)
+                            .service(patch_shortened_url)
+                            .service(refresh_shortened_url_metadata)
+                            .service(set_shortened_url_enabled)
+                            .service(get_shortened_url_history)
+                            .service(restore_shortened_url_revision)
+                            .service(offer_shortened_url_transfer)
+                            .service(cancel_shortened_url_transfer)
+                            .service(get_transfers)
+                            .service(accept_link_transfer)
+                            .service(decline_link_transfer)
+                            .service(create_domain)
+                            .service(get_domains)
+                            .service(remove_domain)
+                            .service(verify_custom_domain)
+                            .service(get_tags)
+                            .service(merge_tags_into)
+                            .service(rename_tag)
+                            .service(get_trash)
+                            .service(restore_trashed_url)
+                            .service(purge_trashed_url)
+                            .service(create_new_workspace)
+                            .service(get_workspaces)
+                            .service(remove_workspace)
+                            .service(get_workspace_members)
+                            .service(set_workspace_member_role)
+                            .service(remove_workspace_member)
+                            .service(invite_workspace_member)
+                            .service(get_invitations)
+                            .service(accept_workspace_invitation)
+                            .service(decline_workspace_invitation)
+                            .service(create_new_api_key)
+                            .service(get_api_keys)
+                            .service(remove_api_key)
+                            .service(get_sessions)
+                            .service(remove_other_sessions)
+                            .service(remove_session)
+                            .service(get_account)
+                            .service(update_account)
+                            .service(update_password)
+                            .service(get_blocklist_matches)
+                            .service(reload_blocklist),
                     ),
             )
             .service(redirect_to_original_url)
-            .app_data(pool.clone());
+            .app_data(pool.clone())
+            .app_data(web::Data::from(resolver.clone()));
 
         if is_production() {
             // Serve the static HTML files if we are in production
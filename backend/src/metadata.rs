@@ -0,0 +1,859 @@
+use chrono::Utc;
+use reqwest::{header, Method};
+use sqlx::PgPool;
+use std::{
+    net::{IpAddr, SocketAddr},
+    time::Duration,
+};
+use url::{Host, Url};
+use uuid::Uuid;
+
+use crate::{
+    constants::{FETCH_METADATA, METADATA_ALLOW_PRIVATE, METADATA_MAX_BYTES, METADATA_TIMEOUT},
+    structs::ShortenedUrl,
+};
+
+/// The maximum number of redirects followed when fetching a page
+const MAX_REDIRECTS: usize = 5;
+
+/// The maximum number of characters kept for the page title
+const MAX_TITLE_LENGTH: usize = 300;
+
+/// The maximum number of characters kept for the page description
+const MAX_DESCRIPTION_LENGTH: usize = 1000;
+
+/// Metadata extracted from a destination page
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct PageMetadata {
+    /// The page title
+    pub title: Option<String>,
+    /// The page description
+    pub description: Option<String>,
+    /// Absolute URL of the page's favicon
+    pub favicon_url: Option<String>,
+    /// Absolute URL of the page's Open Graph image
+    pub og_image_url: Option<String>,
+}
+
+/// Limits applied when fetching a destination page
+pub(crate) struct FetchLimits {
+    /// How long the whole fetch may take
+    pub timeout: Duration,
+    /// The maximum number of bytes read from the response body
+    pub max_bytes: usize,
+    /// Whether private, loopback and link-local addresses may be requested
+    pub allow_private: bool,
+}
+
+impl FetchLimits {
+    /// Builds the fetch limits from the environment
+    pub fn from_env() -> Self {
+        Self {
+            timeout: Duration::from_secs(*METADATA_TIMEOUT),
+            max_bytes: *METADATA_MAX_BYTES,
+            allow_private: *METADATA_ALLOW_PRIVATE,
+        }
+    }
+}
+
+/// Checks whether an address is not publicly routable
+/// 
+/// # Arguments
+/// * `ip` - The address to check
+/// 
+/// # Returns
+/// Boolean indicating if the address is private, loopback, link-local, multicast or otherwise reserved
+pub(crate) fn is_private_address(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let octets = ip.octets();
+            ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_multicast()
+                // Shared address space (100.64.0.0/10)
+                || (octets[0] == 100 && (octets[1] & 0xc0) == 64)
+                // Reserved for future use (240.0.0.0/4)
+                || octets[0] >= 240
+        }
+        IpAddr::V6(ip) => {
+            if let Some(mapped) = ip.to_ipv4_mapped() {
+                return is_private_address(&IpAddr::V4(mapped));
+            }
+            let segments = ip.segments();
+            // NAT64 addresses (64:ff9b::/96) reach the IPv4 address they embed
+            if segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0] {
+                let [.., a, b, c, d] = ip.octets();
+                return is_private_address(&IpAddr::V4([a, b, c, d].into()));
+            }
+            ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                // Unique local addresses (fc00::/7)
+                || (segments[0] & 0xfe00) == 0xfc00
+                // Link-local addresses (fe80::/10)
+                || (segments[0] & 0xffc0) == 0xfe80
+                // Documentation addresses (2001:db8::/32)
+                || (segments[0] == 0x2001 && segments[1] == 0x0db8)
+        }
+    }
+}
+
+/// Resolves the host of a URL and checks every address it resolves to
+/// 
+/// # Arguments
+/// * `url` - The URL about to be requested
+/// * `allow_private` - Whether non-public addresses are allowed
+/// 
+/// # Returns
+/// Result containing the address to connect to
+async fn resolve_checked(url: &Url, allow_private: bool) -> Result<SocketAddr, std::io::Error> {
+    let port = url.port_or_known_default().unwrap_or(80);
+    let addrs: Vec<SocketAddr> = match url.host() {
+        Some(Host::Ipv4(ip)) => vec![SocketAddr::new(IpAddr::V4(ip), port)],
+        Some(Host::Ipv6(ip)) => vec![SocketAddr::new(IpAddr::V6(ip), port)],
+        Some(Host::Domain(domain)) => tokio::net::lookup_host((domain, port)).await?.collect(),
+        None => {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "URL has no host",
+            ));
+        }
+    };
+
+    if !allow_private && addrs.iter().any(|a| is_private_address(&a.ip())) {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::PermissionDenied,
+            "Refusing to fetch a private or loopback address",
+        ));
+    }
+
+    addrs.into_iter().next().ok_or(std::io::Error::new(
+        std::io::ErrorKind::NotFound,
+        "Host did not resolve to any address",
+    ))
+}
+
+/// Settings for requesting a destination supplied by a user
+pub(crate) struct GuardedRequest<'a> {
+    /// How long a single request may take
+    pub timeout: Duration,
+    /// Whether private, loopback and link-local addresses may be requested
+    pub allow_private: bool,
+    /// The maximum number of redirects followed
+    pub max_redirects: usize,
+    /// The user agent sent with the request
+    pub user_agent: &'a str,
+    /// The Accept header sent with the request, if any
+    pub accept: Option<&'a str>,
+}
+
+/// Requests a destination supplied by a user without letting it reach internal hosts
+/// 
+/// Redirects are followed manually so that every hop is checked against the
+/// private address rules, and the connection is pinned to the checked address
+/// so the host can't resolve to a different address when connecting.
+/// 
+/// # Arguments
+/// * `method` - The HTTP method to use
+/// * `url` - The destination to request
+/// * `request` - Settings for the request
+/// 
+/// # Returns
+/// Result containing the first response that isn't a redirect
+pub(crate) async fn send_guarded(
+    method: Method,
+    url: &str,
+    request: &GuardedRequest<'_>,
+) -> Result<reqwest::Response, std::io::Error> {
+    let mut url = Url::parse(url)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e.to_string()))?;
+
+    for _ in 0..=request.max_redirects {
+        if url.scheme() != "http" && url.scheme() != "https" {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Only http and https destinations can be requested",
+            ));
+        }
+
+        let addr = resolve_checked(&url, request.allow_private).await?;
+        let mut builder = reqwest::Client::builder()
+            .timeout(request.timeout)
+            .redirect(reqwest::redirect::Policy::none())
+            .user_agent(request.user_agent);
+        if let Some(Host::Domain(domain)) = url.host() {
+            builder = builder.resolve(domain, addr);
+        }
+        let client = builder
+            .build()
+            .map_err(|e| std::io::Error::other(e.to_string()))?;
+
+        let mut outgoing = client.request(method.clone(), url.clone());
+        if let Some(accept) = request.accept {
+            outgoing = outgoing.header(header::ACCEPT, accept);
+        }
+        let response = outgoing
+            .send()
+            .await
+            .map_err(|e| std::io::Error::other(e.to_string()))?;
+
+        if !response.status().is_redirection() {
+            return Ok(response);
+        }
+        let location = response
+            .headers()
+            .get(header::LOCATION)
+            .and_then(|l| l.to_str().ok())
+            .ok_or(std::io::Error::other("Redirect without a location"))?;
+        url = url
+            .join(location)
+            .map_err(|e| std::io::Error::other(e.to_string()))?;
+    }
+
+    Err(std::io::Error::other("Too many redirects"))
+}
+
+/// Fetches the beginning of an HTML page
+/// 
+/// # Arguments
+/// * `url` - The page to fetch
+/// * `limits` - Limits applied to the fetch
+/// 
+/// # Returns
+/// Result containing the final URL and up to `max_bytes` of the body
+async fn fetch_page(url: &str, limits: &FetchLimits) -> Result<(Url, String), std::io::Error> {
+    let request = GuardedRequest {
+        timeout: limits.timeout,
+        allow_private: limits.allow_private,
+        max_redirects: MAX_REDIRECTS,
+        user_agent: concat!("nurl-metadata/", env!("CARGO_PKG_VERSION")),
+        accept: Some("text/html,application/xhtml+xml"),
+    };
+    let mut response = send_guarded(Method::GET, url, &request).await?;
+    let url = response.url().clone();
+
+    if !response.status().is_success() {
+        return Err(std::io::Error::other(format!(
+            "Destination responded with status {}",
+            response.status()
+        )));
+    }
+
+    let is_html = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|c| c.to_str().ok())
+        .is_none_or(|c| c.contains("html"));
+    if !is_html {
+        return Ok((url, String::new()));
+    }
+
+    let mut body = Vec::new();
+    while body.len() < limits.max_bytes {
+        match response
+            .chunk()
+            .await
+            .map_err(|e| std::io::Error::other(e.to_string()))?
+        {
+            Some(chunk) => body.extend_from_slice(&chunk),
+            None => break,
+        }
+    }
+    body.truncate(limits.max_bytes);
+
+    Ok((url, String::from_utf8_lossy(&body).into_owned()))
+}
+
+/// Decodes the most common HTML entities
+fn decode_entities(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&#x27;", "'")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+}
+
+/// Collapses whitespace and limits the length of extracted text
+fn clean_text(text: &str, max_length: usize) -> Option<String> {
+    let text = decode_entities(text)
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+    if text.is_empty() {
+        return None;
+    }
+    Some(text.chars().take(max_length).collect())
+}
+
+/// Parses the attributes of an HTML tag
+/// 
+/// # Arguments
+/// * `tag` - The contents of the tag after its name, up to the closing `>`
+/// 
+/// # Returns
+/// The attributes as lowercase names and raw values
+fn parse_attributes(tag: &str) -> Vec<(String, String)> {
+    let mut attributes = Vec::new();
+    let mut rest = tag.trim_start();
+
+    while !rest.is_empty() {
+        let name_end = rest
+            .find(|c: char| c.is_whitespace() || c == '=' || c == '/')
+            .unwrap_or(rest.len());
+        let name = rest[..name_end].to_ascii_lowercase();
+        rest = rest[name_end..].trim_start();
+
+        let mut value = String::new();
+        if let Some(after_eq) = rest.strip_prefix('=') {
+            let after_eq = after_eq.trim_start();
+            let quote = after_eq.chars().next().filter(|c| *c == '"' || *c == '\'');
+            let (raw, remaining) = match quote {
+                Some(q) => {
+                    let inner = &after_eq[1..];
+                    let end = inner.find(q).unwrap_or(inner.len());
+                    (&inner[..end], inner.get(end + 1..).unwrap_or(""))
+                }
+                None => {
+                    let end = after_eq.find(char::is_whitespace).unwrap_or(after_eq.len());
+                    (&after_eq[..end], &after_eq[end..])
+                }
+            };
+            value = raw.to_string();
+            rest = remaining;
+        } else if rest.starts_with('/') {
+            rest = &rest[1..];
+        }
+
+        if !name.is_empty() {
+            attributes.push((name, value));
+        }
+        rest = rest.trim_start();
+    }
+
+    attributes
+}
+
+/// Looks up an attribute by name
+fn attribute<'a>(attributes: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    attributes
+        .iter()
+        .find(|(n, _)| n == name)
+        .map(|(_, v)| v.as_str())
+}
+
+/// Extracts metadata from the head of an HTML page
+/// 
+/// # Arguments
+/// * `html` - The (possibly truncated) page
+/// * `base` - The URL of the page, used to resolve relative links
+/// 
+/// # Returns
+/// The extracted metadata
+pub(crate) fn parse_metadata(html: &str, base: &Url) -> PageMetadata {
+    let lower = html.to_ascii_lowercase();
+    let mut metadata = PageMetadata::default();
+    let mut og_title = None;
+    let mut og_description = None;
+    let mut favicon = None;
+
+    let mut position = 0;
+    while let Some(offset) = lower[position..].find('<') {
+        let start = position + offset + 1;
+        let Some(end_offset) = lower[start..].find('>') else {
+            break;
+        };
+        let end = start + end_offset;
+        position = end + 1;
+
+        let tag = &html[start..end];
+        let name_end = tag
+            .find(|c: char| c.is_whitespace() || c == '/')
+            .unwrap_or(tag.len());
+        let name = tag[..name_end].to_ascii_lowercase();
+
+        match name.as_str() {
+            "title" if metadata.title.is_none() => {
+                let close = lower[position..]
+                    .find("</title")
+                    .map(|i| position + i)
+                    .unwrap_or(lower.len());
+                metadata.title = clean_text(&html[position..close], MAX_TITLE_LENGTH);
+                position = close;
+            }
+            "meta" => {
+                let attributes = parse_attributes(&tag[name_end..]);
+                let key = attribute(&attributes, "property")
+                    .or(attribute(&attributes, "name"))
+                    .map(|k| k.to_ascii_lowercase());
+                let content = attribute(&attributes, "content").unwrap_or("");
+                match key.as_deref() {
+                    Some("description") if metadata.description.is_none() => {
+                        metadata.description = clean_text(content, MAX_DESCRIPTION_LENGTH);
+                    }
+                    Some("og:title") if og_title.is_none() => {
+                        og_title = clean_text(content, MAX_TITLE_LENGTH);
+                    }
+                    Some("og:description") if og_description.is_none() => {
+                        og_description = clean_text(content, MAX_DESCRIPTION_LENGTH);
+                    }
+                    Some("og:image") | Some("og:image:url") if metadata.og_image_url.is_none() => {
+                        metadata.og_image_url = resolve_link(base, content);
+                    }
+                    _ => {}
+                }
+            }
+            "link" if favicon.is_none() => {
+                let attributes = parse_attributes(&tag[name_end..]);
+                let is_icon = attribute(&attributes, "rel").is_some_and(|rel| {
+                    rel.split_whitespace()
+                        .any(|r| r.eq_ignore_ascii_case("icon"))
+                });
+                if is_icon {
+                    favicon = attribute(&attributes, "href").and_then(|h| resolve_link(base, h));
+                }
+            }
+            "body" | "/head" => break,
+            _ => {}
+        }
+    }
+
+    metadata.title = metadata.title.or(og_title);
+    metadata.description = metadata.description.or(og_description);
+    metadata.favicon_url = favicon.or_else(|| resolve_link(base, "/favicon.ico"));
+    metadata
+}
+
+/// Resolves a possibly relative link against the page URL
+/// 
+/// Only http and https links are kept.
+fn resolve_link(base: &Url, link: &str) -> Option<String> {
+    let link = decode_entities(link.trim());
+    if link.is_empty() {
+        return None;
+    }
+    base.join(&link)
+        .ok()
+        .filter(|u| u.scheme() == "http" || u.scheme() == "https")
+        .map(|u| u.to_string())
+}
+
+/// Fetches a destination page and extracts its metadata
+/// 
+/// # Arguments
+/// * `url` - The page to fetch
+/// * `limits` - Limits applied to the fetch
+/// 
+/// # Returns
+/// Result containing the extracted metadata
+pub(crate) async fn fetch_metadata(
+    url: &str,
+    limits: &FetchLimits,
+) -> Result<PageMetadata, std::io::Error> {
+    let (final_url, html) = tokio::time::timeout(limits.timeout, fetch_page(url, limits))
+        .await
+        .map_err(|_| std::io::Error::new(std::io::ErrorKind::TimedOut, "Fetch timed out"))??;
+    Ok(parse_metadata(&html, &final_url))
+}
+
+/// Stores the metadata of a link's destination, clearing any recorded fetch error
+/// 
+/// # Arguments
+/// * `id` - The ID of the link
+/// * `url` - The destination the metadata was fetched from
+/// * `metadata` - The fetched metadata
+/// * `pool` - Database connection pool
+/// 
+/// # Returns
+/// Result containing the updated link, or `None` if its destination changed in the meantime
+async fn store_metadata(
+    id: Uuid,
+    url: &str,
+    metadata: &PageMetadata,
+    pool: &PgPool,
+) -> Result<Option<ShortenedUrl>, std::io::Error> {
+    // Only store the metadata if the destination didn't change in the meantime
+    sqlx::query_as::<_, ShortenedUrl>(
+        r#"
+      UPDATE shortened_urls
+      SET
+          title = $1,
+          description = $2,
+          favicon_url = $3,
+          og_image_url = $4,
+          metadata_fetched_at = $5,
+          metadata_error = NULL
+      WHERE id = $6 AND original_url = $7
+      RETURNING *
+      "#,
+    )
+    .bind(&metadata.title)
+    .bind(&metadata.description)
+    .bind(&metadata.favicon_url)
+    .bind(&metadata.og_image_url)
+    .bind(Utc::now())
+    .bind(id)
+    .bind(url)
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| std::io::Error::other(e.to_string()))
+}
+
+/// Fetches the metadata of a link's destination and stores it on the link
+/// 
+/// The stored metadata is left untouched if the fetch fails.
+/// 
+/// # Arguments
+/// * `id` - The ID of the link
+/// * `url` - The destination of the link
+/// * `pool` - Database connection pool
+/// 
+/// # Returns
+/// Result containing the updated link, or `None` if its destination changed in the meantime
+pub(crate) async fn refresh_metadata(
+    id: Uuid,
+    url: &str,
+    pool: &PgPool,
+) -> Result<Option<ShortenedUrl>, std::io::Error> {
+    let metadata = fetch_metadata(url, &FetchLimits::from_env())
+        .await
+        .map_err(|e| {
+            std::io::Error::other(format!(
+                "Could not fetch the metadata of the destination: {}",
+                e
+            ))
+        })?;
+    store_metadata(id, url, &metadata, pool).await
+}
+
+/// Records why fetching the metadata of a link's destination failed
+/// 
+/// The previously stored metadata is kept. Nothing is recorded if the
+/// destination changed in the meantime.
+/// 
+/// # Arguments
+/// * `id` - The ID of the link
+/// * `url` - The destination the fetch was made for
+/// * `error` - Why the fetch failed
+/// * `pool` - Database connection pool
+/// 
+/// # Returns
+/// Result that is an error if the failure could not be stored
+async fn record_metadata_error(
+    id: Uuid,
+    url: &str,
+    error: &str,
+    pool: &PgPool,
+) -> Result<(), std::io::Error> {
+    sqlx::query(
+        r#"
+      UPDATE shortened_urls
+      SET metadata_fetched_at = $1, metadata_error = $2
+      WHERE id = $3 AND original_url = $4
+      "#,
+    )
+    .bind(Utc::now())
+    .bind(error)
+    .bind(id)
+    .bind(url)
+    .execute(pool)
+    .await
+    .map_err(|e| std::io::Error::other(e.to_string()))?;
+    Ok(())
+}
+
+/// Fetches the metadata of a link's destination in the background
+/// 
+/// Nothing is fetched if `FETCH_METADATA` is disabled. If the fetch fails, only
+/// the time and the error are recorded.
+/// 
+/// # Arguments
+/// * `id` - The ID of the link
+/// * `url` - The destination of the link
+/// * `pool` - Database connection pool
+pub(crate) fn spawn_metadata_refresh(id: Uuid, url: String, pool: PgPool) {
+    if !*FETCH_METADATA {
+        return;
+    }
+
+    tokio::spawn(async move {
+        let result = match fetch_metadata(&url, &FetchLimits::from_env()).await {
+            Ok(metadata) => store_metadata(id, &url, &metadata, &pool).await.map(|_| ()),
+            Err(e) => record_metadata_error(id, &url, &e.to_string(), &pool).await,
+        };
+        if let Err(e) = result {
+            println!("Could not store metadata for {}: {}", url, e);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::{create_test_user, delete_test_users, init_test_db};
+    use actix_web::{web, App, HttpResponse, HttpServer};
+
+    const TEST_PAGE: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+  <meta charset="utf-8">
+  <TITLE>  Example &amp; Co
+  </TITLE>
+  <meta name="description" content="An example page">
+  <meta property='og:image' content="/images/og.png" />
+  <link rel="shortcut icon" href="https://cdn.example.com/icon.png">
+</head>
+<body><title>Not this one</title></body>
+</html>"#;
+
+    #[test]
+    fn test_parse_metadata() {
+        let base = Url::parse("https://example.com/page/").unwrap();
+        let metadata = parse_metadata(TEST_PAGE, &base);
+
+        assert_eq!(
+            metadata,
+            PageMetadata {
+                title: Some("Example & Co".to_string()),
+                description: Some("An example page".to_string()),
+                favicon_url: Some("https://cdn.example.com/icon.png".to_string()),
+                og_image_url: Some("https://example.com/images/og.png".to_string()),
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_metadata_fallbacks() {
+        let base = Url::parse("https://example.com/a/b").unwrap();
+        let html = r#"<head><meta property="og:title" content="OG title">
+            <meta property="og:description" content="OG description"></head>"#;
+        let metadata = parse_metadata(html, &base);
+
+        assert_eq!(metadata.title, Some("OG title".to_string()));
+        assert_eq!(metadata.description, Some("OG description".to_string()));
+        assert_eq!(
+            metadata.favicon_url,
+            Some("https://example.com/favicon.ico".to_string())
+        );
+        assert_eq!(metadata.og_image_url, None);
+    }
+
+    #[test]
+    fn test_is_private_address() {
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+            "224.0.0.1",
+            "239.255.255.250",
+            "ff02::1",
+            "2001:db8::1",
+            "64:ff9b::a00:1",
+            "64:ff9b::127.0.0.1",
+        ] {
+            assert!(is_private_address(&ip.parse().unwrap()), "{}", ip);
+        }
+
+        for ip in [
+            "93.184.216.34",
+            "8.8.8.8",
+            "2606:4700::1111",
+            "64:ff9b::808:808",
+        ] {
+            assert!(!is_private_address(&ip.parse().unwrap()), "{}", ip);
+        }
+    }
+
+    /// Starts a local stand-in for destination pages
+    /// 
+    /// Routes:
+    /// - `/page` returns the test page
+    /// - `/moved` redirects to `/page`
+    /// - `/large` returns a page whose title is past the first kilobyte
+    /// 
+    /// # Returns
+    /// The base URL of the server
+    fn start_page_server() -> String {
+        let server = HttpServer::new(|| {
+            App::new()
+                .route(
+                    "/page",
+                    web::get().to(|| async {
+                        HttpResponse::Ok()
+                            .content_type("text/html; charset=utf-8")
+                            .body(TEST_PAGE)
+                    }),
+                )
+                .route(
+                    "/moved",
+                    web::get().to(|| async {
+                        HttpResponse::Found()
+                            .append_header(("Location", "/page"))
+                            .finish()
+                    }),
+                )
+                .route(
+                    "/large",
+                    web::get().to(|| async {
+                        HttpResponse::Ok()
+                            .content_type("text/html")
+                            .body(format!("<head>{}<title>Too far</title>", " ".repeat(4096)))
+                    }),
+                )
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let base = format!("http://{}", server.addrs()[0]);
+        actix_rt::spawn(server.run());
+        base
+    }
+
+    fn test_limits() -> FetchLimits {
+        FetchLimits {
+            timeout: Duration::from_secs(5),
+            max_bytes: 1024,
+            allow_private: true,
+        }
+    }
+
+    /// Tests that redirects are followed and relative links resolved against the final URL
+    #[actix_rt::test]
+    async fn test_fetch_metadata_follows_redirects() {
+        let base = start_page_server();
+
+        let metadata = fetch_metadata(&format!("{}/moved", base), &test_limits())
+            .await
+            .unwrap();
+        assert_eq!(metadata.title, Some("Example & Co".to_string()));
+        assert_eq!(
+            metadata.og_image_url,
+            Some(format!("{}/images/og.png", base))
+        );
+    }
+
+    /// Tests that only the first `max_bytes` of the body are read
+    #[actix_rt::test]
+    async fn test_fetch_metadata_limits_body_size() {
+        let base = start_page_server();
+
+        let metadata = fetch_metadata(&format!("{}/large", base), &test_limits())
+            .await
+            .unwrap();
+        assert_eq!(metadata.title, None);
+    }
+
+    /// Tests that loopback addresses are refused unless explicitly allowed
+    #[actix_rt::test]
+    async fn test_fetch_metadata_refuses_private_addresses() {
+        let base = start_page_server();
+        let limits = FetchLimits {
+            allow_private: false,
+            ..test_limits()
+        };
+
+        let result = fetch_metadata(&format!("{}/page", base), &limits).await;
+        assert_eq!(
+            result.unwrap_err().kind(),
+            std::io::ErrorKind::PermissionDenied
+        );
+    }
+
+    /// Tests that a failed refresh returns the error and keeps the stored metadata
+    #[actix_rt::test]
+    async fn test_refresh_metadata_keeps_metadata_on_failure() {
+        let pool = init_test_db().await;
+        let user = create_test_user(&pool, "metadata_refresh").await;
+
+        // Loopback addresses are refused by default, so the fetch fails
+        let url = "http://127.0.0.1:9/";
+        let id = Uuid::new_v4();
+        sqlx::query(
+            "INSERT INTO shortened_urls (id, short_url, original_url, redirects, created_at, updated_at, owner, title)
+           VALUES ($1, $2, $3, 0, CURRENT_TIMESTAMP, CURRENT_TIMESTAMP, $4, 'Stored title')",
+        )
+        .bind(id)
+        .bind(format!("meta_{}", id))
+        .bind(url)
+        .bind(user.id)
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        assert!(refresh_metadata(id, url, &pool).await.is_err());
+
+        let (title, fetched_at): (Option<String>, Option<chrono::DateTime<Utc>>) =
+            sqlx::query_as("SELECT title, metadata_fetched_at FROM shortened_urls WHERE id = $1")
+                .bind(id)
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert_eq!(title, Some("Stored title".to_string()));
+        assert_eq!(fetched_at, None);
+
+        delete_test_users(&pool, &[user.id]).await;
+    }
+
+    /// Tests that a failed background fetch only records the time and the error
+    #[actix_rt::test]
+    async fn test_record_metadata_error() {
+        let pool = init_test_db().await;
+        let user = create_test_user(&pool, "metadata_error").await;
+
+        let url = "https://example.com/";
+        let id = Uuid::new_v4();
+        sqlx::query(
+            "INSERT INTO shortened_urls (id, short_url, original_url, redirects, created_at, updated_at, owner, title)
+           VALUES ($1, $2, $3, 0, CURRENT_TIMESTAMP, CURRENT_TIMESTAMP, $4, 'Stored title')",
+        )
+        .bind(id)
+        .bind(format!("meta_{}", id))
+        .bind(url)
+        .bind(user.id)
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        // Failures of a previous destination aren't recorded
+        record_metadata_error(id, "https://example.org/", "Fetch timed out", &pool)
+            .await
+            .unwrap();
+        let (error,): (Option<String>,) =
+            sqlx::query_as("SELECT metadata_error FROM shortened_urls WHERE id = $1")
+                .bind(id)
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert_eq!(error, None);
+
+        record_metadata_error(id, url, "Fetch timed out", &pool)
+            .await
+            .unwrap();
+
+        let (title, error, fetched): (Option<String>, Option<String>, bool) = sqlx::query_as(
+            "SELECT title, metadata_error, metadata_fetched_at IS NOT NULL FROM shortened_urls WHERE id = $1",
+        )
+        .bind(id)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        assert_eq!(title, Some("Stored title".to_string()));
+        assert_eq!(error, Some("Fetch timed out".to_string()));
+        assert!(fetched);
+
+        delete_test_users(&pool, &[user.id]).await;
+    }
+}
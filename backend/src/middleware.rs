@@ -1,23 +1,31 @@
 use actix_web::{
+    body::EitherBody,
     dev::{Service, ServiceRequest, ServiceResponse, Transform},
     http::header,
-    Error, HttpMessage,
+    web, Error, HttpMessage, HttpResponse,
 };
 use futures::future::{ready, LocalBoxFuture, Ready};
 use jsonwebtoken::{decode, DecodingKey, Validation};
+use sqlx::PgPool;
 use std::{
     rc::Rc,
     task::{Context, Poll},
 };
 
-use crate::{constants::NURL_SECRET, structs::Claims};
+use crate::{
+    api_keys::{authenticate_api_key, required_scope, API_KEY_PREFIX},
+    constants::NURL_SECRET,
+    sessions::{is_session_active, CurrentSession},
+    structs::{APIResponse, Claims},
+    utils::error_response,
+};
 
-/// Middleware for extracting and validating JWT tokens from requests
+/// Middleware for extracting and validating JWT tokens or API keys from requests
 /// 
 /// This middleware:
-/// 1. Extracts the JWT token from the Authorization header
-/// 2. Validates the token
-/// 3. Extracts the username from the token
+/// 1. Extracts the JWT token or API key from the Authorization header
+/// 2. Validates the token, or looks the API key up and checks its scopes
+/// 3. Extracts the username from the token or key
 /// 4. Adds the username to the request extensions if valid
 pub struct ExtractUsernameJWT;
 
@@ -26,7 +34,7 @@ where
     S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
     B: 'static,
 {
-    type Response = ServiceResponse<B>;
+    type Response = ServiceResponse<EitherBody<B>>;
     type Error = Error;
     type Transform = ExtractUsernameMiddleware<S>;
     type InitError = ();
@@ -49,7 +57,7 @@ where
     S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
     B: 'static,
 {
-    type Response = ServiceResponse<B>;
+    type Response = ServiceResponse<EitherBody<B>>;
     type Error = Error;
     type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
 
@@ -61,27 +69,103 @@ where
         let service = self.service.clone();
 
         Box::pin(async move {
-            process_auth_header(&req);
-            service.call(req).await
+            let result = match extract_token_from_header(&req) {
+                Some(key) if key.starts_with(API_KEY_PREFIX) => process_api_key(&req, &key).await,
+                _ => process_auth_header(&req).await,
+            };
+            if let Err(response) = result {
+                return Ok(req.into_response(response).map_into_right_body());
+            }
+            service
+                .call(req)
+                .await
+                .map(ServiceResponse::map_into_left_body)
         })
     }
 }
 
+/// Returns the database connection pool of the application handling a request
+fn request_pool(req: &ServiceRequest) -> Result<&web::Data<PgPool>, HttpResponse> {
+    req.app_data::<web::Data<PgPool>>()
+        .ok_or(HttpResponse::InternalServerError().finish())
+}
+
+/// Authenticates a request made with an API key
+/// 
+/// This function:
+/// 1. Looks the key up, recording that it was used
+/// 2. Checks that the key has the scope the endpoint requires
+/// 3. Adds the username of the key's owner to request extensions
+/// 
+/// # Arguments
+/// * `req` - The incoming service request
+/// * `key` - The API key sent with the request
+/// 
+/// # Returns
+/// Result containing the response to send instead if the key is invalid or
+/// expired (401), or lacks the required scope (403)
+async fn process_api_key(req: &ServiceRequest, key: &str) -> Result<(), HttpResponse> {
+    let pool = request_pool(req)?;
+    let identity = authenticate_api_key(key, pool.get_ref())
+        .await
+        .map_err(error_response)?
+        .ok_or(
+            HttpResponse::Unauthorized().json(APIResponse::error_message(
+                "Invalid or expired API key".to_string(),
+            )),
+        )?;
+
+    match required_scope(req.method(), req.path()) {
+        Some(scope) if identity.scopes.contains(&scope) => {
+            req.extensions_mut().insert(identity.username);
+            Ok(())
+        }
+        Some(scope) => Err(
+            HttpResponse::Forbidden().json(APIResponse::error_message(format!(
+                "This API key doesn't have the {} scope",
+                scope.as_str()
+            ))),
+        ),
+        None => Err(HttpResponse::Forbidden().json(APIResponse::error_message(
+            "API keys can't be used for this endpoint, log in instead".to_string(),
+        ))),
+    }
+}
+
 /// Processes the Authorization header of a request
 /// 
 /// This function:
 /// 1. Extracts the token from the header
 /// 2. Validates the token
-/// 3. Extracts the username
-/// 4. Adds the username to request extensions if valid
+/// 3. Checks that the token's session wasn't revoked
+/// 4. Adds the username and session to request extensions if valid
 /// 
 /// # Arguments
 /// * `req` - The incoming service request
-pub fn process_auth_header(req: &ServiceRequest) {
-    if let Some(token) = extract_token_from_header(req) {
-        if let Some(username) = validate_and_extract_username(&token) {
-            req.extensions_mut().insert(username);
+/// 
+/// # Returns
+/// Result containing the response to send instead if the token's session was
+/// revoked or expired (401)
+pub async fn process_auth_header(req: &ServiceRequest) -> Result<(), HttpResponse> {
+    let Some(claims) =
+        extract_token_from_header(req).and_then(|token| validate_and_extract_claims(&token))
+    else {
+        return Ok(());
+    };
+
+    let pool = request_pool(req)?;
+    match is_session_active(claims.sid, &claims.username, pool.get_ref()).await {
+        Ok(true) => {
+            req.extensions_mut().insert(claims.username);
+            req.extensions_mut().insert(CurrentSession(claims.sid));
+            Ok(())
         }
+        Ok(false) => Err(
+            HttpResponse::Unauthorized().json(APIResponse::error_message(
+                "Session has been revoked or expired, log in again".to_string(),
+            )),
+        ),
+        Err(e) => Err(error_response(e)),
     }
 }
 
@@ -100,14 +184,14 @@ pub fn extract_token_from_header(req: &ServiceRequest) -> Option<String> {
         .map(|s| s[7..].to_string()) // Skip "Bearer " prefix
 }
 
-/// Validates a JWT token and extracts the username from it
+/// Validates a JWT token and extracts its claims
 /// 
 /// # Arguments
 /// * `token` - The JWT token to validate
 /// 
 /// # Returns
-/// Option containing the username if the token is valid
-pub fn validate_and_extract_username(token: &str) -> Option<String> {
+/// Option containing the claims if the token is valid
+pub fn validate_and_extract_claims(token: &str) -> Option<Claims> {
     let secret = NURL_SECRET.as_bytes();
 
     decode::<Claims>(
@@ -115,26 +199,32 @@ pub fn validate_and_extract_username(token: &str) -> Option<String> {
         &DecodingKey::from_secret(secret),
         &Validation::default(),
     )
-    .map(|data| data.claims.username)
+    .map(|data| data.claims)
     .ok()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::api_keys::{create_api_key, ApiKeyScope};
+    use crate::sessions::{create_session, revoke_session, ClientInfo};
     use crate::structs::Claims;
-    use actix_web::http::header;
-    use actix_web::test::TestRequest;
+    use crate::utils::{create_test_user, delete_test_users, init_test_db};
+    use actix_web::http::{header, StatusCode};
+    use actix_web::test::{call_service, init_service, read_body, TestRequest};
+    use actix_web::{route, App, Responder};
     use jsonwebtoken::{encode, EncodingKey, Header};
     use std::time::{SystemTime, UNIX_EPOCH};
+    use uuid::Uuid;
 
-    fn create_test_token(username: &str) -> String {
+    fn create_test_token(username: &str, session_id: Uuid) -> String {
         let now = SystemTime::now()
             .duration_since(UNIX_EPOCH)
             .unwrap()
             .as_secs();
         let claims = Claims {
             username: username.to_string(),
+            sid: session_id,
             exp: (now + 3600) as usize, // Valid for 1 hour
         };
         encode(
@@ -170,45 +260,172 @@ mod tests {
     }
 
     #[test]
-    fn test_validate_and_extract_username() {
+    fn test_validate_and_extract_claims() {
         // Test with valid token
         let username = "test_user";
-        let token = create_test_token(username);
-        let extracted = validate_and_extract_username(&token);
-        assert_eq!(extracted, Some(username.to_string()));
+        let session_id = Uuid::new_v4();
+        let token = create_test_token(username, session_id);
+        let claims = validate_and_extract_claims(&token).unwrap();
+        assert_eq!(claims.username, username);
+        assert_eq!(claims.sid, session_id);
 
         // Test with invalid token
-        let extracted = validate_and_extract_username("invalid_token");
-        assert_eq!(extracted, None);
+        let extracted = validate_and_extract_claims("invalid_token");
+        assert!(extracted.is_none());
     }
 
-    #[test]
-    fn test_process_auth_header() {
+    #[actix_rt::test]
+    async fn test_process_auth_header() {
+        let pool = init_test_db().await;
+        let user = create_test_user(&pool, "middleware_jwt").await;
+        let session = create_session(&user, false, &ClientInfo::default(), &pool)
+            .await
+            .unwrap();
+        let request = |token: &str| {
+            TestRequest::default()
+                .app_data(web::Data::new(pool.clone()))
+                .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+                .to_srv_request()
+        };
+
         // Test with valid token
-        let username = "test_user";
-        let token = create_test_token(username);
+        let token = create_test_token(&user.username, session.session_id);
+        let req = request(&token);
+        assert!(process_auth_header(&req).await.is_ok());
+
+        // Check if username was inserted into request extensions
+        assert_eq!(req.extensions().get::<String>(), Some(&user.username));
+
+        // Test with invalid token
+        let req = request("invalid_token");
+        assert!(process_auth_header(&req).await.is_ok());
+
+        // No username should be inserted for invalid token
+        assert!(req.extensions().get::<String>().is_none());
+
+        delete_test_users(&pool, &[user.id]).await;
+    }
+
+    /// Tests that tokens of revoked sessions are rejected
+    #[actix_rt::test]
+    async fn test_process_auth_header_revoked_session() {
+        let pool = init_test_db().await;
+        let user = create_test_user(&pool, "middleware_revoked").await;
+        let session = create_session(&user, false, &ClientInfo::default(), &pool)
+            .await
+            .unwrap();
+        let token = create_test_token(&user.username, session.session_id);
+
+        revoke_session(&session.refresh_token, &pool).await.unwrap();
         let req = TestRequest::default()
+            .app_data(web::Data::new(pool.clone()))
             .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
             .to_srv_request();
+        let response = process_auth_header(&req).await.unwrap_err();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert!(req.extensions().get::<String>().is_none());
 
-        process_auth_header(&req);
+        delete_test_users(&pool, &[user.id]).await;
+    }
 
-        // Check if username was inserted into request extensions
-        let binding = req.extensions();
-        let ext_username = binding.get::<String>();
-        assert!(ext_username.is_some());
-        assert_eq!(ext_username.unwrap(), username);
+    /// Responds with the username the middleware extracted
+    #[route("/{path:.*}", method = "GET", method = "POST")]
+    async fn echo_username(username: web::ReqData<String>) -> impl Responder {
+        username.to_string()
+    }
 
-        // Test with invalid token
+    /// Sends a request with a bearer token through the middleware
+    /// 
+    /// # Arguments
+    /// * `method` - The method of the request
+    /// * `path` - The path of the request
+    /// * `token` - The API key or JWT sent as the bearer token
+    /// * `pool` - Database connection pool
+    /// 
+    /// # Returns
+    /// The status of the response and its body, the extracted username on success
+    async fn call_with_token(
+        method: &str,
+        path: &str,
+        token: &str,
+        pool: &PgPool,
+    ) -> (StatusCode, web::Bytes) {
+        let app = init_service(
+            App::new().app_data(web::Data::new(pool.clone())).service(
+                web::scope("/api")
+                    .wrap(ExtractUsernameJWT)
+                    .service(echo_username),
+            ),
+        )
+        .await;
         let req = TestRequest::default()
-            .insert_header((header::AUTHORIZATION, "Bearer invalid_token"))
-            .to_srv_request();
+            .method(method.parse().unwrap())
+            .uri(path)
+            .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+            .to_request();
+        let response = call_service(&app, req).await;
+        (response.status(), read_body(response).await)
+    }
+
+    /// Tests that API keys authenticate requests their scopes allow
+    #[actix_rt::test]
+    async fn test_api_key_in_scope() {
+        let pool = init_test_db().await;
+        let user = create_test_user(&pool, "middleware_key").await;
+        let key = create_api_key(&user, "reader", &[ApiKeyScope::LinksRead], None, &pool)
+            .await
+            .unwrap()
+            .key;
 
-        process_auth_header(&req);
+        let (status, body) = call_with_token("GET", "/api/shorten", &key, &pool).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, user.username.as_bytes());
 
-        // No username should be inserted for invalid token
-        let binding = req.extensions();
-        let ext_username = binding.get::<String>();
-        assert!(ext_username.is_none());
+        delete_test_users(&pool, &[user.id]).await;
+    }
+
+    /// Tests that requests outside the scopes of an API key are forbidden
+    #[actix_rt::test]
+    async fn test_api_key_out_of_scope() {
+        let pool = init_test_db().await;
+        let user = create_test_user(&pool, "middleware_key_scope").await;
+        let key = create_api_key(&user, "reader", &[ApiKeyScope::LinksRead], None, &pool)
+            .await
+            .unwrap()
+            .key;
+
+        let (status, _) = call_with_token("POST", "/api/shorten", &key, &pool).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let (status, _) = call_with_token("GET", "/api/api-keys", &key, &pool).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let (status, _) = call_with_token("GET", "/api/me", &key, &pool).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        delete_test_users(&pool, &[user.id]).await;
+    }
+
+    /// Tests that unknown API keys are rejected
+    #[actix_rt::test]
+    async fn test_unknown_api_key() {
+        let pool = init_test_db().await;
+
+        let (status, _) = call_with_token("GET", "/api/shorten", "nurl_unknown", &pool).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    /// Tests that JWTs aren't limited to the scopes of API keys
+    #[actix_rt::test]
+    async fn test_jwt_not_scoped() {
+        let pool = init_test_db().await;
+        let user = create_test_user(&pool, "middleware_key_jwt").await;
+        let session = create_session(&user, false, &ClientInfo::default(), &pool)
+            .await
+            .unwrap();
+        let token = create_test_token(&user.username, session.session_id);
+
+        let (status, _) = call_with_token("POST", "/api/shorten", &token, &pool).await;
+        assert_eq!(status, StatusCode::OK);
+
+        delete_test_users(&pool, &[user.id]).await;
     }
 }
@@ -0,0 +1,137 @@
+use actix_web::{get, patch, post, web, HttpResponse, Responder};
+use serde::Deserialize;
+use sqlx::PgPool;
+
+use crate::{
+    account::{change_password, get_profile, update_settings, SettingsPatch},
+    sessions::CurrentSession,
+    structs::{APIResponse, User},
+    utils::error_response,
+};
+
+/// Request body for changing the password of a user
+#[derive(Deserialize)]
+struct ChangePasswordRequest {
+    /// The password the user currently logs in with
+    current_password: String,
+    /// The new password
+    password: String,
+    /// The new password entered a second time
+    confirm_password: String,
+}
+
+/// Retrieves the profile of the authenticated user
+/// 
+/// # Arguments
+/// * `pool` - Database connection pool
+/// * `username` - The authenticated user's username
+/// 
+/// # Returns
+/// HTTP response:
+/// - 200 OK with the profile, link counts, total clicks and settings
+/// - 401 Unauthorized if user not found
+/// - 500 Internal Server Error if retrieval fails
+#[get("/me")]
+pub async fn get_account(
+    pool: web::Data<PgPool>,
+    username: web::ReqData<String>,
+) -> impl Responder {
+    let user = match sqlx::query_as::<_, User>("SELECT * FROM users WHERE username = $1")
+        .bind(username.to_string())
+        .fetch_one(pool.get_ref())
+        .await
+    {
+        Ok(u) => u,
+        Err(_) => return HttpResponse::Unauthorized().finish(),
+    };
+
+    match get_profile(&user, pool.get_ref()).await {
+        Ok(profile) => HttpResponse::Ok().json(APIResponse::data(profile)),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Updates the settings of the authenticated user, such as the defaults of new links
+/// 
+/// Absent fields are left unchanged and `default_expiration` is cleared when
+/// set to `null`.
+/// 
+/// # Arguments
+/// * `patch` - The settings to change
+/// * `pool` - Database connection pool
+/// * `username` - The authenticated user's username
+/// 
+/// # Returns
+/// HTTP response:
+/// - 200 OK with the updated profile
+/// - 400 Bad Request if a setting is invalid (with the target field)
+/// - 401 Unauthorized if user not found
+/// - 500 Internal Server Error if updating fails
+#[patch("/me")]
+pub async fn update_account(
+    patch: web::Json<SettingsPatch>,
+    pool: web::Data<PgPool>,
+    username: web::ReqData<String>,
+) -> impl Responder {
+    let user = match sqlx::query_as::<_, User>("SELECT * FROM users WHERE username = $1")
+        .bind(username.to_string())
+        .fetch_one(pool.get_ref())
+        .await
+    {
+        Ok(u) => u,
+        Err(_) => return HttpResponse::Unauthorized().finish(),
+    };
+
+    match update_settings(&user, &patch, pool.get_ref()).await {
+        Ok(profile) => HttpResponse::Ok().json(APIResponse::data(profile)),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Changes the password of the authenticated user
+/// 
+/// The new password follows the same rules as when registering. Every other
+/// session of the user is revoked, logging out their other devices.
+/// 
+/// # Arguments
+/// * `body` - The request body containing the current and new passwords
+/// * `pool` - Database connection pool
+/// * `username` - The authenticated user's username
+/// * `current` - The session the request was made with, which stays logged in
+/// 
+/// # Returns
+/// HTTP response:
+/// - 204 No Content if successful
+/// - 400 Bad Request if the current password is wrong or the new one is invalid (with the target field)
+/// - 401 Unauthorized if user not found
+/// - 500 Internal Server Error if changing fails
+#[post("/me/password")]
+pub async fn update_password(
+    body: web::Json<ChangePasswordRequest>,
+    pool: web::Data<PgPool>,
+    username: web::ReqData<String>,
+    current: Option<web::ReqData<CurrentSession>>,
+) -> impl Responder {
+    let user = match sqlx::query_as::<_, User>("SELECT * FROM users WHERE username = $1")
+        .bind(username.to_string())
+        .fetch_one(pool.get_ref())
+        .await
+    {
+        Ok(u) => u,
+        Err(_) => return HttpResponse::Unauthorized().finish(),
+    };
+
+    match change_password(
+        &user,
+        &body.current_password,
+        &body.password,
+        &body.confirm_password,
+        current.map(|c| c.0),
+        pool.get_ref(),
+    )
+    .await
+    {
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(e) => error_response(e),
+    }
+}
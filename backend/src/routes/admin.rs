@@ -0,0 +1,81 @@
+use actix_web::{get, post, web, HttpResponse, Responder};
+use sqlx::PgPool;
+
+use crate::{
+    blocklist::reload,
+    constants::ADMIN_USERNAMES,
+    service::{list_blocklisted_urls, BlocklistMatchFilter},
+    structs::APIResponse,
+    utils::error_response,
+};
+
+/// Checks whether a user is allowed to use the admin endpoints
+/// 
+/// # Arguments
+/// * `username` - The authenticated user's username
+/// 
+/// # Returns
+/// Boolean indicating if the user is an admin
+fn is_admin(username: &str) -> bool {
+    ADMIN_USERNAMES.iter().any(|admin| admin == username)
+}
+
+/// Lists a page of the shortened URLs whose destinations match the blocklist
+/// 
+/// This endpoint:
+/// 1. Verifies the user is an admin
+/// 2. Checks every shortened URL against the current blocklist
+/// 3. Returns a page of the matching URLs along with the rule each one matched
+/// 
+/// # Arguments
+/// * `pool` - Database connection pool
+/// * `username` - The authenticated user's username
+/// * `filter` - The cursor and limit of the page
+/// 
+/// # Returns
+/// HTTP response:
+/// - 200 OK with the page of matching URLs and pagination metadata if successful
+/// - 400 Bad Request if the cursor or limit is invalid
+/// - 403 Forbidden if the user is not an admin
+/// - 500 Internal Server Error if retrieval fails
+#[get("/admin/blocklist/matches")]
+pub async fn get_blocklist_matches(
+    pool: web::Data<PgPool>,
+    username: web::ReqData<String>,
+    filter: web::Query<BlocklistMatchFilter>,
+) -> impl Responder {
+    if !is_admin(&username) {
+        return HttpResponse::Forbidden().json(APIResponse::error_message(
+            "Admin access required".to_string(),
+        ));
+    }
+
+    match list_blocklisted_urls(&filter, pool.get_ref()).await {
+        Ok((urls, pagination)) => HttpResponse::Ok().json(APIResponse::page(urls, pagination)),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Reloads the blocklist from the configured files
+/// 
+/// # Arguments
+/// * `username` - The authenticated user's username
+/// 
+/// # Returns
+/// HTTP response:
+/// - 200 OK with the number of loaded rules if successful
+/// - 403 Forbidden if the user is not an admin
+/// - 500 Internal Server Error if a blocklist file could not be loaded
+#[post("/admin/blocklist/reload")]
+pub async fn reload_blocklist(username: web::ReqData<String>) -> impl Responder {
+    if !is_admin(&username) {
+        return HttpResponse::Forbidden().json(APIResponse::error_message(
+            "Admin access required".to_string(),
+        ));
+    }
+
+    match reload().await {
+        Ok(len) => HttpResponse::Ok().json(APIResponse::data(len)),
+        Err(e) => error_response(std::io::Error::other(e.to_string())),
+    }
+}
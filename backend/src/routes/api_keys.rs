@@ -0,0 +1,131 @@
+use actix_web::{delete, get, post, web, HttpResponse, Responder};
+use serde::Deserialize;
+use sqlx::PgPool;
+
+use crate::{
+    api_keys::{create_api_key, list_api_keys, revoke_api_key, ApiKeyScope},
+    structs::{APIResponse, User},
+    utils::error_response,
+};
+
+/// Request body for creating an API key
+#[derive(Deserialize)]
+struct CreateApiKeyRequest {
+    /// A name to recognize the key by
+    name: String,
+    /// What the key may be used for: `links:read`, `links:write` and `stats:read`
+    scopes: Vec<ApiKeyScope>,
+    /// Optional expiration time in seconds, the key never expires if absent
+    expiration: Option<i64>,
+}
+
+/// Creates an API key for the authenticated user
+/// 
+/// The key is sent as a bearer token in the Authorization header, just like a
+/// JWT. It is only returned by this endpoint, as just its hash is stored.
+/// 
+/// # Arguments
+/// * `body` - The request body containing the name, scopes and expiration
+/// * `pool` - Database connection pool
+/// * `username` - The authenticated user's username
+/// 
+/// # Returns
+/// HTTP response:
+/// - 200 OK with the created key, including the key itself
+/// - 400 Bad Request if the name, scopes or expiration are invalid (with the target field)
+/// - 401 Unauthorized if user not found
+/// - 500 Internal Server Error if creation fails
+#[post("/api-keys")]
+pub async fn create_new_api_key(
+    body: web::Json<CreateApiKeyRequest>,
+    pool: web::Data<PgPool>,
+    username: web::ReqData<String>,
+) -> impl Responder {
+    let user = match sqlx::query_as::<_, User>("SELECT * FROM users WHERE username = $1")
+        .bind(username.to_string())
+        .fetch_one(pool.get_ref())
+        .await
+    {
+        Ok(u) => u,
+        Err(_) => return HttpResponse::Unauthorized().finish(),
+    };
+
+    match create_api_key(
+        &user,
+        &body.name,
+        &body.scopes,
+        body.expiration,
+        pool.get_ref(),
+    )
+    .await
+    {
+        Ok(api_key) => HttpResponse::Ok().json(APIResponse::data(api_key)),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Lists the API keys of the authenticated user
+/// 
+/// # Arguments
+/// * `pool` - Database connection pool
+/// * `username` - The authenticated user's username
+/// 
+/// # Returns
+/// HTTP response:
+/// - 200 OK with the keys, without the keys themselves
+/// - 401 Unauthorized if user not found
+/// - 500 Internal Server Error if retrieval fails
+#[get("/api-keys")]
+pub async fn get_api_keys(
+    pool: web::Data<PgPool>,
+    username: web::ReqData<String>,
+) -> impl Responder {
+    let user = match sqlx::query_as::<_, User>("SELECT * FROM users WHERE username = $1")
+        .bind(username.to_string())
+        .fetch_one(pool.get_ref())
+        .await
+    {
+        Ok(u) => u,
+        Err(_) => return HttpResponse::Unauthorized().finish(),
+    };
+
+    match list_api_keys(&user, pool.get_ref()).await {
+        Ok(api_keys) => HttpResponse::Ok().json(APIResponse::data(api_keys)),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Revokes an API key of the authenticated user
+/// 
+/// # Arguments
+/// * `id` - The ID of the key
+/// * `pool` - Database connection pool
+/// * `username` - The authenticated user's username
+/// 
+/// # Returns
+/// HTTP response:
+/// - 204 No Content if successful
+/// - 400 Bad Request if the ID is invalid
+/// - 401 Unauthorized if user not found
+/// - 404 Not Found if the key doesn't exist or belongs to another user
+/// - 500 Internal Server Error if revoking fails
+#[delete("/api-keys/{id}")]
+pub async fn remove_api_key(
+    id: web::Path<String>,
+    pool: web::Data<PgPool>,
+    username: web::ReqData<String>,
+) -> impl Responder {
+    let user = match sqlx::query_as::<_, User>("SELECT * FROM users WHERE username = $1")
+        .bind(username.to_string())
+        .fetch_one(pool.get_ref())
+        .await
+    {
+        Ok(u) => u,
+        Err(_) => return HttpResponse::Unauthorized().finish(),
+    };
+
+    match revoke_api_key(&user, &id, pool.get_ref()).await {
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(e) => error_response(e),
+    }
+}
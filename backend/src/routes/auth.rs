@@ -4,9 +4,14 @@ use chrono::{Duration, Utc};
 use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
 use serde::{Deserialize, Serialize};
 use sqlx::PgPool;
+use uuid::Uuid;
 
 use crate::{
-    constants::NURL_SECRET,
+    constants::{ACCESS_TOKEN_MINUTES, NURL_SECRET},
+    sessions::{
+        create_session, is_session_active, refresh_session, revoke_session, ClientInfo,
+        IssuedSession, SessionRefresh,
+    },
     structs::{APIResponse, Claims, User},
 };
 
@@ -21,11 +26,22 @@ pub struct LoginForm {
     pub remember_me: bool,
 }
 
+/// Request body for refreshing or logging out of a session
+#[derive(Deserialize)]
+pub struct RefreshRequest {
+    /// The refresh token of the session
+    pub refresh_token: String,
+}
+
 /// JWT token response structure
 #[derive(Serialize)]
 pub struct TokenResponse {
-    /// The generated JWT token
+    /// The generated JWT access token
     pub token: String,
+    /// The refresh token to exchange for the next access token, usable once
+    pub refresh_token: String,
+    /// The number of seconds the access token is valid for
+    pub expires_in: i64,
 }
 
 /// Finds a user by username in the database
@@ -55,34 +71,50 @@ fn validate_password(password: &str, hash: &str) -> Result<bool, bcrypt::BcryptE
     verify(password, hash)
 }
 
-/// Generates a JWT token for the user
+/// Generates a short-lived JWT access token for a session
 /// 
 /// # Arguments
 /// * `username` - The username to include in the token
-/// * `remember_me` - Whether to create a longer-lived token
+/// * `session_id` - The ID of the session the token belongs to
 /// 
 /// # Returns
 /// Result containing the generated token string
-fn generate_token(
-    username: &str,
-    remember_me: bool,
-) -> Result<String, jsonwebtoken::errors::Error> {
-    let expiration = if remember_me {
-        Utc::now() + Duration::days(30)
-    } else {
-        Utc::now() + Duration::hours(1)
-    };
+fn generate_token(username: &str, session_id: Uuid) -> Result<String, jsonwebtoken::errors::Error> {
+    let expiration = Utc::now() + Duration::minutes(*ACCESS_TOKEN_MINUTES);
 
     encode(
         &Header::default(),
         &Claims {
             username: username.to_string(),
+            sid: session_id,
             exp: expiration.timestamp() as usize,
         },
         &EncodingKey::from_secret((*NURL_SECRET).as_bytes()),
     )
 }
 
+/// Responds with an access token and the refresh token of a session
+/// 
+/// # Arguments
+/// * `session` - The session the tokens belong to
+/// 
+/// # Returns
+/// HTTP response:
+/// - 200 OK with the tokens
+/// - 500 Internal Server Error if token generation fails
+fn token_response(session: IssuedSession) -> HttpResponse {
+    match generate_token(&session.username, session.session_id) {
+        Ok(token) => HttpResponse::Ok().json(APIResponse::data(TokenResponse {
+            token,
+            refresh_token: session.refresh_token,
+            expires_in: *ACCESS_TOKEN_MINUTES * 60,
+        })),
+        Err(_) => HttpResponse::InternalServerError().json(APIResponse::error_message(
+            "Failed to generate token".to_string(),
+        )),
+    }
+}
+
 /// Validates a JWT token and returns its claims
 /// 
 /// # Arguments
@@ -120,19 +152,25 @@ fn extract_token_from_header(req: &HttpRequest) -> Option<String> {
 /// This endpoint:
 /// 1. Verifies the user exists
 /// 2. Validates the password
-/// 3. Generates a JWT token
+/// 3. Starts a session, lasting 30 days with `remember_me` and an hour otherwise
+/// 4. Generates a short-lived JWT access token along with a refresh token
 /// 
 /// # Arguments
+/// * `req` - The HTTP request, identifying the device logging in
 /// * `form` - The login form data
 /// * `db` - Database connection pool
 /// 
 /// # Returns
 /// HTTP response:
-/// - 200 OK with the JWT token if successful
+/// - 200 OK with the JWT access token and refresh token if successful
 /// - 422 Unprocessable Entity if credentials are invalid
-/// - 500 Internal Server Error if token generation fails
+/// - 500 Internal Server Error if the session can't be started
 #[post("/auth")]
-pub async fn login(form: web::Json<LoginForm>, db: web::Data<PgPool>) -> impl Responder {
+pub async fn login(
+    req: HttpRequest,
+    form: web::Json<LoginForm>,
+    db: web::Data<PgPool>,
+) -> impl Responder {
     // Find user in database
     let user = match find_user(&form.username, db.get_ref()).await {
         Err(_) => {
@@ -161,17 +199,87 @@ pub async fn login(form: web::Json<LoginForm>, db: web::Data<PgPool>) -> impl Re
         ));
     }
 
-    // Generate token
-    let jwt = match generate_token(&form.username, form.remember_me) {
-        Ok(token) => token,
-        Err(_) => {
-            return HttpResponse::InternalServerError().json(APIResponse::error_message(
-                "Failed to generate token".to_string(),
-            ));
+    // Start the session
+    match create_session(
+        &user,
+        form.remember_me,
+        &ClientInfo::from_request(&req),
+        db.get_ref(),
+    )
+    .await
+    {
+        Ok(session) => token_response(session),
+        Err(_) => HttpResponse::InternalServerError().json(APIResponse::error_message(
+            "Failed to start session".to_string(),
+        )),
+    }
+}
+
+/// Exchanges a refresh token for a new access token and refresh token
+/// 
+/// Every refresh token can only be used once. Using one again revokes its
+/// whole session, as it means the token was stolen.
+/// 
+/// # Arguments
+/// * `req` - The HTTP request, identifying the device refreshing
+/// * `body` - The request body containing the refresh token
+/// * `db` - Database connection pool
+/// 
+/// # Returns
+/// HTTP response:
+/// - 200 OK with the new JWT access token and refresh token
+/// - 401 Unauthorized if the refresh token is invalid, was already used, or its
+///   session was revoked or expired
+/// - 500 Internal Server Error if refreshing fails
+#[post("/auth/refresh")]
+pub async fn refresh(
+    req: HttpRequest,
+    body: web::Json<RefreshRequest>,
+    db: web::Data<PgPool>,
+) -> impl Responder {
+    match refresh_session(
+        &body.refresh_token,
+        &ClientInfo::from_request(&req),
+        db.get_ref(),
+    )
+    .await
+    {
+        Ok(SessionRefresh::Refreshed(session)) => token_response(session),
+        Ok(SessionRefresh::Reused) => {
+            HttpResponse::Unauthorized().json(APIResponse::error_message(
+                "Refresh token was already used, its session has been revoked".to_string(),
+            ))
         }
-    };
+        Ok(SessionRefresh::Invalid) => HttpResponse::Unauthorized().json(
+            APIResponse::error_message("Invalid or expired refresh token".to_string()),
+        ),
+        Err(_) => HttpResponse::InternalServerError().json(APIResponse::error_message(
+            "Failed to refresh session".to_string(),
+        )),
+    }
+}
 
-    HttpResponse::Ok().json(APIResponse::data(TokenResponse { token: jwt }))
+/// Logs out of a session, revoking its refresh tokens and access tokens
+/// 
+/// # Arguments
+/// * `body` - The request body containing the refresh token of the session
+/// * `db` - Database connection pool
+/// 
+/// # Returns
+/// HTTP response:
+/// - 204 No Content if successful
+/// - 401 Unauthorized if the refresh token is invalid
+/// - 500 Internal Server Error if logging out fails
+#[post("/auth/logout")]
+pub async fn logout(body: web::Json<RefreshRequest>, db: web::Data<PgPool>) -> impl Responder {
+    match revoke_session(&body.refresh_token, db.get_ref()).await {
+        Ok(true) => HttpResponse::NoContent().finish(),
+        Ok(false) => HttpResponse::Unauthorized().json(APIResponse::error_message(
+            "Invalid refresh token".to_string(),
+        )),
+        Err(_) => HttpResponse::InternalServerError()
+            .json(APIResponse::error_message("Failed to log out".to_string())),
+    }
 }
 
 /// Validates a user's authentication token
@@ -179,16 +287,18 @@ pub async fn login(form: web::Json<LoginForm>, db: web::Data<PgPool>) -> impl Re
 /// This endpoint:
 /// 1. Extracts the token from the Authorization header
 /// 2. Validates the token
+/// 3. Checks that the token's session wasn't revoked
 /// 
 /// # Arguments
 /// * `req` - The HTTP request containing the token
+/// * `db` - Database connection pool
 /// 
 /// # Returns
 /// HTTP response:
 /// - 200 OK if the token is valid
-/// - 401 Unauthorized if the token is missing or invalid
+/// - 401 Unauthorized if the token is missing, invalid or its session was revoked
 #[get("/auth")]
-async fn is_authenticated(req: HttpRequest) -> impl Responder {
+async fn is_authenticated(req: HttpRequest, db: web::Data<PgPool>) -> impl Responder {
     // Extract token from headers
     let token = match extract_token_from_header(&req) {
         Some(token) => token,
@@ -199,10 +309,23 @@ async fn is_authenticated(req: HttpRequest) -> impl Responder {
     };
 
     // Validate token
-    match validate_token(&token) {
-        Ok(_) => HttpResponse::Ok().json(APIResponse::data("authenticated")),
-        Err(_) => HttpResponse::Unauthorized()
-            .json(APIResponse::error_message("Invalid token".to_string())),
+    let claims = match validate_token(&token) {
+        Ok(claims) => claims,
+        Err(_) => {
+            return HttpResponse::Unauthorized()
+                .json(APIResponse::error_message("Invalid token".to_string()));
+        }
+    };
+
+    // Check the session
+    match is_session_active(claims.sid, &claims.username, db.get_ref()).await {
+        Ok(true) => HttpResponse::Ok().json(APIResponse::data("authenticated")),
+        Ok(false) => HttpResponse::Unauthorized().json(APIResponse::error_message(
+            "Session has been revoked or expired".to_string(),
+        )),
+        Err(_) => HttpResponse::InternalServerError().json(APIResponse::error_message(
+            "Could not verify session".to_string(),
+        )),
     }
 }
 
@@ -259,30 +382,13 @@ mod tests {
         assert!(!result.unwrap());
     }
 
-    /// Tests JWT token generation with different expiration times
+    /// Tests JWT access token generation
     #[test]
     fn test_generate_token() {
         let username = "test_user";
+        let session_id = Uuid::new_v4();
 
-        // Test token with remember_me = false (1 hour)
-        let token = generate_token(username, false).unwrap();
-        let claims = decode::<Claims>(
-            &token,
-            &DecodingKey::from_secret(NURL_SECRET.as_bytes()),
-            &Validation::default(),
-        )
-        .unwrap()
-        .claims;
-
-        assert_eq!(claims.username, username);
-        // Verify expiration is roughly 1 hour in the future (with small margin for test execution time)
-        let now = Utc::now().timestamp() as usize;
-        let hour_from_now = now + 3600;
-        assert!(claims.exp > now);
-        assert!(claims.exp <= hour_from_now + 5); // Allow 5 seconds margin
-
-        // Test token with remember_me = true (30 days)
-        let token = generate_token(username, true).unwrap();
+        let token = generate_token(username, session_id).unwrap();
         let claims = decode::<Claims>(
             &token,
             &DecodingKey::from_secret(NURL_SECRET.as_bytes()),
@@ -292,11 +398,12 @@ mod tests {
         .claims;
 
         assert_eq!(claims.username, username);
-        // Verify expiration is roughly 30 days in the future
+        assert_eq!(claims.sid, session_id);
+        // Verify expiration is roughly ACCESS_TOKEN_MINUTES in the future
         let now = Utc::now().timestamp() as usize;
-        let month_from_now = now + (30 * 24 * 3600);
+        let access_lifetime = (*ACCESS_TOKEN_MINUTES * 60) as usize;
         assert!(claims.exp > now);
-        assert!(claims.exp <= month_from_now + 5); // Allow 5 seconds margin
+        assert!(claims.exp <= now + access_lifetime + 5); // Allow 5 seconds margin
     }
 
     /// Tests JWT token validation
@@ -310,6 +417,7 @@ mod tests {
             &Header::default(),
             &Claims {
                 username: username.to_string(),
+                sid: Uuid::new_v4(),
                 exp: expiration.timestamp() as usize,
             },
             &EncodingKey::from_secret((*NURL_SECRET).as_bytes()),
@@ -328,6 +436,7 @@ mod tests {
             &Header::default(),
             &Claims {
                 username: username.to_string(),
+                sid: Uuid::new_v4(),
                 exp: expired_time.timestamp() as usize,
             },
             &EncodingKey::from_secret((*NURL_SECRET).as_bytes()),
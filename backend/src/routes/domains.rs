@@ -0,0 +1,162 @@
+use actix_web::{delete, get, post, web, HttpResponse, Responder};
+use serde::Deserialize;
+use sqlx::PgPool;
+
+use crate::{
+    domain_verification::{verify_domain, SystemResolver},
+    domains::{add_domain, delete_domain, list_domains},
+    structs::{APIResponse, User},
+    utils::error_response,
+};
+
+/// Request body for adding a custom domain
+#[derive(Deserialize)]
+struct AddDomainRequest {
+    /// The hostname of the domain, such as go.example.com
+    hostname: String,
+}
+
+/// Adds a custom domain links can be served on
+/// 
+/// The domain's DNS has to point at nurl for its links to redirect, and links
+/// can only be created on it once it is verified.
+/// 
+/// # Arguments
+/// * `body` - The request body containing the hostname
+/// * `pool` - Database connection pool
+/// * `username` - The authenticated user's username
+/// 
+/// # Returns
+/// HTTP response:
+/// - 200 OK with the added domain
+/// - 400 Bad Request if the hostname is invalid or already served by nurl
+/// - 401 Unauthorized if user not found
+/// - 409 Conflict if the domain has already been added, unless it is an expired unverified claim of another user
+/// - 500 Internal Server Error if adding fails
+#[post("/domains")]
+pub async fn create_domain(
+    body: web::Json<AddDomainRequest>,
+    pool: web::Data<PgPool>,
+    username: web::ReqData<String>,
+) -> impl Responder {
+    let user = match sqlx::query_as::<_, User>("SELECT * FROM users WHERE username = $1")
+        .bind(username.to_string())
+        .fetch_one(pool.get_ref())
+        .await
+    {
+        Ok(u) => u,
+        Err(_) => return HttpResponse::Unauthorized().finish(),
+    };
+
+    match add_domain(&user, &body.hostname, pool.get_ref()).await {
+        Ok(domain) => HttpResponse::Ok().json(APIResponse::data(domain)),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Lists the custom domains of the authenticated user
+/// 
+/// # Arguments
+/// * `pool` - Database connection pool
+/// * `username` - The authenticated user's username
+/// 
+/// # Returns
+/// HTTP response:
+/// - 200 OK with the domains
+/// - 401 Unauthorized if user not found
+/// - 500 Internal Server Error if retrieval fails
+#[get("/domains")]
+pub async fn get_domains(
+    pool: web::Data<PgPool>,
+    username: web::ReqData<String>,
+) -> impl Responder {
+    let user = match sqlx::query_as::<_, User>("SELECT * FROM users WHERE username = $1")
+        .bind(username.to_string())
+        .fetch_one(pool.get_ref())
+        .await
+    {
+        Ok(u) => u,
+        Err(_) => return HttpResponse::Unauthorized().finish(),
+    };
+
+    match list_domains(&user, pool.get_ref()).await {
+        Ok(domains) => HttpResponse::Ok().json(APIResponse::data(domains)),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Removes a custom domain of the authenticated user
+/// 
+/// # Arguments
+/// * `id` - The ID of the domain
+/// * `pool` - Database connection pool
+/// * `username` - The authenticated user's username
+/// 
+/// # Returns
+/// HTTP response:
+/// - 204 No Content if successful
+/// - 400 Bad Request if the ID is invalid or the domain still has links
+/// - 401 Unauthorized if user not found
+/// - 404 Not Found if the domain doesn't exist or belongs to another user
+/// - 500 Internal Server Error if removal fails
+#[delete("/domains/{id}")]
+pub async fn remove_domain(
+    id: web::Path<String>,
+    pool: web::Data<PgPool>,
+    username: web::ReqData<String>,
+) -> impl Responder {
+    let user = match sqlx::query_as::<_, User>("SELECT * FROM users WHERE username = $1")
+        .bind(username.to_string())
+        .fetch_one(pool.get_ref())
+        .await
+    {
+        Ok(u) => u,
+        Err(_) => return HttpResponse::Unauthorized().finish(),
+    };
+
+    match delete_domain(&user, &id, pool.get_ref()).await {
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Checks a custom domain of the authenticated user for its verification token
+/// 
+/// The domain is verified if a TXT record `nurl-verification=<token>` is
+/// published on `_nurl-verification.<hostname>`, or if
+/// `http://<hostname>/.well-known/nurl-verification.txt` contains the token.
+/// 
+/// # Arguments
+/// * `id` - The ID of the domain
+/// * `resolver` - The resolver to look the records up with
+/// * `pool` - Database connection pool
+/// * `username` - The authenticated user's username
+/// 
+/// # Returns
+/// HTTP response:
+/// - 200 OK with the domain, with `verification_error` set if it isn't verified
+/// - 400 Bad Request if the ID is invalid
+/// - 401 Unauthorized if user not found
+/// - 404 Not Found if the domain doesn't exist or belongs to another user
+/// - 500 Internal Server Error if the check can't be saved
+#[post("/domains/{id}/verify")]
+pub async fn verify_custom_domain(
+    id: web::Path<String>,
+    resolver: web::Data<SystemResolver>,
+    pool: web::Data<PgPool>,
+    username: web::ReqData<String>,
+) -> impl Responder {
+    let user = match sqlx::query_as::<_, User>("SELECT * FROM users WHERE username = $1")
+        .bind(username.to_string())
+        .fetch_one(pool.get_ref())
+        .await
+    {
+        Ok(u) => u,
+        Err(_) => return HttpResponse::Unauthorized().finish(),
+    };
+
+    match verify_domain(&user, &id, resolver.get_ref(), pool.get_ref()).await {
+        Ok(domain) => HttpResponse::Ok().json(APIResponse::data(domain)),
+        Err(e) => error_response(e),
+    }
+}
@@ -1,13 +1,31 @@
 /// Module containing all route handlers for the application
 /// 
 /// This module organizes the route handlers into logical groups:
-/// - auth: Authentication-related routes (login, token validation)
+/// - account: The user's profile, settings and password
+/// - admin: Administrative endpoints (blocklist matches)
+/// - api_keys: Creating, listing and revoking API keys
+/// - auth: Authentication-related routes (login, token refresh, logout, token validation)
+/// - domains: Adding, verifying, listing and removing custom domains
 /// - health: Health check endpoints
 /// - redirect: URL redirection handling
 /// - register: User registration endpoints
+/// - sessions: Listing and revoking the devices a user is logged in on
 /// - shorten: URL shortening endpoints
+/// - tags: Tag listing, renaming and merging
+/// - transfers: Offering, accepting and declining URL transfers between users
+/// - trash: Listing, restoring and purging deleted URLs
+/// - workspaces: Workspaces, their members and invitations
+pub mod account;
+pub mod admin;
+pub mod api_keys;
 pub mod auth;
+pub mod domains;
 pub mod health;
 pub mod redirect;
 pub mod register;
+pub mod sessions;
 pub mod shorten;
+pub mod tags;
+pub mod transfers;
+pub mod trash;
+pub mod workspaces;
@@ -1,34 +1,143 @@
-use actix_web::{get, web, HttpResponse, Responder};
+use actix_web::{get, http::StatusCode, web, HttpRequest, HttpResponse, Responder};
+use serde::Deserialize;
 use sqlx::PgPool;
 
-use crate::structs::ShortenedUrl;
+use crate::{
+    blocklist::{check_url, escape_html},
+    constants::{BLOCKLIST_ACTION, DISABLED_LINK_FALLBACK_URL, DISABLED_LINK_MESSAGE},
+    domains::hostname,
+    structs::ShortenedUrl,
+};
+
+/// Query parameters accepted by the redirect endpoint
+#[derive(Deserialize)]
+pub struct RedirectQuery {
+    /// "true" when the visitor chose to continue past the warning interstitial
+    confirm: Option<String>,
+}
+
+/// Checks whether the visitor chose to continue past the warning interstitial
+/// 
+/// The query string is read leniently, so that a malformed one never breaks a
+/// redirect.
+/// 
+/// # Arguments
+/// * `req` - The HTTP request, used for its query string
+/// 
+/// # Returns
+/// Whether `confirm=true` was sent
+fn is_confirmed(req: &HttpRequest) -> bool {
+    web::Query::<RedirectQuery>::from_query(req.query_string())
+        .is_ok_and(|query| query.confirm.as_deref() == Some("true"))
+}
+
+/// Builds the page shown when a link's destination is on the blocklist
+/// 
+/// # Arguments
+/// * `short_path` - The short URL path that was visited
+/// * `original_url` - The blocklisted destination
+/// 
+/// # Returns
+/// HTTP response:
+/// - 403 Forbidden with an explanation if `BLOCKLIST_ACTION` is "block"
+/// - 200 OK with a warning and a link to continue if `BLOCKLIST_ACTION` is "warn"
+fn blocklisted_response(short_path: &str, original_url: &str) -> HttpResponse {
+    if *BLOCKLIST_ACTION == "warn" {
+        let body = format!(
+            "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>Warning</title></head><body>\
+             <h1>Warning: this link may be dangerous</h1>\
+             <p>The destination of this link has been flagged as malicious:</p>\
+             <p><code>{}</code></p>\
+             <p><a href=\"/{}?confirm=true\" rel=\"noreferrer\">Continue anyway</a></p>\
+             </body></html>",
+            escape_html(original_url),
+            escape_html(short_path),
+        );
+        return HttpResponse::Ok()
+            .content_type("text/html; charset=utf-8")
+            .body(body);
+    }
+
+    HttpResponse::Forbidden()
+        .content_type("text/html; charset=utf-8")
+        .body(
+            "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>Link blocked</title></head><body>\
+             <h1>Link blocked</h1>\
+             <p>The destination of this link has been flagged as malicious.</p>\
+             </body></html>",
+        )
+}
+
+/// Builds the response for a visit to a disabled link
+/// 
+/// # Returns
+/// HTTP response:
+/// - 307 Temporary Redirect to `DISABLED_LINK_FALLBACK_URL` if it is set
+/// - 503 Service Unavailable with `DISABLED_LINK_MESSAGE` otherwise, since the
+///   link is expected to come back
+fn disabled_response() -> HttpResponse {
+    if let Some(fallback_url) = DISABLED_LINK_FALLBACK_URL.as_deref() {
+        return HttpResponse::TemporaryRedirect()
+            .append_header(("Location", fallback_url))
+            .finish();
+    }
+
+    let body = format!(
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>Link disabled</title></head><body>\
+         <h1>Link disabled</h1>\
+         <p>{}</p>\
+         </body></html>",
+        escape_html(&DISABLED_LINK_MESSAGE),
+    );
+    HttpResponse::ServiceUnavailable()
+        .content_type("text/html; charset=utf-8")
+        .body(body)
+}
 
 /// Redirects a short URL to its original destination
 /// 
 /// This endpoint:
-/// 1. Looks up the short URL in the database
-/// 2. Checks if the URL has expired
-/// 3. Increments the redirect counter
-/// 4. Returns a 307 Temporary Redirect to the original URL
+/// 1. Looks up the short URL on the domain the request was made to, falling
+///    back to `APP_DOMAIN` for hosts that aren't custom domains and finding
+///    nothing on custom domains that aren't verified
+/// 2. Checks if the URL has expired or is disabled
+/// 3. Checks the destination against the blocklist
+/// 4. Increments the redirect counter
+/// 5. Redirects to the original URL with the redirect type of the URL
 /// 
 /// # Arguments
+/// * `req` - The HTTP request, used for its host and to continue past the warning interstitial
 /// * `pool` - Database connection pool
 /// * `short_path` - The short URL path to redirect from
 /// 
 /// # Returns
 /// HTTP response:
-/// - 307 Temporary Redirect with Location header if URL is valid
-/// - 404 Not Found if URL doesn't exist or has expired
+/// - 301, 302, 307 or 308, as set by the URL's redirect type, with Location header if URL is valid
+/// - 200 OK with a warning interstitial if the destination is blocklisted and
+///   `BLOCKLIST_ACTION` is "warn"
+/// - 403 Forbidden if the destination is blocklisted and `BLOCKLIST_ACTION` is "block"
+/// - 307 Temporary Redirect to `DISABLED_LINK_FALLBACK_URL` if the URL is disabled and it is set
+/// - 503 Service Unavailable with `DISABLED_LINK_MESSAGE` if the URL is disabled otherwise
+/// - 404 Not Found if URL doesn't exist, is in the trash, has expired or its domain isn't verified
 /// - 500 Internal Server Error if database update fails
 #[get("/{short_path}")]
 pub async fn redirect_to_original_url(
+    req: HttpRequest,
     pool: web::Data<PgPool>,
     short_path: web::Path<String>,
 ) -> impl Responder {
+    let host = hostname(req.connection_info().host());
     let shortened_url = match sqlx::query_as::<_, ShortenedUrl>(
-        "SELECT * FROM shortened_urls WHERE short_url = $1",
+        r#"
+      SELECT * FROM shortened_urls
+      WHERE short_url = $1
+          AND domain IS NOT DISTINCT FROM (SELECT hostname FROM domains WHERE hostname = $2)
+          AND NOT EXISTS(SELECT 1 FROM domains WHERE hostname = $2 AND verified_at IS NULL)
+          AND deleted_at IS NULL
+      "#,
     )
-    .bind(&short_path.to_string())
+    .bind(short_path.to_string())
+    .bind(host)
     .fetch_one(pool.get_ref())
     .await
     {
@@ -36,10 +145,20 @@ pub async fn redirect_to_original_url(
         Err(_) => return HttpResponse::NotFound().finish(),
     };
 
-    if let Some(expiry_date) = shortened_url.expiry_date {
-        if expiry_date < chrono::Utc::now() {
-            return HttpResponse::NotFound().finish();
-        }
+    if let Some(expiry_date) = shortened_url.expiry_date
+        && expiry_date < chrono::Utc::now()
+    {
+        return HttpResponse::NotFound().finish();
+    }
+
+    if !shortened_url.enabled {
+        return disabled_response();
+    }
+
+    if check_url(&shortened_url.original_url).is_some()
+        && (*BLOCKLIST_ACTION == "block" || !is_confirmed(&req))
+    {
+        return blocklisted_response(&short_path, &shortened_url.original_url);
     }
 
     match sqlx::query("UPDATE shortened_urls SET redirects = redirects + 1 WHERE id = $1")
@@ -55,7 +174,9 @@ pub async fn redirect_to_original_url(
     let original_url = shortened_url.original_url;
     println!("Redirecting to: {}", original_url);
 
-    HttpResponse::TemporaryRedirect()
+    let status = StatusCode::from_u16(shortened_url.redirect_type as u16)
+        .unwrap_or(StatusCode::TEMPORARY_REDIRECT);
+    HttpResponse::build(status)
         .append_header(("Location", original_url))
         .finish()
 }
@@ -64,7 +185,16 @@ pub async fn redirect_to_original_url(
 #[cfg(test)]
 mod tests {
 
-    use crate::utils::{get_test_user, init_test_db};
+    use crate::{
+        blocklist::BLOCKLIST,
+        constants::APP_DOMAIN,
+        service::create_url,
+        structs::{Domain, User},
+        utils::{
+            add_test_domain, create_test_user, delete_test_users, get_test_user, init_test_db,
+        },
+        workspaces::Member,
+    };
 
     use super::*;
     use actix_web::{http::StatusCode, test, web, App};
@@ -147,6 +277,86 @@ mod tests {
             .expect("Failed to delete test URL");
     }
 
+    /// Tests that URLs redirect with their own redirect type
+    #[actix_rt::test]
+    async fn test_redirect_type() {
+        let pool = init_test_db().await;
+        let test_user = get_test_user(&pool).await;
+        let test_id = Uuid::new_v4();
+        let short_path = format!("type_{}", &Uuid::new_v4().simple().to_string()[..6]);
+        sqlx::query(
+            "INSERT INTO shortened_urls (id, short_url, original_url, redirects, created_at, updated_at, owner, redirect_type) 
+           VALUES ($1, $2, 'https://example.com', 0, CURRENT_TIMESTAMP, CURRENT_TIMESTAMP, $3, 301)",
+        )
+        .bind(test_id)
+        .bind(&short_path)
+        .bind(test_user.id)
+        .execute(&pool)
+        .await
+        .expect("Failed to insert test data");
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .service(redirect_to_original_url),
+        )
+        .await;
+        let req = test::TestRequest::get()
+            .uri(&format!("/{}", short_path))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::MOVED_PERMANENTLY);
+        assert_eq!(
+            resp.headers().get("Location").unwrap(),
+            "https://example.com"
+        );
+
+        sqlx::query("DELETE FROM shortened_urls WHERE id = $1")
+            .bind(test_id)
+            .execute(&pool)
+            .await
+            .expect("Failed to delete test URL");
+    }
+
+    /// Tests that a malformed confirmation in the query string doesn't break redirects
+    #[actix_rt::test]
+    async fn test_redirect_invalid_confirm() {
+        let pool = init_test_db().await;
+        let test_user = get_test_user(&pool).await;
+        let test_id = Uuid::new_v4();
+        let short_path = format!("confirm_{}", &Uuid::new_v4().simple().to_string()[..6]);
+        sqlx::query(
+            "INSERT INTO shortened_urls (id, short_url, original_url, redirects, created_at, updated_at, owner) 
+           VALUES ($1, $2, 'https://example.com', 0, CURRENT_TIMESTAMP, CURRENT_TIMESTAMP, $3)",
+        )
+        .bind(test_id)
+        .bind(&short_path)
+        .bind(test_user.id)
+        .execute(&pool)
+        .await
+        .expect("Failed to insert test data");
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .service(redirect_to_original_url),
+        )
+        .await;
+        for query in ["confirm=1", "confirm=yes", "confirm=", "confirm=%zz"] {
+            let req = test::TestRequest::get()
+                .uri(&format!("/{}?{}", short_path, query))
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), StatusCode::TEMPORARY_REDIRECT);
+        }
+
+        sqlx::query("DELETE FROM shortened_urls WHERE id = $1")
+            .bind(test_id)
+            .execute(&pool)
+            .await
+            .expect("Failed to delete test URL");
+    }
+
     /// Tests handling of non-existent short URLs
     /// 
     /// This test verifies that the endpoint returns a 404 response
@@ -237,4 +447,233 @@ mod tests {
             .await
             .expect("Failed to delete test URL");
     }
+
+    /// Tests that a disabled short URL shows the disabled page without counting the visit
+    #[actix_rt::test]
+    async fn test_redirect_disabled() {
+        let pool = init_test_db().await;
+        let test_user = create_test_user(&pool, "redirect_disabled").await;
+
+        // Set up test data with a disabled link and unique short path
+        let test_id = Uuid::new_v4();
+        let short_path = format!(
+            "disabled_{}",
+            Uuid::new_v4()
+                .to_string()
+                .chars()
+                .take(6)
+                .collect::<String>()
+        );
+
+        sqlx::query(
+            "INSERT INTO shortened_urls (id, short_url, original_url, redirects, created_at, updated_at, owner, enabled) 
+           VALUES ($1, $2, $3, 0, CURRENT_TIMESTAMP, CURRENT_TIMESTAMP, $4, FALSE)",
+        )
+        .bind(test_id)
+        .bind(&short_path)
+        .bind("https://example.com")
+        .bind(test_user.id)
+        .execute(&pool)
+        .await
+        .expect("Failed to insert test data");
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .service(redirect_to_original_url),
+        )
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/{}", short_path))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        // Without a fallback URL the disabled message is shown
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let body = test::read_body(resp).await;
+        assert!(String::from_utf8_lossy(&body).contains(DISABLED_LINK_MESSAGE.as_str()));
+
+        let url = sqlx::query_as::<_, ShortenedUrl>("SELECT * FROM shortened_urls WHERE id = $1")
+            .bind(test_id)
+            .fetch_one(&pool)
+            .await
+            .expect("Failed to fetch url");
+        assert_eq!(url.redirects, 0);
+
+        delete_test_users(&pool, &[test_user.id]).await;
+    }
+
+    /// Tests handling of short URLs whose destination is on the blocklist
+    /// 
+    /// This test:
+    /// 1. Creates a short URL pointing to a domain
+    /// 2. Adds the domain to the blocklist after the link was created
+    /// 3. Verifies that the redirect is refused and not counted
+    #[actix_rt::test]
+    async fn test_redirect_blocklisted() {
+        let pool = init_test_db().await;
+        let test_user = get_test_user(&pool).await;
+
+        // Set up test data with a unique domain and short path
+        let test_id = Uuid::new_v4();
+        let unique = Uuid::new_v4()
+            .to_string()
+            .chars()
+            .take(6)
+            .collect::<String>();
+        let short_path = format!("blocked_{}", unique);
+        let blocked_domain = format!("phish-{}.example", unique);
+        let original_url = format!("https://{}/login", blocked_domain);
+
+        sqlx::query(
+            "INSERT INTO shortened_urls (id, short_url, original_url, redirects, created_at, updated_at, owner) 
+           VALUES ($1, $2, $3, 0, CURRENT_TIMESTAMP, CURRENT_TIMESTAMP, $4)",
+        )
+        .bind(test_id)
+        .bind(&short_path)
+        .bind(&original_url)
+        .bind(test_user.id)
+        .execute(&pool)
+        .await
+        .expect("Failed to insert test data");
+
+        // The domain becomes listed after the link was created
+        BLOCKLIST.write().unwrap().parse(&blocked_domain);
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .service(redirect_to_original_url),
+        )
+        .await;
+
+        // Send test request, even confirming must not get through when blocking
+        let req = test::TestRequest::get()
+            .uri(&format!("/{}?confirm=true", short_path))
+            .to_request();
+
+        let resp = test::call_service(&app, req).await;
+
+        // Assert the redirect was refused
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+        assert!(resp.headers().get("Location").is_none());
+
+        // Verify the redirect was not counted
+        let url = sqlx::query_as::<_, ShortenedUrl>("SELECT * FROM shortened_urls WHERE id = $1")
+            .bind(test_id)
+            .fetch_one(&pool)
+            .await
+            .expect("Failed to fetch url");
+        assert_eq!(url.redirects, 0);
+
+        // Clean up the specific test data
+        sqlx::query("DELETE FROM shortened_urls WHERE id = $1")
+            .bind(test_id)
+            .execute(&pool)
+            .await
+            .expect("Failed to delete test URL");
+    }
+
+    /// Creates URLs with the same code on `APP_DOMAIN` and on a verified custom domain
+    /// 
+    /// # Arguments
+    /// * `user` - The user owning the URLs and the domain
+    /// * `pool` - Database connection pool
+    /// 
+    /// # Returns
+    /// The custom domain and the shared short code
+    async fn create_urls_on_both_hosts(user: &User, pool: &PgPool) -> (Domain, String) {
+        let member = Member::personal(user);
+        let short_path = format!("host_{}", &Uuid::new_v4().simple().to_string()[..8]);
+
+        let domain = add_test_domain(pool, user, true).await;
+        for (hostname, original_url) in [
+            (None, "https://example.com/default"),
+            (
+                Some(domain.hostname.as_str()),
+                "https://example.com/branded",
+            ),
+        ] {
+            create_url(
+                &member,
+                original_url,
+                Some(short_path.clone()),
+                hostname,
+                None,
+                &[],
+                None,
+                pool,
+            )
+            .await
+            .unwrap();
+        }
+        (domain, short_path)
+    }
+
+    /// Tests that each domain redirects to its own destination, unknown hosts falling back to `APP_DOMAIN`
+    #[actix_rt::test]
+    async fn test_redirect_by_host() {
+        let pool = init_test_db().await;
+        let user = create_test_user(&pool, "redirect_host").await;
+        let (domain, short_path) = create_urls_on_both_hosts(&user, &pool).await;
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .service(redirect_to_original_url),
+        )
+        .await;
+
+        for (host, expected) in [
+            (
+                format!("{}:443", domain.hostname.to_uppercase()),
+                "https://example.com/branded",
+            ),
+            (APP_DOMAIN.to_string(), "https://example.com/default"),
+            (
+                format!("unknown-{}", domain.hostname),
+                "https://example.com/default",
+            ),
+        ] {
+            let req = test::TestRequest::get()
+                .uri(&format!("/{}", short_path))
+                .insert_header(("Host", host))
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), StatusCode::TEMPORARY_REDIRECT);
+            assert_eq!(resp.headers().get("Location").unwrap(), expected);
+        }
+
+        delete_test_users(&pool, &[user.id]).await;
+    }
+
+    /// Tests that domains that lost their verification don't redirect
+    #[actix_rt::test]
+    async fn test_redirect_unverified_host() {
+        let pool = init_test_db().await;
+        let user = create_test_user(&pool, "redirect_unverified").await;
+        let (domain, short_path) = create_urls_on_both_hosts(&user, &pool).await;
+        sqlx::query("UPDATE domains SET verified_at = NULL WHERE id = $1")
+            .bind(domain.id)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .service(redirect_to_original_url),
+        )
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/{}", short_path))
+            .insert_header(("Host", domain.hostname))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+        delete_test_users(&pool, &[user.id]).await;
+    }
 }
@@ -2,8 +2,12 @@ use actix_web::{post, web, HttpResponse, Responder};
 use bcrypt::{hash, DEFAULT_COST};
 use serde::{Deserialize, Serialize};
 use sqlx::PgPool;
+use uuid::Uuid;
 
-use crate::structs::APIResponse;
+use crate::{
+    account::validate_new_password, structs::APIResponse, utils::error_response,
+    workspaces::create_personal_workspace,
+};
 
 /// Registration form data structure
 #[derive(Deserialize, Serialize)]
@@ -29,7 +33,7 @@ struct RegisterFormInputTarget {
 /// 1. Validates the registration form data
 /// 2. Checks if the username is already taken
 /// 3. Hashes the password
-/// 4. Creates the new user account
+/// 4. Creates the new user account along with their personal workspace
 /// 
 /// # Arguments
 /// * `form` - The registration form data
@@ -62,31 +66,8 @@ async fn register(form: web::Json<RegisterForm>, pool: web::Data<PgPool>) -> imp
         ));
     }
 
-    if form.password.is_empty() {
-        return HttpResponse::BadRequest().json(APIResponse::error(
-            "Password cannot be empty".to_string(),
-            Some(RegisterFormInputTarget {
-                target_field: "password".to_string(),
-            }),
-        ));
-    }
-
-    if form.password.len() < 6 {
-        return HttpResponse::BadRequest().json(APIResponse::error(
-            "Password must be at least 6 characters long".to_string(),
-            Some(RegisterFormInputTarget {
-                target_field: "password".to_string(),
-            }),
-        ));
-    }
-
-    if form.password != form.confirm_password {
-        return HttpResponse::BadRequest().json(APIResponse::error(
-            "Passwords do not match".to_string(),
-            Some(RegisterFormInputTarget {
-                target_field: "confirm_password".to_string(),
-            }),
-        ));
+    if let Err(e) = validate_new_password(&form.password, &form.confirm_password) {
+        return error_response(e);
     }
 
     let exists: (i64,) = match sqlx::query_as("SELECT COUNT(*) FROM users WHERE username = $1")
@@ -120,17 +101,32 @@ async fn register(form: web::Json<RegisterForm>, pool: web::Data<PgPool>) -> imp
         }
     };
 
-    let result = sqlx::query("INSERT INTO users (username, password) VALUES ($1, $2) RETURNING id")
-        .bind(&form.username)
-        .bind(&hashed)
-        .fetch_one(pool.get_ref())
-        .await;
+    let Ok(mut tx) = pool.begin().await else {
+        return HttpResponse::InternalServerError().json(APIResponse::error_message(
+            "Could not create user".to_string(),
+        ));
+    };
+
+    let result: Result<(Uuid,), _> =
+        sqlx::query_as("INSERT INTO users (username, password) VALUES ($1, $2) RETURNING id")
+            .bind(&form.username)
+            .bind(&hashed)
+            .fetch_one(&mut *tx)
+            .await;
+
+    let created = match result {
+        Ok((id,)) => create_personal_workspace(id, &form.username, &mut *tx)
+            .await
+            .is_ok(),
+        Err(_) => false,
+    };
 
-    match result {
-        Ok(_) => HttpResponse::Ok().finish(),
-        Err(_) => HttpResponse::InternalServerError().json(APIResponse::error_message(
+    if created && tx.commit().await.is_ok() {
+        HttpResponse::Ok().finish()
+    } else {
+        HttpResponse::InternalServerError().json(APIResponse::error_message(
             "Could not create user".to_string(),
-        )),
+        ))
     }
 }
 
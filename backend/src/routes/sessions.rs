@@ -0,0 +1,123 @@
+use actix_web::{delete, get, post, web, HttpResponse, Responder};
+use serde::Serialize;
+use sqlx::PgPool;
+
+use crate::{
+    sessions::{list_sessions, revoke_other_sessions, revoke_user_session, CurrentSession},
+    structs::{APIResponse, User},
+    utils::error_response,
+};
+
+/// Response body for revoking the other sessions of a user
+#[derive(Serialize)]
+struct RevokedSessions {
+    /// The number of sessions that were revoked
+    revoked: u64,
+}
+
+/// Lists the devices the authenticated user is logged in on
+/// 
+/// # Arguments
+/// * `pool` - Database connection pool
+/// * `username` - The authenticated user's username
+/// * `current` - The session the request was made with
+/// 
+/// # Returns
+/// HTTP response:
+/// - 200 OK with the active sessions, the current one marked with `current`
+/// - 401 Unauthorized if user not found
+/// - 500 Internal Server Error if retrieval fails
+#[get("/sessions")]
+pub async fn get_sessions(
+    pool: web::Data<PgPool>,
+    username: web::ReqData<String>,
+    current: Option<web::ReqData<CurrentSession>>,
+) -> impl Responder {
+    let user = match sqlx::query_as::<_, User>("SELECT * FROM users WHERE username = $1")
+        .bind(username.to_string())
+        .fetch_one(pool.get_ref())
+        .await
+    {
+        Ok(u) => u,
+        Err(_) => return HttpResponse::Unauthorized().finish(),
+    };
+
+    match list_sessions(&user, current.map(|c| c.0), pool.get_ref()).await {
+        Ok(sessions) => HttpResponse::Ok().json(APIResponse::data(sessions)),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Revokes a session of the authenticated user, logging that device out
+/// 
+/// Its access tokens stop working right away and its refresh token can't be
+/// used anymore. Revoking the current session logs the user out.
+/// 
+/// # Arguments
+/// * `id` - The ID of the session
+/// * `pool` - Database connection pool
+/// * `username` - The authenticated user's username
+/// 
+/// # Returns
+/// HTTP response:
+/// - 204 No Content if successful
+/// - 400 Bad Request if the ID is invalid
+/// - 401 Unauthorized if user not found
+/// - 404 Not Found if the session doesn't exist, belongs to another user or already ended
+/// - 500 Internal Server Error if revoking fails
+#[delete("/sessions/{id}")]
+pub async fn remove_session(
+    id: web::Path<String>,
+    pool: web::Data<PgPool>,
+    username: web::ReqData<String>,
+) -> impl Responder {
+    let user = match sqlx::query_as::<_, User>("SELECT * FROM users WHERE username = $1")
+        .bind(username.to_string())
+        .fetch_one(pool.get_ref())
+        .await
+    {
+        Ok(u) => u,
+        Err(_) => return HttpResponse::Unauthorized().finish(),
+    };
+
+    match revoke_user_session(&user, &id, pool.get_ref()).await {
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Revokes every session of the authenticated user except the current one
+/// 
+/// # Arguments
+/// * `pool` - Database connection pool
+/// * `username` - The authenticated user's username
+/// * `current` - The session the request was made with
+/// 
+/// # Returns
+/// HTTP response:
+/// - 200 OK with the number of revoked sessions
+/// - 401 Unauthorized if user not found or the request wasn't made with a session
+/// - 500 Internal Server Error if revoking fails
+#[post("/sessions/revoke-others")]
+pub async fn remove_other_sessions(
+    pool: web::Data<PgPool>,
+    username: web::ReqData<String>,
+    current: Option<web::ReqData<CurrentSession>>,
+) -> impl Responder {
+    let Some(current) = current else {
+        return HttpResponse::Unauthorized().finish();
+    };
+    let user = match sqlx::query_as::<_, User>("SELECT * FROM users WHERE username = $1")
+        .bind(username.to_string())
+        .fetch_one(pool.get_ref())
+        .await
+    {
+        Ok(u) => u,
+        Err(_) => return HttpResponse::Unauthorized().finish(),
+    };
+
+    match revoke_other_sessions(&user, Some(current.0), pool.get_ref()).await {
+        Ok(revoked) => HttpResponse::Ok().json(APIResponse::data(RevokedSessions { revoked })),
+        Err(e) => error_response(e),
+    }
+}
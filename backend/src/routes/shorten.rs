@@ -1,10 +1,23 @@
-use actix_web::{delete, get, post, put, web, HttpResponse, Responder};
+use actix_web::{
+    delete, get, http::header, patch, post, put, web, HttpMessage, HttpRequest, HttpResponse,
+    Responder,
+};
 use serde::Deserialize;
 use sqlx::PgPool;
 
 use crate::{
-    service::{create_url, delete_url, list_urls, update_url},
-    structs::{APIResponse, User},
+    bulk::{
+        bulk_create_urls, bulk_update_urls, parse_csv_rows, parse_json_rows, BulkUpdateRequest,
+    },
+    history::{list_revisions, restore_revision},
+    idempotency::{create_url_idempotent, IdempotentCreation},
+    routes::workspaces::request_member,
+    service::{
+        create_url, delete_url, list_urls, patch_url, refresh_url_metadata, set_url_enabled,
+        update_url, LinkFilter, LinkPatch,
+    },
+    structs::APIResponse,
+    utils::{error_response, link_response},
 };
 
 /// Request body for creating a new shortened URL
@@ -14,8 +27,14 @@ struct ShortenURLRequest {
     original_url: String,
     /// Optional custom path for the shortened URL
     custom_path: Option<String>,
-    /// Optional expiration time in seconds
+    /// Optional custom domain to serve the URL on, `APP_DOMAIN` if omitted
+    domain: Option<String>,
+    /// Optional expiration time in seconds, the user's default expiration if omitted
     expiration: Option<i64>,
+    /// Optional tags to attach to the URL
+    tags: Option<Vec<String>>,
+    /// Optional notes about the URL
+    notes: Option<String>,
 }
 
 /// Request body for updating an existing shortened URL
@@ -29,52 +48,207 @@ struct UpdateURLRequest {
     custom_path: Option<String>,
     /// Optional new expiration time in seconds
     expiration: Option<i64>,
+    /// Optional new tags, leaving the tags unchanged if omitted
+    tags: Option<Vec<String>>,
+    /// Optional new notes, leaving the notes unchanged if omitted
+    notes: Option<String>,
+}
+
+/// Request body for enabling or disabling a shortened URL
+#[derive(Deserialize)]
+struct SetEnabledRequest {
+    /// Whether the URL should redirect
+    enabled: bool,
 }
 
+/// The header clients send to safely retry creating a shortened URL
+const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+
 /// Creates a new shortened URL
 /// 
 /// This endpoint:
 /// 1. Verifies the user's authentication
-/// 2. Creates a new shortened URL
+/// 2. Creates a new shortened URL, or returns the original response if the
+///    request is a retry with the same `Idempotency-Key`
 /// 3. Returns the created URL data
 /// 
 /// # Arguments
+/// * `req` - The HTTP request, holding the optional `Idempotency-Key` header and the optional `Workspace-Id` header
 /// * `body` - The request body containing URL details
 /// * `pool` - Database connection pool
 /// * `username` - The authenticated user's username
 /// 
 /// # Returns
 /// HTTP response:
-/// - 200 OK with the created URL data if successful
+/// - 200 OK with the created URL data and its ETag if successful
+/// - 200 OK with the original URL data and `Idempotent-Replayed: true` if the request is a retry
+/// - 400 Bad Request if the URL, custom path, domain or Idempotency-Key is invalid (with the target field)
 /// - 401 Unauthorized if user not found
+/// - 403 Forbidden if the user's role in the workspace doesn't allow it
+/// - 404 Not Found if the user isn't a member of the selected workspace
+/// - 409 Conflict if the custom path is already used on the domain (with the target field) or
+///   reserved for the owner of a deleted URL
+/// - 422 Unprocessable Entity if the Idempotency-Key was used for a different request
 /// - 500 Internal Server Error if creation fails
 #[post("/shorten")]
 pub async fn shorten_url(
+    req: HttpRequest,
     body: web::Json<ShortenURLRequest>,
     pool: web::Data<PgPool>,
     username: web::ReqData<String>,
 ) -> impl Responder {
-    let user = match sqlx::query_as::<_, User>("SELECT * FROM users WHERE username = $1")
-        .bind(&username.to_string())
-        .fetch_one(pool.get_ref())
-        .await
-    {
-        Ok(u) => u,
-        Err(_) => return HttpResponse::Unauthorized().finish(),
+    let member = match request_member(&req, &username, pool.get_ref()).await {
+        Ok(m) => m,
+        Err(response) => return response,
     };
+
+    if let Some(key) = req.headers().get(IDEMPOTENCY_KEY_HEADER) {
+        let Ok(key) = key.to_str() else {
+            return HttpResponse::BadRequest().json(APIResponse::error_message(
+                "Idempotency-Key must only contain visible ASCII characters".to_string(),
+            ));
+        };
+        return match create_url_idempotent(
+            &member,
+            key,
+            &body.original_url,
+            body.custom_path.clone(),
+            body.domain.as_deref(),
+            body.expiration,
+            body.tags.as_deref().unwrap_or_default(),
+            body.notes.as_deref(),
+            pool.get_ref(),
+        )
+        .await
+        {
+            Ok(IdempotentCreation::Created(url)) => link_response(*url),
+            Ok(IdempotentCreation::Replayed(url)) => HttpResponse::Ok()
+                .insert_header(("Idempotent-Replayed", "true"))
+                .json(APIResponse::data(url)),
+            Err(e) => error_response(e),
+        };
+    }
+
     match create_url(
-        &user,
+        &member,
         &body.original_url,
         body.custom_path.clone(),
+        body.domain.as_deref(),
         body.expiration,
+        body.tags.as_deref().unwrap_or_default(),
+        body.notes.as_deref(),
         pool.get_ref(),
     )
     .await
     {
-        Ok(url) => HttpResponse::Ok().json(APIResponse::data(url)),
-        Err(e) => {
-            HttpResponse::InternalServerError().json(APIResponse::error_message(e.to_string()))
-        }
+        Ok(url) => link_response(url),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Creates shortened URLs in bulk
+/// 
+/// This endpoint:
+/// 1. Verifies the user's authentication
+/// 2. Parses the rows from a JSON array, or from a CSV upload if the content
+///    type is `text/csv`
+/// 3. Validates and creates every row independently
+/// 4. Returns the outcome of every row
+/// 
+/// JSON rows have the same fields as a single URL. CSV uploads need a header
+/// row with an `original_url` column and may have `custom_path`, `domain`,
+/// `expiration`, (comma separated) `tags` and `notes` columns.
+/// 
+/// # Arguments
+/// * `req` - The HTTP request, used for its content type and the optional `Workspace-Id` header
+/// * `body` - The JSON array or CSV
+/// * `pool` - Database connection pool
+/// * `username` - The authenticated user's username
+/// 
+/// # Returns
+/// HTTP response:
+/// - 200 OK with the per-row results, even if some rows failed
+/// - 400 Bad Request if the body can't be parsed or has too many or no rows
+/// - 401 Unauthorized if user not found
+/// - 403 Forbidden if the user's role in the workspace doesn't allow it
+/// - 404 Not Found if the user isn't a member of the selected workspace
+/// - 500 Internal Server Error if saving fails
+#[post("/shorten/bulk")]
+pub async fn bulk_shorten_urls(
+    req: HttpRequest,
+    body: web::Bytes,
+    pool: web::Data<PgPool>,
+    username: web::ReqData<String>,
+) -> impl Responder {
+    let member = match request_member(&req, &username, pool.get_ref()).await {
+        Ok(m) => m,
+        Err(response) => return response,
+    };
+
+    let is_csv = req
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|c| c.to_str().ok())
+        .is_some_and(|c| c.starts_with("text/csv"));
+    let rows = if is_csv {
+        parse_csv_rows(&body)
+    } else {
+        parse_json_rows(&body)
+    };
+
+    let rows = match rows {
+        Ok(rows) => rows,
+        Err(e) => return error_response(e),
+    };
+
+    match bulk_create_urls(&member, rows, pool.get_ref()).await {
+        Ok(result) => HttpResponse::Ok().json(APIResponse::data(result)),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Applies an operation to many shortened URLs at once
+/// 
+/// This endpoint:
+/// 1. Verifies the user's authentication
+/// 2. Selects the workspace's URLs either by `ids` or by a `filter` taking the
+///    same fields as the listing's query parameters
+/// 3. Applies the `action` to every selected URL independently
+/// 4. Returns the number of affected URLs and the per-URL failures
+/// 
+/// The `action` is one of `delete`, `set_expiry` (with an optional
+/// `expiration` in seconds), `change_host` (with `from` and `to` hosts),
+/// `add_tags` or `remove_tags` (with `tags`), `disable` and `enable`.
+/// 
+/// # Arguments
+/// * `req` - The HTTP request, holding the optional `Workspace-Id` header
+/// * `request` - The URLs to select and the operation to apply
+/// * `pool` - Database connection pool
+/// * `username` - The authenticated user's username
+/// 
+/// # Returns
+/// HTTP response:
+/// - 200 OK with the summary, even if the operation failed for some URLs
+/// - 400 Bad Request if the selection or the operation is invalid
+/// - 401 Unauthorized if user not found
+/// - 403 Forbidden if the user's role in the workspace doesn't allow it
+/// - 404 Not Found if the user isn't a member of the selected workspace
+/// - 500 Internal Server Error if the URLs can't be loaded
+#[post("/shorten/bulk/actions")]
+pub async fn bulk_update_shortened_urls(
+    req: HttpRequest,
+    request: web::Json<BulkUpdateRequest>,
+    pool: web::Data<PgPool>,
+    username: web::ReqData<String>,
+) -> impl Responder {
+    let member = match request_member(&req, &username, pool.get_ref()).await {
+        Ok(m) => m,
+        Err(response) => return response,
+    };
+
+    match bulk_update_urls(&member, request.into_inner(), pool.get_ref()).await {
+        Ok(result) => HttpResponse::Ok().json(APIResponse::data(result)),
+        Err(e) => error_response(e),
     }
 }
 
@@ -82,9 +256,10 @@ pub async fn shorten_url(
 /// 
 /// This endpoint:
 /// 1. Verifies the user's authentication
-/// 2. Deletes the specified URL if owned by the user
+/// 2. Deletes the specified URL if owned by the selected workspace
 /// 
 /// # Arguments
+/// * `req` - The HTTP request, holding the optional `Workspace-Id` header
 /// * `id` - The ID of the URL to delete
 /// * `pool` - Database connection pool
 /// * `username` - The authenticated user's username
@@ -93,65 +268,230 @@ pub async fn shorten_url(
 /// HTTP response:
 /// - 204 No Content if successful
 /// - 401 Unauthorized if user not found
+/// - 403 Forbidden if the user's role in the workspace doesn't allow it
+/// - 404 Not Found if the URL doesn't exist, isn't owned by the workspace or the user isn't a member of it
 /// - 500 Internal Server Error if deletion fails
 #[delete("/shorten/{id}")]
 pub async fn delete_shortened_url(
+    req: HttpRequest,
     id: web::Path<String>,
     pool: web::Data<PgPool>,
     username: web::ReqData<String>,
 ) -> impl Responder {
-    let user = match sqlx::query_as::<_, User>("SELECT * FROM users WHERE username = $1")
-        .bind(&username.to_string())
-        .fetch_one(pool.get_ref())
-        .await
-    {
-        Ok(u) => u,
-        Err(_) => return HttpResponse::Unauthorized().finish(),
+    let member = match request_member(&req, &username, pool.get_ref()).await {
+        Ok(m) => m,
+        Err(response) => return response,
     };
 
     let s = id.into_inner();
-    match delete_url(&user, &s, pool.get_ref()).await {
+    match delete_url(&member, &s, pool.get_ref()).await {
         Ok(_) => HttpResponse::NoContent().finish(),
-        Err(e) => {
-            HttpResponse::InternalServerError().json(APIResponse::error_message(e.to_string()))
-        }
+        Err(e) => error_response(e),
     }
 }
 
-/// Lists all shortened URLs for the authenticated user
+/// Enables or disables a shortened URL
 /// 
 /// This endpoint:
 /// 1. Verifies the user's authentication
-/// 2. Retrieves all URLs owned by the user
+/// 2. Sets whether the URL redirects, keeping everything else about it
+/// 3. Returns the updated URL data
+/// 
+/// Visitors of a disabled URL are sent to `DISABLED_LINK_FALLBACK_URL` if it
+/// is set, or shown `DISABLED_LINK_MESSAGE` otherwise.
 /// 
 /// # Arguments
+/// * `req` - The HTTP request, holding the optional `Workspace-Id` header
+/// * `id` - The ID of the URL
+/// * `body` - Whether the URL should be enabled
 /// * `pool` - Database connection pool
 /// * `username` - The authenticated user's username
 /// 
 /// # Returns
 /// HTTP response:
+/// - 200 OK with the updated URL data if successful
+/// - 400 Bad Request if the ID is invalid
+/// - 401 Unauthorized if user not found
+/// - 403 Forbidden if the user's role in the workspace doesn't allow it
+/// - 404 Not Found if the URL doesn't exist, isn't owned by the workspace or the user isn't a member of it
+/// - 500 Internal Server Error if the update fails
+#[put("/shorten/{id}/enabled")]
+pub async fn set_shortened_url_enabled(
+    req: HttpRequest,
+    id: web::Path<String>,
+    body: web::Json<SetEnabledRequest>,
+    pool: web::Data<PgPool>,
+    username: web::ReqData<String>,
+) -> impl Responder {
+    let member = match request_member(&req, &username, pool.get_ref()).await {
+        Ok(m) => m,
+        Err(response) => return response,
+    };
+
+    match set_url_enabled(&member, &id, body.enabled, pool.get_ref()).await {
+        Ok(url) => link_response(url),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Lists the revision history of a shortened URL
+/// 
+/// This endpoint:
+/// 1. Verifies the user's authentication
+/// 2. Returns every recorded change to the URL's destination, short code,
+///    expiry, enabled state and tags, newest first, with who made it, when,
+///    and the old and new values
+/// 
+/// # Arguments
+/// * `req` - The HTTP request, holding the optional `Workspace-Id` header
+/// * `id` - The ID of the URL
+/// * `pool` - Database connection pool
+/// * `username` - The authenticated user's username
+/// 
+/// # Returns
+/// HTTP response:
+/// - 200 OK with the revisions
+/// - 400 Bad Request if the ID is invalid
+/// - 401 Unauthorized if user not found
+/// - 404 Not Found if the URL doesn't exist, isn't owned by the workspace or the user isn't a member of it
+/// - 500 Internal Server Error if the history can't be loaded
+#[get("/shorten/{id}/history")]
+pub async fn get_shortened_url_history(
+    req: HttpRequest,
+    id: web::Path<String>,
+    pool: web::Data<PgPool>,
+    username: web::ReqData<String>,
+) -> impl Responder {
+    let member = match request_member(&req, &username, pool.get_ref()).await {
+        Ok(m) => m,
+        Err(response) => return response,
+    };
+
+    match list_revisions(&member, &id, pool.get_ref()).await {
+        Ok(revisions) => HttpResponse::Ok().json(APIResponse::data(revisions)),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Restores a shortened URL to one of its revisions
+/// 
+/// This endpoint:
+/// 1. Verifies the user's authentication
+/// 2. Restores the destination, short code, expiry, enabled state and tags
+///    recorded by the revision
+/// 3. Records the restore as a new revision
+/// 
+/// # Arguments
+/// * `req` - The HTTP request, holding the optional `Workspace-Id` header
+/// * `path` - The ID of the URL and the ID of the revision to restore
+/// * `pool` - Database connection pool
+/// * `username` - The authenticated user's username
+/// 
+/// # Returns
+/// HTTP response:
+/// - 200 OK with the restored URL
+/// - 400 Bad Request if an ID is invalid or the destination is no longer allowed
+/// - 401 Unauthorized if user not found
+/// - 403 Forbidden if the user's role in the workspace doesn't allow it
+/// - 404 Not Found if the URL or revision doesn't exist, isn't owned by the workspace or the user isn't a member of it
+/// - 409 Conflict if another URL now uses or reserves the revision's short code
+/// - 500 Internal Server Error if restoring fails
+#[post("/shorten/{id}/history/{revision_id}/restore")]
+pub async fn restore_shortened_url_revision(
+    req: HttpRequest,
+    path: web::Path<(String, String)>,
+    pool: web::Data<PgPool>,
+    username: web::ReqData<String>,
+) -> impl Responder {
+    let member = match request_member(&req, &username, pool.get_ref()).await {
+        Ok(m) => m,
+        Err(response) => return response,
+    };
+
+    let (id, revision_id) = path.into_inner();
+    match restore_revision(&member, &id, &revision_id, pool.get_ref()).await {
+        Ok(url) => link_response(url),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Lists the shortened URLs of the authenticated user page by page
+/// 
+/// This endpoint:
+/// 1. Verifies the user's authentication
+/// 2. Retrieves a page of the URLs owned by the selected workspace matching the filters
+/// 3. Returns the page along with pagination metadata
+/// 
+/// Supported query parameters:
+/// - `broken`: links whose destination was (or wasn't) found broken by the last health check
+/// - `enabled`: enabled or disabled links
+/// - `tags`: comma separated list of tags, with `tag_mode` selecting whether
+///   links must have `all` (the default) or `any` of them
+/// - `status`: `active` or `expired` links
+/// - `expiring_within_days`: links expiring within the given number of days
+/// - `domain`: links whose destination is on the domain or one of its subdomains
+/// - `q`: full-text search over the short code, destination, title and notes
+/// - `sort`: `created` (the default), `updated`, `clicks` or `expiry`, with
+///   `order` being `asc` or `desc` (the default)
+/// - `limit` and `cursor`: the page size and the `next_cursor` of the previous page
+/// 
+/// # Arguments
+/// * `req` - The HTTP request, holding the optional `Workspace-Id` header
+/// * `pool` - Database connection pool
+/// * `username` - The authenticated user's username
+/// * `filter` - Filters from the query string
+/// 
+/// # Returns
+/// HTTP response:
 /// - 200 OK with the list of URLs if successful
+/// - 400 Bad Request if a filter, the limit or the cursor is invalid (with the target field)
 /// - 401 Unauthorized if user not found
+/// - 404 Not Found if the user isn't a member of the selected workspace
 /// - 500 Internal Server Error if retrieval fails
 #[get("/shorten")]
 pub async fn get_shortened_urls(
+    req: HttpRequest,
     pool: web::Data<PgPool>,
     username: web::ReqData<String>,
+    filter: web::Query<LinkFilter>,
 ) -> impl Responder {
-    let user = match sqlx::query_as::<_, User>("SELECT * FROM users WHERE username = $1")
-        .bind(&username.to_string())
-        .fetch_one(pool.get_ref())
-        .await
-    {
-        Ok(u) => u,
-        Err(_) => return HttpResponse::Unauthorized().finish(),
+    let member = match request_member(&req, &username, pool.get_ref()).await {
+        Ok(m) => m,
+        Err(response) => return response,
     };
 
-    match list_urls(&user, pool.get_ref()).await {
-        Ok(urls) => HttpResponse::Ok().json(APIResponse::data(urls)),
-        Err(e) => {
-            HttpResponse::InternalServerError().json(APIResponse::error_message(e.to_string()))
-        }
+    match list_urls(&member, &filter, pool.get_ref()).await {
+        Ok((urls, pagination)) => HttpResponse::Ok().json(APIResponse::page(urls, pagination)),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Reads the version a client expects a URL to be at from the `If-Match` header
+/// 
+/// # Arguments
+/// * `req` - The HTTP request
+/// 
+/// # Returns
+/// The expected version, `None` for `If-Match: *`, or the error response to
+/// send if the header is missing or doesn't hold a URL's ETag
+fn expected_version(req: &HttpRequest) -> Result<Option<i64>, HttpResponse> {
+    match req.get_header::<header::IfMatch>() {
+        None => Err(
+            HttpResponse::PreconditionRequired().json(APIResponse::error_message(
+                "The If-Match header with the ETag of the URL is required".to_string(),
+            )),
+        ),
+        Some(header::IfMatch::Any) => Ok(None),
+        Some(header::IfMatch::Items(tags)) => tags
+            .iter()
+            .filter(|tag| !tag.weak)
+            .find_map(|tag| tag.tag().parse().ok())
+            .map(Some)
+            .ok_or_else(|| {
+                HttpResponse::BadRequest().json(APIResponse::error_message(
+                    "The If-Match header must hold the ETag of the URL".to_string(),
+                ))
+            }),
     }
 }
 
@@ -159,45 +499,145 @@ pub async fn get_shortened_urls(
 /// 
 /// This endpoint:
 /// 1. Verifies the user's authentication
-/// 2. Updates the specified URL if owned by the user
+/// 2. Checks the `If-Match` header against the current version of the URL
+/// 3. Updates the specified URL if owned by the selected workspace
 /// 
 /// # Arguments
+/// * `req` - The HTTP request, holding the `If-Match` header and the optional `Workspace-Id` header
 /// * `pool` - Database connection pool
 /// * `username` - The authenticated user's username
 /// * `url_data` - The new URL data
 /// 
 /// # Returns
 /// HTTP response:
-/// - 200 OK with the updated URL data if successful
+/// - 200 OK with the updated URL data and its ETag if successful
+/// - 400 Bad Request if the URL or custom path is invalid (with the target field)
 /// - 401 Unauthorized if user not found
+/// - 403 Forbidden if the user's role in the workspace doesn't allow it
+/// - 404 Not Found if the URL doesn't exist, isn't owned by the workspace or the user isn't a member of it
+/// - 409 Conflict if the custom path is already used on the domain (with the target field) or
+///   reserved for the owner of a deleted URL
+/// - 412 Precondition Failed with the current URL if it was changed since the ETag was read
+/// - 428 Precondition Required if the `If-Match` header is missing
 /// - 500 Internal Server Error if update fails
 #[put("/shorten")]
 pub async fn update_shortened_url(
+    req: HttpRequest,
     pool: web::Data<PgPool>,
     username: web::ReqData<String>,
     url_data: web::Json<UpdateURLRequest>,
 ) -> impl Responder {
-    let user = match sqlx::query_as::<_, User>("SELECT * FROM users WHERE username = $1")
-        .bind(&username.to_string())
-        .fetch_one(pool.get_ref())
-        .await
-    {
-        Ok(u) => u,
-        Err(_) => return HttpResponse::Unauthorized().finish(),
+    let member = match request_member(&req, &username, pool.get_ref()).await {
+        Ok(m) => m,
+        Err(response) => return response,
+    };
+    let expected_version = match expected_version(&req) {
+        Ok(v) => v,
+        Err(response) => return response,
     };
     match update_url(
-        &user,
+        &member,
         &url_data.id,
         pool.get_ref(),
         &url_data.original_url,
         url_data.custom_path.as_ref(),
         url_data.expiration,
+        url_data.tags.as_deref(),
+        url_data.notes.as_deref(),
+        expected_version,
     )
     .await
     {
-        Ok(url) => HttpResponse::Ok().json(APIResponse::data(url)),
-        Err(e) => {
-            HttpResponse::InternalServerError().json(APIResponse::error_message(e.to_string()))
-        }
+        Ok(url) => link_response(url),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Partially updates a shortened URL
+/// 
+/// The body follows JSON Merge Patch semantics: fields that are absent are left
+/// unchanged and fields set to `null` are cleared, so clearing `custom_path`
+/// generates a new random code, clearing `expiration` makes the URL never
+/// expire and clearing `tags` removes every tag. The `If-Match` header must
+/// hold the ETag of the URL the same as for full updates.
+/// 
+/// # Arguments
+/// * `id` - The ID of the URL to update
+/// * `req` - The HTTP request, holding the `If-Match` header and the optional `Workspace-Id` header
+/// * `pool` - Database connection pool
+/// * `username` - The authenticated user's username
+/// * `body` - The fields to change
+/// 
+/// # Returns
+/// HTTP response:
+/// - 200 OK with the updated URL data and its ETag if successful
+/// - 400 Bad Request if a field is invalid or can't be cleared (with the target field)
+/// - 401 Unauthorized if user not found
+/// - 403 Forbidden if the user's role in the workspace doesn't allow it
+/// - 404 Not Found if the URL doesn't exist, isn't owned by the workspace or the user isn't a member of it
+/// - 409 Conflict if the custom path is already used on the domain (with the target field) or
+///   reserved for the owner of a deleted URL
+/// - 412 Precondition Failed with the current URL if it was changed since the ETag was read
+/// - 428 Precondition Required if the `If-Match` header is missing
+/// - 500 Internal Server Error if update fails
+#[patch("/shorten/{id}")]
+pub async fn patch_shortened_url(
+    id: web::Path<String>,
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    username: web::ReqData<String>,
+    body: web::Json<LinkPatch>,
+) -> impl Responder {
+    let member = match request_member(&req, &username, pool.get_ref()).await {
+        Ok(m) => m,
+        Err(response) => return response,
+    };
+
+    let expected_version = match expected_version(&req) {
+        Ok(v) => v,
+        Err(response) => return response,
+    };
+    match patch_url(&member, &id, &body, expected_version, pool.get_ref()).await {
+        Ok(url) => link_response(url),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Fetches the title, description, favicon and Open Graph image of a
+/// shortened URL's destination again
+/// 
+/// This endpoint:
+/// 1. Verifies the user's authentication
+/// 2. Fetches the destination page if the URL is owned by the selected workspace
+/// 3. Stores and returns the refreshed metadata
+/// 
+/// # Arguments
+/// * `req` - The HTTP request, holding the optional `Workspace-Id` header
+/// * `id` - The ID of the URL to refresh
+/// * `pool` - Database connection pool
+/// * `username` - The authenticated user's username
+/// 
+/// # Returns
+/// HTTP response:
+/// - 200 OK with the updated URL data if successful
+/// - 401 Unauthorized if user not found
+/// - 403 Forbidden if the user's role in the workspace doesn't allow it
+/// - 404 Not Found if the URL doesn't exist, isn't owned by the workspace or the user isn't a member of it
+/// - 500 Internal Server Error if the destination can't be fetched, keeping the stored metadata, or storing fails
+#[post("/shorten/{id}/metadata")]
+pub async fn refresh_shortened_url_metadata(
+    req: HttpRequest,
+    id: web::Path<String>,
+    pool: web::Data<PgPool>,
+    username: web::ReqData<String>,
+) -> impl Responder {
+    let member = match request_member(&req, &username, pool.get_ref()).await {
+        Ok(m) => m,
+        Err(response) => return response,
+    };
+
+    match refresh_url_metadata(&member, &id, pool.get_ref()).await {
+        Ok(url) => link_response(url),
+        Err(e) => error_response(e),
     }
 }
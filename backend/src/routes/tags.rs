@@ -0,0 +1,132 @@
+use actix_web::{get, post, put, web, HttpRequest, HttpResponse, Responder};
+use serde::Deserialize;
+use sqlx::PgPool;
+
+use crate::{
+    routes::workspaces::request_member,
+    service::{list_tags, merge_tags},
+    structs::APIResponse,
+    utils::error_response,
+};
+
+/// Request body for renaming a tag
+#[derive(Deserialize)]
+struct RenameTagRequest {
+    /// The new name of the tag
+    name: String,
+}
+
+/// Request body for merging tags
+#[derive(Deserialize)]
+struct MergeTagsRequest {
+    /// The tags to merge
+    sources: Vec<String>,
+    /// The tag to merge the sources into
+    target: String,
+}
+
+/// Lists the tags of the selected workspace
+/// 
+/// This endpoint:
+/// 1. Verifies the user's authentication
+/// 2. Returns every tag used by the workspace's URLs with the number of URLs using it
+/// 
+/// # Arguments
+/// * `req` - The HTTP request, holding the optional `Workspace-Id` header
+/// * `pool` - Database connection pool
+/// * `username` - The authenticated user's username
+/// 
+/// # Returns
+/// HTTP response:
+/// - 200 OK with the list of tags if successful
+/// - 401 Unauthorized if user not found
+/// - 404 Not Found if the user isn't a member of the selected workspace
+/// - 500 Internal Server Error if retrieval fails
+#[get("/tags")]
+pub async fn get_tags(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    username: web::ReqData<String>,
+) -> impl Responder {
+    let member = match request_member(&req, &username, pool.get_ref()).await {
+        Ok(m) => m,
+        Err(response) => return response,
+    };
+
+    match list_tags(&member, pool.get_ref()).await {
+        Ok(tags) => HttpResponse::Ok().json(APIResponse::data(tags)),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Renames a tag across all of the selected workspace's URLs
+/// 
+/// Renaming a tag to the name of another existing tag merges the two.
+/// 
+/// # Arguments
+/// * `req` - The HTTP request, holding the optional `Workspace-Id` header
+/// * `name` - The current name of the tag
+/// * `pool` - Database connection pool
+/// * `username` - The authenticated user's username
+/// * `body` - The new name of the tag
+/// 
+/// # Returns
+/// HTTP response:
+/// - 200 OK with the renamed tag and its link count if successful
+/// - 400 Bad Request if the new name is invalid (with the target field)
+/// - 401 Unauthorized if user not found
+/// - 403 Forbidden if the user's role in the workspace doesn't allow it
+/// - 404 Not Found if the user has no such tag
+/// - 500 Internal Server Error if renaming fails
+#[put("/tags/{name}")]
+pub async fn rename_tag(
+    req: HttpRequest,
+    name: web::Path<String>,
+    pool: web::Data<PgPool>,
+    username: web::ReqData<String>,
+    body: web::Json<RenameTagRequest>,
+) -> impl Responder {
+    let member = match request_member(&req, &username, pool.get_ref()).await {
+        Ok(m) => m,
+        Err(response) => return response,
+    };
+
+    match merge_tags(&member, &[name.into_inner()], &body.name, pool.get_ref()).await {
+        Ok(tag) => HttpResponse::Ok().json(APIResponse::data(tag)),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Merges several tags into one across all of the selected workspace's URLs
+/// 
+/// # Arguments
+/// * `req` - The HTTP request, holding the optional `Workspace-Id` header
+/// * `pool` - Database connection pool
+/// * `username` - The authenticated user's username
+/// * `body` - The tags to merge and the tag to merge them into
+/// 
+/// # Returns
+/// HTTP response:
+/// - 200 OK with the target tag and its link count if successful
+/// - 400 Bad Request if a tag name is invalid (with the target field)
+/// - 401 Unauthorized if user not found
+/// - 403 Forbidden if the user's role in the workspace doesn't allow it
+/// - 404 Not Found if the user has none of the source tags
+/// - 500 Internal Server Error if merging fails
+#[post("/tags/merge")]
+pub async fn merge_tags_into(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    username: web::ReqData<String>,
+    body: web::Json<MergeTagsRequest>,
+) -> impl Responder {
+    let member = match request_member(&req, &username, pool.get_ref()).await {
+        Ok(m) => m,
+        Err(response) => return response,
+    };
+
+    match merge_tags(&member, &body.sources, &body.target, pool.get_ref()).await {
+        Ok(tag) => HttpResponse::Ok().json(APIResponse::data(tag)),
+        Err(e) => error_response(e),
+    }
+}
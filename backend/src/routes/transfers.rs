@@ -0,0 +1,200 @@
+use actix_web::{delete, get, post, web, HttpRequest, HttpResponse, Responder};
+use serde::Deserialize;
+use sqlx::PgPool;
+
+use crate::{
+    routes::workspaces::request_member,
+    structs::{APIResponse, User},
+    transfers::{
+        accept_transfer, cancel_transfer, decline_transfer, list_transfers, offer_transfer,
+    },
+    utils::{error_response, link_response},
+};
+
+/// Request body for offering a URL to another user
+#[derive(Deserialize)]
+struct OfferTransferRequest {
+    /// The username of the user to offer the URL to
+    username: String,
+}
+
+/// Offers a shortened URL to another user
+/// 
+/// This endpoint:
+/// 1. Verifies the user's authentication
+/// 2. Records a pending transfer that the recipient has to accept, replacing
+///    any pending transfer of the URL
+/// 
+/// # Arguments
+/// * `req` - The HTTP request, holding the optional `Workspace-Id` header
+/// * `id` - The ID of the URL to transfer
+/// * `pool` - Database connection pool
+/// * `username` - The authenticated user's username
+/// * `body` - The username of the recipient
+/// 
+/// # Returns
+/// HTTP response:
+/// - 200 OK with the pending transfer if successful
+/// - 400 Bad Request if the ID is invalid, the recipient is unknown (with the target field) or
+///   the URL is on a custom domain
+/// - 401 Unauthorized if user not found
+/// - 403 Forbidden if the user's role in the workspace doesn't allow it
+/// - 404 Not Found if the URL doesn't exist, isn't owned by the workspace or the user isn't a member of it
+/// - 500 Internal Server Error if offering fails
+#[post("/shorten/{id}/transfer")]
+pub async fn offer_shortened_url_transfer(
+    req: HttpRequest,
+    id: web::Path<String>,
+    pool: web::Data<PgPool>,
+    username: web::ReqData<String>,
+    body: web::Json<OfferTransferRequest>,
+) -> impl Responder {
+    let member = match request_member(&req, &username, pool.get_ref()).await {
+        Ok(m) => m,
+        Err(response) => return response,
+    };
+
+    match offer_transfer(&member, &id, &body.username, pool.get_ref()).await {
+        Ok(transfer) => HttpResponse::Ok().json(APIResponse::data(transfer)),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Withdraws the pending transfer of a shortened URL
+/// 
+/// # Arguments
+/// * `req` - The HTTP request, holding the optional `Workspace-Id` header
+/// * `id` - The ID of the URL
+/// * `pool` - Database connection pool
+/// * `username` - The authenticated user's username
+/// 
+/// # Returns
+/// HTTP response:
+/// - 204 No Content if successful
+/// - 400 Bad Request if the ID is invalid
+/// - 401 Unauthorized if user not found
+/// - 403 Forbidden if the user's role in the workspace doesn't allow it
+/// - 404 Not Found if the workspace offered no transfer of the URL
+/// - 500 Internal Server Error if withdrawing fails
+#[delete("/shorten/{id}/transfer")]
+pub async fn cancel_shortened_url_transfer(
+    req: HttpRequest,
+    id: web::Path<String>,
+    pool: web::Data<PgPool>,
+    username: web::ReqData<String>,
+) -> impl Responder {
+    let member = match request_member(&req, &username, pool.get_ref()).await {
+        Ok(m) => m,
+        Err(response) => return response,
+    };
+
+    match cancel_transfer(&member, &id, pool.get_ref()).await {
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Lists the pending transfers offered by or to the authenticated user
+/// 
+/// # Arguments
+/// * `pool` - Database connection pool
+/// * `username` - The authenticated user's username
+/// 
+/// # Returns
+/// HTTP response:
+/// - 200 OK with the incoming and outgoing transfers
+/// - 401 Unauthorized if user not found
+/// - 500 Internal Server Error if retrieval fails
+#[get("/transfers")]
+pub async fn get_transfers(
+    pool: web::Data<PgPool>,
+    username: web::ReqData<String>,
+) -> impl Responder {
+    let user = match sqlx::query_as::<_, User>("SELECT * FROM users WHERE username = $1")
+        .bind(username.to_string())
+        .fetch_one(pool.get_ref())
+        .await
+    {
+        Ok(u) => u,
+        Err(_) => return HttpResponse::Unauthorized().finish(),
+    };
+
+    match list_transfers(&user, pool.get_ref()).await {
+        Ok(transfers) => HttpResponse::Ok().json(APIResponse::data(transfers)),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Accepts a transfer offered to the authenticated user
+/// 
+/// This endpoint:
+/// 1. Verifies the user's authentication
+/// 2. Makes the user the owner of the URL, keeping its tags
+/// 3. Records the transfer in the URL's revision history
+/// 
+/// # Arguments
+/// * `req` - The HTTP request, holding the optional `Workspace-Id` header
+/// * `id` - The ID of the transfer
+/// * `pool` - Database connection pool
+/// * `username` - The authenticated user's username
+/// 
+/// # Returns
+/// HTTP response:
+/// - 200 OK with the transferred URL
+/// - 400 Bad Request if the ID is invalid or the URL was moved to a custom domain
+/// - 401 Unauthorized if user not found
+/// - 403 Forbidden if the user's role in the workspace doesn't allow it
+/// - 404 Not Found if the transfer wasn't offered to the user or the URL is gone
+/// - 500 Internal Server Error if accepting fails
+#[post("/transfers/{id}/accept")]
+pub async fn accept_link_transfer(
+    req: HttpRequest,
+    id: web::Path<String>,
+    pool: web::Data<PgPool>,
+    username: web::ReqData<String>,
+) -> impl Responder {
+    let member = match request_member(&req, &username, pool.get_ref()).await {
+        Ok(m) => m,
+        Err(response) => return response,
+    };
+
+    match accept_transfer(&member, &id, pool.get_ref()).await {
+        Ok(url) => link_response(url),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Declines a transfer offered to the authenticated user
+/// 
+/// # Arguments
+/// * `id` - The ID of the transfer
+/// * `pool` - Database connection pool
+/// * `username` - The authenticated user's username
+/// 
+/// # Returns
+/// HTTP response:
+/// - 204 No Content if successful
+/// - 400 Bad Request if the ID is invalid
+/// - 401 Unauthorized if user not found
+/// - 404 Not Found if the transfer wasn't offered to the user
+/// - 500 Internal Server Error if declining fails
+#[post("/transfers/{id}/decline")]
+pub async fn decline_link_transfer(
+    id: web::Path<String>,
+    pool: web::Data<PgPool>,
+    username: web::ReqData<String>,
+) -> impl Responder {
+    let user = match sqlx::query_as::<_, User>("SELECT * FROM users WHERE username = $1")
+        .bind(username.to_string())
+        .fetch_one(pool.get_ref())
+        .await
+    {
+        Ok(u) => u,
+        Err(_) => return HttpResponse::Unauthorized().finish(),
+    };
+
+    match decline_transfer(&user, &id, pool.get_ref()).await {
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(e) => error_response(e),
+    }
+}
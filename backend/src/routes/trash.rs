@@ -0,0 +1,120 @@
+use actix_web::{delete, get, post, web, HttpRequest, HttpResponse, Responder};
+use sqlx::PgPool;
+
+use crate::{
+    routes::workspaces::request_member,
+    structs::APIResponse,
+    trash::{list_trash, purge_url, restore_from_trash},
+    utils::{error_response, link_response},
+};
+
+/// Lists the shortened URLs in the selected workspace's trash
+/// 
+/// This endpoint:
+/// 1. Verifies the user's authentication
+/// 2. Returns the workspace's deleted URLs, most recently deleted first, along
+///    with when each will be purged for good
+/// 
+/// # Arguments
+/// * `req` - The HTTP request, holding the optional `Workspace-Id` header
+/// * `pool` - Database connection pool
+/// * `username` - The authenticated user's username
+/// 
+/// # Returns
+/// HTTP response:
+/// - 200 OK with the list of trashed URLs if successful
+/// - 401 Unauthorized if user not found
+/// - 404 Not Found if the user isn't a member of the selected workspace
+/// - 500 Internal Server Error if retrieval fails
+#[get("/trash")]
+pub async fn get_trash(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    username: web::ReqData<String>,
+) -> impl Responder {
+    let member = match request_member(&req, &username, pool.get_ref()).await {
+        Ok(m) => m,
+        Err(response) => return response,
+    };
+
+    match list_trash(&member, pool.get_ref()).await {
+        Ok(urls) => HttpResponse::Ok().json(APIResponse::data(urls)),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Restores a shortened URL from the selected workspace's trash
+/// 
+/// This endpoint:
+/// 1. Verifies the user's authentication
+/// 2. Moves the URL out of the trash so it redirects again
+/// 
+/// # Arguments
+/// * `req` - The HTTP request, holding the optional `Workspace-Id` header
+/// * `id` - The ID of the URL to restore
+/// * `pool` - Database connection pool
+/// * `username` - The authenticated user's username
+/// 
+/// # Returns
+/// HTTP response:
+/// - 200 OK with the restored URL if successful
+/// - 400 Bad Request if the ID is invalid
+/// - 401 Unauthorized if user not found
+/// - 403 Forbidden if the user's role in the workspace doesn't allow it
+/// - 404 Not Found if the URL isn't in the workspace's trash
+/// - 500 Internal Server Error if restoring fails
+#[post("/trash/{id}/restore")]
+pub async fn restore_trashed_url(
+    req: HttpRequest,
+    id: web::Path<String>,
+    pool: web::Data<PgPool>,
+    username: web::ReqData<String>,
+) -> impl Responder {
+    let member = match request_member(&req, &username, pool.get_ref()).await {
+        Ok(m) => m,
+        Err(response) => return response,
+    };
+
+    match restore_from_trash(&member, &id, pool.get_ref()).await {
+        Ok(url) => link_response(url),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Permanently deletes a shortened URL from the selected workspace's trash
+/// 
+/// This endpoint:
+/// 1. Verifies the user's authentication
+/// 2. Deletes the URL for good without waiting for the retention period
+/// 
+/// # Arguments
+/// * `req` - The HTTP request, holding the optional `Workspace-Id` header
+/// * `id` - The ID of the URL to purge
+/// * `pool` - Database connection pool
+/// * `username` - The authenticated user's username
+/// 
+/// # Returns
+/// HTTP response:
+/// - 204 No Content if successful
+/// - 400 Bad Request if the ID is invalid
+/// - 401 Unauthorized if user not found
+/// - 403 Forbidden if the user's role in the workspace doesn't allow it
+/// - 404 Not Found if the URL isn't in the workspace's trash
+/// - 500 Internal Server Error if deletion fails
+#[delete("/trash/{id}")]
+pub async fn purge_trashed_url(
+    req: HttpRequest,
+    id: web::Path<String>,
+    pool: web::Data<PgPool>,
+    username: web::ReqData<String>,
+) -> impl Responder {
+    let member = match request_member(&req, &username, pool.get_ref()).await {
+        Ok(m) => m,
+        Err(response) => return response,
+    };
+
+    match purge_url(&member, &id, pool.get_ref()).await {
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(e) => error_response(e),
+    }
+}
@@ -0,0 +1,428 @@
+use actix_web::{delete, get, post, put, web, HttpRequest, HttpResponse, Responder};
+use serde::Deserialize;
+use sqlx::PgPool;
+
+use crate::{
+    structs::{APIResponse, User},
+    utils::error_response,
+    workspaces::{
+        accept_invitation, change_member_role, create_workspace, decline_invitation,
+        delete_workspace, invite_member, list_invitations, list_members, list_workspaces,
+        load_member, remove_member, Member, WorkspaceRole,
+    },
+};
+
+/// The header selecting the workspace the link endpoints act in
+pub(crate) const WORKSPACE_HEADER: &str = "Workspace-Id";
+
+/// Request body for creating a workspace
+#[derive(Deserialize)]
+struct CreateWorkspaceRequest {
+    /// The name of the workspace
+    name: String,
+}
+
+/// Request body for inviting a user to a workspace
+#[derive(Deserialize)]
+struct InviteMemberRequest {
+    /// The username of the user to invite
+    username: String,
+    /// The role the user gets when accepting
+    role: WorkspaceRole,
+}
+
+/// Request body for changing the role of a member
+#[derive(Deserialize)]
+struct ChangeRoleRequest {
+    /// The new role of the member
+    role: WorkspaceRole,
+}
+
+/// Loads the authenticated user as a member of a workspace
+/// 
+/// # Arguments
+/// * `username` - The authenticated user's username
+/// * `workspace_id` - The ID of the workspace, the user's personal workspace if `None`
+/// * `pool` - Database connection pool
+/// 
+/// # Returns
+/// Result containing the member, or the response to send if the user isn't
+/// found (401) or isn't a member of the workspace (404)
+async fn workspace_member(
+    username: &str,
+    workspace_id: Option<&str>,
+    pool: &PgPool,
+) -> Result<Member, HttpResponse> {
+    let user = sqlx::query_as::<_, User>("SELECT * FROM users WHERE username = $1")
+        .bind(username)
+        .fetch_one(pool)
+        .await
+        .map_err(|_| HttpResponse::Unauthorized().finish())?;
+
+    load_member(user, workspace_id, pool)
+        .await
+        .map_err(error_response)
+}
+
+/// Loads the authenticated user as a member of the workspace a request selects
+/// 
+/// The workspace is selected with the `Workspace-Id` header, and is the
+/// user's personal workspace if the header is absent.
+/// 
+/// # Arguments
+/// * `req` - The HTTP request, holding the optional `Workspace-Id` header
+/// * `username` - The authenticated user's username
+/// * `pool` - Database connection pool
+/// 
+/// # Returns
+/// Result containing the member, or the response to send if the user isn't
+/// found (401), the header is invalid (400) or the user isn't a member of the
+/// workspace (404)
+pub(crate) async fn request_member(
+    req: &HttpRequest,
+    username: &str,
+    pool: &PgPool,
+) -> Result<Member, HttpResponse> {
+    let workspace_id = match req.headers().get(WORKSPACE_HEADER) {
+        Some(value) => Some(value.to_str().map_err(|_| {
+            HttpResponse::BadRequest().json(APIResponse::error_message(
+                "Workspace-Id must be the ID of a workspace".to_string(),
+            ))
+        })?),
+        None => None,
+    };
+
+    workspace_member(username, workspace_id, pool).await
+}
+
+/// Creates a workspace owned by the authenticated user
+/// 
+/// # Arguments
+/// * `body` - The request body containing the name
+/// * `pool` - Database connection pool
+/// * `username` - The authenticated user's username
+/// 
+/// # Returns
+/// HTTP response:
+/// - 200 OK with the created workspace
+/// - 400 Bad Request if the name is invalid (with the target field)
+/// - 401 Unauthorized if user not found
+/// - 500 Internal Server Error if creation fails
+#[post("/workspaces")]
+pub async fn create_new_workspace(
+    body: web::Json<CreateWorkspaceRequest>,
+    pool: web::Data<PgPool>,
+    username: web::ReqData<String>,
+) -> impl Responder {
+    let user = match sqlx::query_as::<_, User>("SELECT * FROM users WHERE username = $1")
+        .bind(username.to_string())
+        .fetch_one(pool.get_ref())
+        .await
+    {
+        Ok(u) => u,
+        Err(_) => return HttpResponse::Unauthorized().finish(),
+    };
+
+    match create_workspace(&user, &body.name, pool.get_ref()).await {
+        Ok(workspace) => HttpResponse::Ok().json(APIResponse::data(workspace)),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Lists the workspaces of the authenticated user along with their role in each
+/// 
+/// # Arguments
+/// * `pool` - Database connection pool
+/// * `username` - The authenticated user's username
+/// 
+/// # Returns
+/// HTTP response:
+/// - 200 OK with the workspaces, personal workspace first
+/// - 401 Unauthorized if user not found
+/// - 500 Internal Server Error if retrieval fails
+#[get("/workspaces")]
+pub async fn get_workspaces(
+    pool: web::Data<PgPool>,
+    username: web::ReqData<String>,
+) -> impl Responder {
+    let user = match sqlx::query_as::<_, User>("SELECT * FROM users WHERE username = $1")
+        .bind(username.to_string())
+        .fetch_one(pool.get_ref())
+        .await
+    {
+        Ok(u) => u,
+        Err(_) => return HttpResponse::Unauthorized().finish(),
+    };
+
+    match list_workspaces(&user, pool.get_ref()).await {
+        Ok(workspaces) => HttpResponse::Ok().json(APIResponse::data(workspaces)),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Deletes a workspace of the authenticated user
+/// 
+/// # Arguments
+/// * `id` - The ID of the workspace
+/// * `pool` - Database connection pool
+/// * `username` - The authenticated user's username
+/// 
+/// # Returns
+/// HTTP response:
+/// - 204 No Content if successful
+/// - 400 Bad Request if the ID is invalid, the workspace is personal or still has links
+/// - 401 Unauthorized if user not found
+/// - 403 Forbidden if the user isn't an owner of the workspace
+/// - 404 Not Found if the workspace doesn't exist or the user isn't a member
+/// - 500 Internal Server Error if deletion fails
+#[delete("/workspaces/{id}")]
+pub async fn remove_workspace(
+    id: web::Path<String>,
+    pool: web::Data<PgPool>,
+    username: web::ReqData<String>,
+) -> impl Responder {
+    let member = match workspace_member(&username, Some(&id), pool.get_ref()).await {
+        Ok(m) => m,
+        Err(response) => return response,
+    };
+
+    match delete_workspace(&member, pool.get_ref()).await {
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Lists the members of a workspace of the authenticated user
+/// 
+/// # Arguments
+/// * `id` - The ID of the workspace
+/// * `pool` - Database connection pool
+/// * `username` - The authenticated user's username
+/// 
+/// # Returns
+/// HTTP response:
+/// - 200 OK with the members, owners first
+/// - 400 Bad Request if the ID is invalid
+/// - 401 Unauthorized if user not found
+/// - 404 Not Found if the workspace doesn't exist or the user isn't a member
+/// - 500 Internal Server Error if retrieval fails
+#[get("/workspaces/{id}/members")]
+pub async fn get_workspace_members(
+    id: web::Path<String>,
+    pool: web::Data<PgPool>,
+    username: web::ReqData<String>,
+) -> impl Responder {
+    let member = match workspace_member(&username, Some(&id), pool.get_ref()).await {
+        Ok(m) => m,
+        Err(response) => return response,
+    };
+
+    match list_members(&member, pool.get_ref()).await {
+        Ok(members) => HttpResponse::Ok().json(APIResponse::data(members)),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Changes the role of a member of a workspace
+/// 
+/// # Arguments
+/// * `path` - The ID of the workspace and the ID of the member
+/// * `body` - The request body containing the new role
+/// * `pool` - Database connection pool
+/// * `username` - The authenticated user's username
+/// 
+/// # Returns
+/// HTTP response:
+/// - 200 OK with the updated member
+/// - 400 Bad Request if an ID is invalid or the last owner would lose the role
+/// - 401 Unauthorized if user not found
+/// - 403 Forbidden if the user isn't an admin or the role is above their own
+/// - 404 Not Found if the workspace or member doesn't exist
+/// - 500 Internal Server Error if the change fails
+#[put("/workspaces/{id}/members/{user_id}")]
+pub async fn set_workspace_member_role(
+    path: web::Path<(String, String)>,
+    body: web::Json<ChangeRoleRequest>,
+    pool: web::Data<PgPool>,
+    username: web::ReqData<String>,
+) -> impl Responder {
+    let (id, user_id) = path.into_inner();
+    let member = match workspace_member(&username, Some(&id), pool.get_ref()).await {
+        Ok(m) => m,
+        Err(response) => return response,
+    };
+
+    match change_member_role(&member, &user_id, body.role, pool.get_ref()).await {
+        Ok(updated) => HttpResponse::Ok().json(APIResponse::data(updated)),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Removes a member from a workspace, or leaves it when removing oneself
+/// 
+/// # Arguments
+/// * `path` - The ID of the workspace and the ID of the member
+/// * `pool` - Database connection pool
+/// * `username` - The authenticated user's username
+/// 
+/// # Returns
+/// HTTP response:
+/// - 204 No Content if successful
+/// - 400 Bad Request if an ID is invalid or the member is the last owner
+/// - 401 Unauthorized if user not found
+/// - 403 Forbidden if the user may not remove the member
+/// - 404 Not Found if the workspace or member doesn't exist
+/// - 500 Internal Server Error if removal fails
+#[delete("/workspaces/{id}/members/{user_id}")]
+pub async fn remove_workspace_member(
+    path: web::Path<(String, String)>,
+    pool: web::Data<PgPool>,
+    username: web::ReqData<String>,
+) -> impl Responder {
+    let (id, user_id) = path.into_inner();
+    let member = match workspace_member(&username, Some(&id), pool.get_ref()).await {
+        Ok(m) => m,
+        Err(response) => return response,
+    };
+
+    match remove_member(&member, &user_id, pool.get_ref()).await {
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Invites a user to a workspace by their username
+/// 
+/// # Arguments
+/// * `id` - The ID of the workspace
+/// * `body` - The request body containing the username and role
+/// * `pool` - Database connection pool
+/// * `username` - The authenticated user's username
+/// 
+/// # Returns
+/// HTTP response:
+/// - 200 OK with the pending invitation
+/// - 400 Bad Request if the ID or username is invalid, or the workspace is personal
+/// - 401 Unauthorized if user not found
+/// - 403 Forbidden if the user isn't an admin or the role is above their own
+/// - 404 Not Found if the workspace doesn't exist or the user isn't a member
+/// - 409 Conflict if the invited user is already a member
+/// - 500 Internal Server Error if the invitation fails
+#[post("/workspaces/{id}/invitations")]
+pub async fn invite_workspace_member(
+    id: web::Path<String>,
+    body: web::Json<InviteMemberRequest>,
+    pool: web::Data<PgPool>,
+    username: web::ReqData<String>,
+) -> impl Responder {
+    let member = match workspace_member(&username, Some(&id), pool.get_ref()).await {
+        Ok(m) => m,
+        Err(response) => return response,
+    };
+
+    match invite_member(&member, &body.username, body.role, pool.get_ref()).await {
+        Ok(invitation) => HttpResponse::Ok().json(APIResponse::data(invitation)),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Lists the pending workspace invitations of the authenticated user
+/// 
+/// # Arguments
+/// * `pool` - Database connection pool
+/// * `username` - The authenticated user's username
+/// 
+/// # Returns
+/// HTTP response:
+/// - 200 OK with the invitations, newest first
+/// - 401 Unauthorized if user not found
+/// - 500 Internal Server Error if retrieval fails
+#[get("/invitations")]
+pub async fn get_invitations(
+    pool: web::Data<PgPool>,
+    username: web::ReqData<String>,
+) -> impl Responder {
+    let user = match sqlx::query_as::<_, User>("SELECT * FROM users WHERE username = $1")
+        .bind(username.to_string())
+        .fetch_one(pool.get_ref())
+        .await
+    {
+        Ok(u) => u,
+        Err(_) => return HttpResponse::Unauthorized().finish(),
+    };
+
+    match list_invitations(&user, pool.get_ref()).await {
+        Ok(invitations) => HttpResponse::Ok().json(APIResponse::data(invitations)),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Accepts a workspace invitation sent to the authenticated user
+/// 
+/// # Arguments
+/// * `id` - The ID of the invitation
+/// * `pool` - Database connection pool
+/// * `username` - The authenticated user's username
+/// 
+/// # Returns
+/// HTTP response:
+/// - 200 OK with the joined workspace
+/// - 400 Bad Request if the ID is invalid
+/// - 401 Unauthorized if user not found
+/// - 404 Not Found if the invitation doesn't exist or wasn't sent to the user
+/// - 500 Internal Server Error if accepting fails
+#[post("/invitations/{id}/accept")]
+pub async fn accept_workspace_invitation(
+    id: web::Path<String>,
+    pool: web::Data<PgPool>,
+    username: web::ReqData<String>,
+) -> impl Responder {
+    let user = match sqlx::query_as::<_, User>("SELECT * FROM users WHERE username = $1")
+        .bind(username.to_string())
+        .fetch_one(pool.get_ref())
+        .await
+    {
+        Ok(u) => u,
+        Err(_) => return HttpResponse::Unauthorized().finish(),
+    };
+
+    match accept_invitation(&user, &id, pool.get_ref()).await {
+        Ok(workspace) => HttpResponse::Ok().json(APIResponse::data(workspace)),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Declines a workspace invitation sent to the authenticated user
+/// 
+/// # Arguments
+/// * `id` - The ID of the invitation
+/// * `pool` - Database connection pool
+/// * `username` - The authenticated user's username
+/// 
+/// # Returns
+/// HTTP response:
+/// - 204 No Content if successful
+/// - 400 Bad Request if the ID is invalid
+/// - 401 Unauthorized if user not found
+/// - 404 Not Found if the invitation doesn't exist or wasn't sent to the user
+/// - 500 Internal Server Error if declining fails
+#[post("/invitations/{id}/decline")]
+pub async fn decline_workspace_invitation(
+    id: web::Path<String>,
+    pool: web::Data<PgPool>,
+    username: web::ReqData<String>,
+) -> impl Responder {
+    let user = match sqlx::query_as::<_, User>("SELECT * FROM users WHERE username = $1")
+        .bind(username.to_string())
+        .fetch_one(pool.get_ref())
+        .await
+    {
+        Ok(u) => u,
+        Err(_) => return HttpResponse::Unauthorized().finish(),
+    };
+
+    match decline_invitation(&user, &id, pool.get_ref()).await {
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(e) => error_response(e),
+    }
+}
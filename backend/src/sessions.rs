@@ -0,0 +1,711 @@
+use actix_web::{http::header, HttpRequest};
+use chrono::{Duration, Utc};
+use nanoid::nanoid;
+use sqlx::{PgConnection, PgExecutor, PgPool};
+use std::net::SocketAddr;
+use uuid::Uuid;
+
+use crate::{
+    service::parse_uuid,
+    structs::{Session, User},
+    utils::hash_secret,
+};
+
+/// The maximum length (in characters) of a stored user agent
+const MAX_USER_AGENT_LENGTH: usize = 512;
+
+/// How often (in seconds) the last use of a session is recorded when its access tokens are used
+const LAST_SEEN_INTERVAL: i64 = 60;
+
+/// Returns how long a session lasts before the user has to log in again
+/// 
+/// # Arguments
+/// * `remember_me` - Whether the user asked to be remembered for a longer period
+fn session_lifetime(remember_me: bool) -> Duration {
+    if remember_me {
+        Duration::days(30)
+    } else {
+        Duration::hours(1)
+    }
+}
+
+/// The session an access token was issued for, added to request extensions
+#[derive(Debug, Clone, Copy)]
+pub struct CurrentSession(pub Uuid);
+
+/// The device a session is used from, as shown when listing sessions
+#[derive(Debug, Default)]
+pub struct ClientInfo {
+    /// The IP address of the client
+    pub ip_address: Option<String>,
+    /// The User-Agent header sent by the client
+    pub user_agent: Option<String>,
+}
+
+impl ClientInfo {
+    /// Reads the client of a request
+    /// 
+    /// The IP address honors the Forwarded and X-Forwarded-For headers, so it
+    /// is only informational and not to be relied upon.
+    /// 
+    /// # Arguments
+    /// * `req` - The HTTP request
+    pub fn from_request(req: &HttpRequest) -> Self {
+        let ip_address = req.connection_info().realip_remote_addr().map(|addr| {
+            addr.parse::<SocketAddr>()
+                .map(|a| a.ip().to_string())
+                .unwrap_or(addr.to_string())
+        });
+        let user_agent = req
+            .headers()
+            .get(header::USER_AGENT)
+            .and_then(|h| h.to_str().ok())
+            .map(|ua| ua.chars().take(MAX_USER_AGENT_LENGTH).collect());
+
+        Self {
+            ip_address,
+            user_agent,
+        }
+    }
+}
+
+/// A session along with the refresh token that continues it
+#[derive(Debug)]
+pub struct IssuedSession {
+    /// ID of the session, included in its access tokens
+    pub session_id: Uuid,
+    /// Username of the user the session belongs to
+    pub username: String,
+    /// The refresh token to exchange for the next access token, usable once
+    pub refresh_token: String,
+}
+
+/// The result of exchanging a refresh token
+#[derive(Debug)]
+pub enum SessionRefresh {
+    /// The token was valid and has been replaced by a new one
+    Refreshed(IssuedSession),
+    /// The token was already exchanged before, so the session has been revoked
+    Reused,
+    /// The token doesn't exist, or its session was revoked or expired
+    Invalid,
+}
+
+/// Stores a new refresh token for a session
+/// 
+/// # Arguments
+/// * `session_id` - The ID of the session
+/// * `conn` - Database connection
+/// 
+/// # Returns
+/// Result containing the refresh token, of which only the hash is stored
+async fn issue_refresh_token(
+    session_id: Uuid,
+    conn: &mut PgConnection,
+) -> Result<String, std::io::Error> {
+    let token = nanoid!(48);
+    sqlx::query(
+        "INSERT INTO refresh_tokens (token_hash, session_id, created_at) VALUES ($1, $2, $3)",
+    )
+    .bind(hash_secret(&token))
+    .bind(session_id)
+    .bind(Utc::now())
+    .execute(conn)
+    .await
+    .map_err(|e| std::io::Error::other(e.to_string()))?;
+    Ok(token)
+}
+
+/// Starts a session for a user who just logged in
+/// 
+/// The user's sessions that already expired are removed along the way.
+/// 
+/// # Arguments
+/// * `user` - The user who logged in
+/// * `remember_me` - Whether the session lasts 30 days instead of an hour
+/// * `client` - The device the user logged in from
+/// * `pool` - Database connection pool
+/// 
+/// # Returns
+/// Result containing the session and its first refresh token
+pub async fn create_session(
+    user: &User,
+    remember_me: bool,
+    client: &ClientInfo,
+    pool: &PgPool,
+) -> Result<IssuedSession, std::io::Error> {
+    let session_id = Uuid::new_v4();
+    let now = Utc::now();
+
+    let mut tx = pool
+        .begin()
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+    sqlx::query("DELETE FROM sessions WHERE user_id = $1 AND expires_at <= $2")
+        .bind(user.id)
+        .bind(now)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+    sqlx::query(
+        r#"
+      INSERT INTO sessions (id, user_id, created_at, expires_at, last_used_at, remember_me, ip_address, user_agent)
+      VALUES ($1, $2, $3, $4, $3, $5, $6, $7)
+      "#,
+    )
+    .bind(session_id)
+    .bind(user.id)
+    .bind(now)
+    .bind(now + session_lifetime(remember_me))
+    .bind(remember_me)
+    .bind(&client.ip_address)
+    .bind(&client.user_agent)
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| std::io::Error::other(e.to_string()))?;
+    let refresh_token = issue_refresh_token(session_id, &mut tx).await?;
+    tx.commit()
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+
+    Ok(IssuedSession {
+        session_id,
+        username: user.username.clone(),
+        refresh_token,
+    })
+}
+
+/// Exchanges a refresh token for a new one, continuing its session
+/// 
+/// Every refresh token can only be exchanged once. Presenting one again means
+/// it was stolen (or the legitimate client lost the race to a thief), so the
+/// whole session is revoked and none of its tokens work anymore.
+/// 
+/// # Arguments
+/// * `refresh_token` - The refresh token sent by the client
+/// * `client` - The device the session is refreshed from
+/// * `pool` - Database connection pool
+/// 
+/// # Returns
+/// Result containing whether the session was refreshed
+pub async fn refresh_session(
+    refresh_token: &str,
+    client: &ClientInfo,
+    pool: &PgPool,
+) -> Result<SessionRefresh, std::io::Error> {
+    let now = Utc::now();
+
+    let mut tx = pool
+        .begin()
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+    let row: Option<(Uuid, bool, bool, String)> = sqlx::query_as(
+        r#"
+      SELECT s.id, t.used_at IS NOT NULL, s.revoked_at IS NULL AND s.expires_at > $2, u.username
+      FROM refresh_tokens t
+      JOIN sessions s ON s.id = t.session_id
+      JOIN users u ON u.id = s.user_id
+      WHERE t.token_hash = $1
+      FOR UPDATE OF t, s
+      "#,
+    )
+    .bind(hash_secret(refresh_token))
+    .bind(now)
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|e| std::io::Error::other(e.to_string()))?;
+
+    let Some((session_id, used, active, username)) = row else {
+        return Ok(SessionRefresh::Invalid);
+    };
+    if used {
+        sqlx::query("UPDATE sessions SET revoked_at = COALESCE(revoked_at, $2) WHERE id = $1")
+            .bind(session_id)
+            .bind(now)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| std::io::Error::other(e.to_string()))?;
+        tx.commit()
+            .await
+            .map_err(|e| std::io::Error::other(e.to_string()))?;
+        return Ok(SessionRefresh::Reused);
+    }
+    if !active {
+        return Ok(SessionRefresh::Invalid);
+    }
+
+    sqlx::query("UPDATE refresh_tokens SET used_at = $2 WHERE token_hash = $1")
+        .bind(hash_secret(refresh_token))
+        .bind(now)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+    sqlx::query(
+        r#"
+      UPDATE sessions SET
+          last_used_at = $2,
+          ip_address = COALESCE($3, ip_address),
+          user_agent = COALESCE($4, user_agent)
+      WHERE id = $1
+      "#,
+    )
+    .bind(session_id)
+    .bind(now)
+    .bind(&client.ip_address)
+    .bind(&client.user_agent)
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| std::io::Error::other(e.to_string()))?;
+    let refresh_token = issue_refresh_token(session_id, &mut tx).await?;
+    tx.commit()
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+
+    Ok(SessionRefresh::Refreshed(IssuedSession {
+        session_id,
+        username,
+        refresh_token,
+    }))
+}
+
+/// Revokes the session a refresh token belongs to, logging it out
+/// 
+/// # Arguments
+/// * `refresh_token` - Any refresh token of the session
+/// * `pool` - Database connection pool
+/// 
+/// # Returns
+/// Result containing whether the refresh token belongs to a session
+pub async fn revoke_session(refresh_token: &str, pool: &PgPool) -> Result<bool, std::io::Error> {
+    let result = sqlx::query(
+        r#"
+      UPDATE sessions s SET revoked_at = COALESCE(s.revoked_at, $2)
+      FROM refresh_tokens t
+      WHERE t.token_hash = $1 AND s.id = t.session_id
+      "#,
+    )
+    .bind(hash_secret(refresh_token))
+    .bind(Utc::now())
+    .execute(pool)
+    .await
+    .map_err(|e| std::io::Error::other(e.to_string()))?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Checks whether an access token's session can still be used
+/// 
+/// The session is recorded as last seen now, at most once a minute to spare
+/// the database a write on every request.
+/// 
+/// # Arguments
+/// * `session_id` - The ID of the session the access token was issued for
+/// * `username` - The username the access token was issued to
+/// * `pool` - Database connection pool
+/// 
+/// # Returns
+/// Result containing whether the session exists, belongs to the user and
+/// wasn't revoked or expired
+pub(crate) async fn is_session_active(
+    session_id: Uuid,
+    username: &str,
+    pool: &PgPool,
+) -> Result<bool, std::io::Error> {
+    let now = Utc::now();
+    let (active,): (bool,) = sqlx::query_as(
+        r#"
+      WITH active AS (
+          SELECT s.id, s.last_used_at FROM sessions s
+          JOIN users u ON u.id = s.user_id
+          WHERE s.id = $1 AND u.username = $2 AND s.revoked_at IS NULL AND s.expires_at > $3
+      ), seen AS (
+          UPDATE sessions SET last_used_at = $3
+          WHERE id IN (SELECT id FROM active WHERE last_used_at < $4)
+      )
+      SELECT EXISTS(SELECT 1 FROM active)
+      "#,
+    )
+    .bind(session_id)
+    .bind(username)
+    .bind(now)
+    .bind(now - Duration::seconds(LAST_SEEN_INTERVAL))
+    .fetch_one(pool)
+    .await
+    .map_err(|e| std::io::Error::other(e.to_string()))?;
+    Ok(active)
+}
+
+/// Lists the active sessions of a user, most recently used first
+/// 
+/// # Arguments
+/// * `user` - The user whose sessions to list
+/// * `current` - The session the request was made with, if any
+/// * `pool` - Database connection pool
+/// 
+/// # Returns
+/// Result containing the sessions that weren't revoked and haven't expired
+pub async fn list_sessions(
+    user: &User,
+    current: Option<Uuid>,
+    pool: &PgPool,
+) -> Result<Vec<Session>, std::io::Error> {
+    sqlx::query_as(
+        r#"
+      SELECT id, created_at, last_used_at, expires_at, ip_address, user_agent, remember_me,
+          COALESCE(id = $2, FALSE) AS current
+      FROM sessions
+      WHERE user_id = $1 AND revoked_at IS NULL AND expires_at > $3
+      ORDER BY last_used_at DESC, id
+      "#,
+    )
+    .bind(user.id)
+    .bind(current)
+    .bind(Utc::now())
+    .fetch_all(pool)
+    .await
+    .map_err(|e| std::io::Error::other(e.to_string()))
+}
+
+/// Revokes a session of a user, logging that device out
+/// 
+/// # Arguments
+/// * `user` - The user owning the session
+/// * `id` - The ID of the session
+/// * `pool` - Database connection pool
+/// 
+/// # Returns
+/// Result indicating success or failure
+pub async fn revoke_user_session(
+    user: &User,
+    id: &str,
+    pool: &PgPool,
+) -> Result<(), std::io::Error> {
+    let uuid = parse_uuid(id)?;
+    let now = Utc::now();
+
+    let result = sqlx::query(
+        r#"
+      UPDATE sessions SET revoked_at = $3
+      WHERE id = $1 AND user_id = $2 AND revoked_at IS NULL AND expires_at > $3
+      "#,
+    )
+    .bind(uuid)
+    .bind(user.id)
+    .bind(now)
+    .execute(pool)
+    .await
+    .map_err(|e| std::io::Error::other(e.to_string()))?;
+    if result.rows_affected() == 0 {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            "Session not found or already logged out",
+        ));
+    }
+
+    Ok(())
+}
+
+/// Revokes every active session of a user except one, logging out other devices
+/// 
+/// # Arguments
+/// * `user` - The user owning the sessions
+/// * `keep` - The ID of the session to keep, usually the current one, or `None` to revoke them all
+/// * `executor` - Database connection or pool
+/// 
+/// # Returns
+/// Result containing the number of revoked sessions
+pub async fn revoke_other_sessions<'e>(
+    user: &User,
+    keep: Option<Uuid>,
+    executor: impl PgExecutor<'e>,
+) -> Result<u64, std::io::Error> {
+    let now = Utc::now();
+
+    let result = sqlx::query(
+        r#"
+      UPDATE sessions SET revoked_at = $3
+      WHERE user_id = $1 AND id IS DISTINCT FROM $2 AND revoked_at IS NULL AND expires_at > $3
+      "#,
+    )
+    .bind(user.id)
+    .bind(keep)
+    .bind(now)
+    .execute(executor)
+    .await
+    .map_err(|e| std::io::Error::other(e.to_string()))?;
+    Ok(result.rows_affected())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::{create_test_user, delete_test_users, init_test_db};
+    use actix_web::test::TestRequest;
+
+    /// Starts a session without remembering the device
+    /// 
+    /// # Arguments
+    /// * `user` - The user logging in
+    /// * `pool` - Database connection pool
+    /// 
+    /// # Returns
+    /// The issued session
+    async fn start_session(user: &User, pool: &PgPool) -> IssuedSession {
+        create_session(user, false, &ClientInfo::default(), pool)
+            .await
+            .unwrap()
+    }
+
+    /// Refreshes a session from an unknown device
+    /// 
+    /// # Arguments
+    /// * `refresh_token` - The refresh token to exchange
+    /// * `pool` - Database connection pool
+    /// 
+    /// # Returns
+    /// The outcome of the refresh
+    async fn refresh(refresh_token: &str, pool: &PgPool) -> SessionRefresh {
+        refresh_session(refresh_token, &ClientInfo::default(), pool)
+            .await
+            .unwrap()
+    }
+
+    /// Tests that a new session is only active for its own user
+    #[actix_rt::test]
+    async fn test_create_session() {
+        let pool = init_test_db().await;
+        let user = create_test_user(&pool, "sessions_create").await;
+
+        let session = start_session(&user, &pool).await;
+        assert_eq!(session.username, user.username);
+        assert!(is_session_active(session.session_id, &user.username, &pool)
+            .await
+            .unwrap());
+        assert!(
+            !is_session_active(session.session_id, "someone_else", &pool)
+                .await
+                .unwrap()
+        );
+
+        delete_test_users(&pool, &[user.id]).await;
+    }
+
+    /// Tests that refreshing a session rotates its refresh token
+    #[actix_rt::test]
+    async fn test_refresh_session() {
+        let pool = init_test_db().await;
+        let user = create_test_user(&pool, "sessions_refresh").await;
+        let session = start_session(&user, &pool).await;
+
+        let SessionRefresh::Refreshed(refreshed) = refresh(&session.refresh_token, &pool).await
+        else {
+            panic!("Expected the session to be refreshed");
+        };
+        assert_eq!(refreshed.session_id, session.session_id);
+        assert_ne!(refreshed.refresh_token, session.refresh_token);
+
+        delete_test_users(&pool, &[user.id]).await;
+    }
+
+    /// Tests that unknown refresh tokens are invalid
+    #[actix_rt::test]
+    async fn test_refresh_unknown_token() {
+        let pool = init_test_db().await;
+
+        assert!(matches!(
+            refresh("unknown", &pool).await,
+            SessionRefresh::Invalid
+        ));
+    }
+
+    /// Tests that reusing an exchanged refresh token revokes the whole session
+    #[actix_rt::test]
+    async fn test_refresh_reused_token() {
+        let pool = init_test_db().await;
+        let user = create_test_user(&pool, "sessions_reuse").await;
+        let session = start_session(&user, &pool).await;
+        let SessionRefresh::Refreshed(refreshed) = refresh(&session.refresh_token, &pool).await
+        else {
+            panic!("Expected the session to be refreshed");
+        };
+
+        assert!(matches!(
+            refresh(&session.refresh_token, &pool).await,
+            SessionRefresh::Reused
+        ));
+        assert!(
+            !is_session_active(session.session_id, &user.username, &pool)
+                .await
+                .unwrap()
+        );
+        assert!(matches!(
+            refresh(&refreshed.refresh_token, &pool).await,
+            SessionRefresh::Invalid
+        ));
+
+        delete_test_users(&pool, &[user.id]).await;
+    }
+
+    /// Tests that the tokens of a session stop working once it is logged out
+    #[actix_rt::test]
+    async fn test_revoke_session() {
+        let pool = init_test_db().await;
+        let user = create_test_user(&pool, "sessions_logout").await;
+        let session = create_session(&user, true, &ClientInfo::default(), &pool)
+            .await
+            .unwrap();
+
+        assert!(revoke_session(&session.refresh_token, &pool).await.unwrap());
+        assert!(
+            !is_session_active(session.session_id, &user.username, &pool)
+                .await
+                .unwrap()
+        );
+        assert!(matches!(
+            refresh(&session.refresh_token, &pool).await,
+            SessionRefresh::Invalid
+        ));
+
+        delete_test_users(&pool, &[user.id]).await;
+    }
+
+    /// Tests that logging out with an unknown refresh token revokes nothing
+    #[actix_rt::test]
+    async fn test_revoke_unknown_session() {
+        let pool = init_test_db().await;
+
+        assert!(!revoke_session("unknown", &pool).await.unwrap());
+    }
+
+    /// Tests reading the device of a request
+    #[test]
+    fn test_client_info() {
+        let req = TestRequest::default()
+            .peer_addr("203.0.113.7:51234".parse().unwrap())
+            .insert_header((header::USER_AGENT, "curl/8.0"))
+            .to_http_request();
+        let client = ClientInfo::from_request(&req);
+        assert_eq!(client.ip_address.as_deref(), Some("203.0.113.7"));
+        assert_eq!(client.user_agent.as_deref(), Some("curl/8.0"));
+
+        let client = ClientInfo::from_request(&TestRequest::default().to_http_request());
+        assert_eq!(client.user_agent, None);
+    }
+
+    /// Tests that sessions are listed with their device, the current one being marked
+    #[actix_rt::test]
+    async fn test_list_sessions() {
+        let pool = init_test_db().await;
+        let user = create_test_user(&pool, "session_list").await;
+        let laptop = ClientInfo {
+            ip_address: Some("198.51.100.1".to_string()),
+            user_agent: Some("Firefox".to_string()),
+        };
+        let current = create_session(&user, true, &laptop, &pool).await.unwrap();
+        let phone = start_session(&user, &pool).await;
+
+        let sessions = list_sessions(&user, Some(current.session_id), &pool)
+            .await
+            .unwrap();
+        assert_eq!(sessions.len(), 2);
+        let listed = sessions
+            .iter()
+            .find(|s| s.id == current.session_id)
+            .unwrap();
+        assert!(listed.current);
+        assert!(listed.remember_me);
+        assert_eq!(listed.ip_address.as_deref(), Some("198.51.100.1"));
+        assert_eq!(listed.user_agent.as_deref(), Some("Firefox"));
+        assert!(sessions
+            .iter()
+            .any(|s| s.id == phone.session_id && !s.current && !s.remember_me));
+
+        delete_test_users(&pool, &[user.id]).await;
+    }
+
+    /// Tests that using a session records when it was last seen
+    #[actix_rt::test]
+    async fn test_session_last_used() {
+        let pool = init_test_db().await;
+        let user = create_test_user(&pool, "session_last_used").await;
+        let current = start_session(&user, &pool).await;
+        let phone = start_session(&user, &pool).await;
+        let long_ago = Utc::now() - Duration::minutes(10);
+        sqlx::query("UPDATE sessions SET last_used_at = $1 WHERE id = $2")
+            .bind(long_ago)
+            .bind(phone.session_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        assert!(is_session_active(phone.session_id, &user.username, &pool)
+            .await
+            .unwrap());
+        let sessions = list_sessions(&user, None, &pool).await.unwrap();
+        assert_eq!(sessions.len(), 2);
+        assert_eq!(sessions[0].id, phone.session_id);
+        assert!(sessions[0].last_used_at > long_ago);
+        assert_eq!(sessions[1].id, current.session_id);
+        assert!(sessions.iter().all(|s| !s.current));
+
+        delete_test_users(&pool, &[user.id]).await;
+    }
+
+    /// Tests that a single session can be revoked once
+    #[actix_rt::test]
+    async fn test_revoke_user_session() {
+        let pool = init_test_db().await;
+        let user = create_test_user(&pool, "session_revoke").await;
+        let phone = start_session(&user, &pool).await;
+        let id = phone.session_id.to_string();
+
+        revoke_user_session(&user, &id, &pool).await.unwrap();
+        assert!(!is_session_active(phone.session_id, &user.username, &pool)
+            .await
+            .unwrap());
+        let result = revoke_user_session(&user, &id, &pool).await;
+        assert!(result.is_err_and(|e| e.kind() == std::io::ErrorKind::NotFound));
+
+        delete_test_users(&pool, &[user.id]).await;
+    }
+
+    /// Tests that sessions of other users can't be revoked
+    #[actix_rt::test]
+    async fn test_revoke_user_session_of_other_user() {
+        let pool = init_test_db().await;
+        let user = create_test_user(&pool, "session_owner").await;
+        let other = create_test_user(&pool, "session_other").await;
+        let phone = start_session(&user, &pool).await;
+
+        let result = revoke_user_session(&other, &phone.session_id.to_string(), &pool).await;
+        assert!(result.is_err_and(|e| e.kind() == std::io::ErrorKind::NotFound));
+        assert!(is_session_active(phone.session_id, &user.username, &pool)
+            .await
+            .unwrap());
+
+        delete_test_users(&pool, &[user.id, other.id]).await;
+    }
+
+    /// Tests that every session but the current one can be revoked at once
+    #[actix_rt::test]
+    async fn test_revoke_other_sessions() {
+        let pool = init_test_db().await;
+        let user = create_test_user(&pool, "session_revoke_others").await;
+        let current = start_session(&user, &pool).await;
+        let tablet = start_session(&user, &pool).await;
+
+        let revoked = revoke_other_sessions(&user, Some(current.session_id), &pool)
+            .await
+            .unwrap();
+        assert_eq!(revoked, 1);
+        assert!(!is_session_active(tablet.session_id, &user.username, &pool)
+            .await
+            .unwrap());
+        let sessions = list_sessions(&user, Some(current.session_id), &pool)
+            .await
+            .unwrap();
+        let ids: Vec<Uuid> = sessions.iter().map(|s| s.id).collect();
+        assert_eq!(ids, vec![current.session_id]);
+
+        delete_test_users(&pool, &[user.id]).await;
+    }
+}
@@ -1,5 +1,6 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use sqlx::types::Json;
 use uuid::Uuid;
 
 /// Represents a user in the system
@@ -23,6 +24,8 @@ pub(crate) struct User {
 pub(crate) struct Claims {
     /// Username of the authenticated user
     pub username: String,
+    /// ID of the session the token was issued for
+    pub sid: Uuid,
     /// Expiration timestamp of the token
     pub exp: usize,
 }
@@ -46,10 +49,355 @@ pub(crate) struct ShortenedUrl {
     /// When the URL was last updated
     pub updated_at: DateTime<Utc>,
 
-    /// ID of the user who owns this shortened URL
-    pub owner: Uuid,    // foreign key. the id of the person that owns this
+    /// ID of the workspace that owns this shortened URL
+    pub owner: Uuid,    // foreign key. the id of the workspace that owns this
     /// Number of times this URL has been accessed
     pub redirects: i64, // use count
+
+    /// HTTP status code returned by the destination on the last health check
+    pub last_status_code: Option<i32>,
+    /// URL the destination ended up at after following redirects
+    pub final_url: Option<String>,
+    /// Error encountered on the last health check, if the request failed
+    pub last_check_error: Option<String>,
+    /// When the destination was last health checked
+    pub last_checked_at: Option<DateTime<Utc>>,
+    /// Whether the last health check found the destination broken
+    pub is_broken: bool,
+
+    /// Title of the destination page
+    pub title: Option<String>,
+    /// Description of the destination page
+    pub description: Option<String>,
+    /// Favicon of the destination page
+    pub favicon_url: Option<String>,
+    /// Open Graph image of the destination page
+    pub og_image_url: Option<String>,
+    /// When the metadata of the destination page was last fetched
+    pub metadata_fetched_at: Option<DateTime<Utc>>,
+    /// Why the last background fetch of the metadata failed, if it did
+    pub metadata_error: Option<String>,
+
+    /// Whether the URL redirects, disabled URLs stay reserved but don't redirect
+    pub enabled: bool,
+    /// When the URL was moved to the trash, trashed URLs stay reserved but don't redirect
+    pub deleted_at: Option<DateTime<Utc>>,
+    /// Incremented on every edit, sent as the ETag of the URL
+    pub version: i64,
+    /// Free-text notes about the URL, such as who requested it or for which campaign
+    pub notes: Option<String>,
+    /// Hostname of the custom domain the URL is served on, `None` for `APP_DOMAIN`
+    pub domain: Option<String>,
+    /// The HTTP status code the URL redirects with, the creator's default redirect type
+    pub redirect_type: i16,
+    /// The full short URL on the URL's domain, filled in along with the tags
+    #[sqlx(skip)]
+    pub link: String,
+
+    /// Tags used to organize the URL, loaded separately from the URL itself
+    #[sqlx(default)]
+    pub tags: Vec<String>,
+}
+
+/// The user editable state of a shortened URL, as kept in its revision history
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct LinkSnapshot {
+    /// The destination of the URL
+    pub original_url: String,
+    /// The short code of the URL
+    pub short_url: String,
+    /// When the URL expires
+    pub expiry_date: Option<DateTime<Utc>>,
+    /// Whether the URL redirects
+    pub enabled: bool,
+    /// The tags of the URL
+    pub tags: Vec<String>,
+    /// The notes of the URL
+    #[serde(default)]
+    pub notes: Option<String>,
+    /// ID of the workspace owning the URL, absent from revisions recorded before transfers existed
+    #[serde(default)]
+    pub owner: Option<Uuid>,
+}
+
+impl From<&ShortenedUrl> for LinkSnapshot {
+    fn from(url: &ShortenedUrl) -> Self {
+        Self {
+            original_url: url.original_url.clone(),
+            short_url: url.short_url.clone(),
+            expiry_date: url.expiry_date,
+            enabled: url.enabled,
+            tags: url.tags.clone(),
+            notes: url.notes.clone(),
+            owner: Some(url.owner),
+        }
+    }
+}
+
+/// A recorded change to a shortened URL
+#[derive(sqlx::FromRow, Serialize)]
+pub(crate) struct LinkRevision {
+    /// Unique identifier for the revision
+    pub id: Uuid,
+    /// ID of the shortened URL that was changed
+    pub link_id: Uuid,
+    /// ID of the user who made the change, if they still exist
+    pub changed_by: Option<Uuid>,
+    /// Username of the user who made the change, if they still exist
+    pub changed_by_username: Option<String>,
+    /// When the change was made
+    pub changed_at: DateTime<Utc>,
+    /// What kind of change was made
+    pub action: String,
+    /// The state before the change, absent for the revision creating the URL
+    pub old_values: Option<Json<LinkSnapshot>>,
+    /// The state after the change
+    pub new_values: Json<LinkSnapshot>,
+}
+
+/// A shortened URL in the trash
+#[derive(Serialize)]
+pub(crate) struct TrashedUrl {
+    /// The trashed shortened URL
+    #[serde(flatten)]
+    pub link: ShortenedUrl,
+    /// When the URL will be purged for good
+    pub purge_at: DateTime<Utc>,
+}
+
+/// A pending offer to transfer a shortened URL to another user
+#[derive(sqlx::FromRow, Serialize)]
+pub(crate) struct LinkTransfer {
+    /// Unique identifier for the transfer
+    pub id: Uuid,
+    /// ID of the shortened URL being transferred
+    pub link_id: Uuid,
+    /// The short code of the URL being transferred
+    pub short_url: String,
+    /// ID of the user offering the URL
+    pub from_user: Uuid,
+    /// Username of the user offering the URL
+    pub from_username: String,
+    /// ID of the user the URL is offered to
+    pub to_user: Uuid,
+    /// Username of the user the URL is offered to
+    pub to_username: String,
+    /// When the transfer was offered
+    pub created_at: DateTime<Utc>,
+}
+
+/// A custom domain links can be served on besides `APP_DOMAIN`
+#[derive(Debug, sqlx::FromRow, Serialize)]
+pub(crate) struct Domain {
+    /// Unique identifier for the domain
+    pub id: Uuid,
+    /// The lowercase hostname of the domain
+    pub hostname: String,
+    /// ID of the user who added the domain
+    pub owner: Uuid,
+    /// When the domain was added
+    pub created_at: DateTime<Utc>,
+    /// The token proving control of the domain, published in DNS or a well-known file
+    pub verification_token: String,
+    /// Since when the domain is verified, `None` while links can't be served on it
+    pub verified_at: Option<DateTime<Utc>>,
+    /// When the domain was last checked for the verification token
+    pub verification_checked_at: Option<DateTime<Utc>>,
+    /// Why the last check didn't find the verification token, if it didn't
+    pub verification_error: Option<String>,
+}
+
+/// A workspace owning shortened URLs, shared by its members
+#[derive(Debug, sqlx::FromRow, Serialize)]
+pub(crate) struct Workspace {
+    /// Unique identifier for the workspace, the ID of its user for personal workspaces
+    pub id: Uuid,
+    /// The name of the workspace
+    pub name: String,
+    /// Whether this is the personal workspace of a user, which can't be shared
+    pub personal: bool,
+    /// When the workspace was created
+    pub created_at: DateTime<Utc>,
+    /// The role of the user the workspace was loaded for
+    pub role: String,
+}
+
+/// A member of a workspace
+#[derive(Debug, sqlx::FromRow, Serialize)]
+pub(crate) struct WorkspaceMember {
+    /// ID of the member
+    pub user_id: Uuid,
+    /// Username of the member
+    pub username: String,
+    /// The role of the member
+    pub role: String,
+    /// When the member joined the workspace
+    pub joined_at: DateTime<Utc>,
+}
+
+/// A pending invitation for a user to join a workspace
+#[derive(Debug, sqlx::FromRow, Serialize)]
+pub(crate) struct WorkspaceInvitation {
+    /// Unique identifier for the invitation
+    pub id: Uuid,
+    /// ID of the workspace the user is invited to
+    pub workspace_id: Uuid,
+    /// The name of the workspace the user is invited to
+    pub workspace_name: String,
+    /// Username of the invited user
+    pub username: String,
+    /// The role the user gets when accepting
+    pub role: String,
+    /// Username of the member who sent the invitation, if they still exist
+    pub invited_by_username: Option<String>,
+    /// When the invitation was sent
+    pub created_at: DateTime<Utc>,
+}
+
+/// An API key of a user, without the key itself which is only stored hashed
+#[derive(Debug, sqlx::FromRow, Serialize)]
+pub(crate) struct ApiKey {
+    /// Unique identifier for the API key
+    pub id: Uuid,
+    /// The name the user gave the key
+    pub name: String,
+    /// The first characters of the key, to recognize it by
+    pub prefix: String,
+    /// What the key may be used for, such as `links:read`
+    pub scopes: Vec<String>,
+    /// When the key stops working, `None` if it never expires
+    pub expires_at: Option<DateTime<Utc>>,
+    /// When the key was last used to authenticate a request
+    pub last_used_at: Option<DateTime<Utc>>,
+    /// When the key was created
+    pub created_at: DateTime<Utc>,
+}
+
+/// A device a user is logged in on
+#[derive(Debug, sqlx::FromRow, Serialize)]
+pub(crate) struct Session {
+    /// Unique identifier for the session
+    pub id: Uuid,
+    /// When the user logged in
+    pub created_at: DateTime<Utc>,
+    /// When the session was last used
+    pub last_used_at: DateTime<Utc>,
+    /// When the session ends and the user has to log in again
+    pub expires_at: DateTime<Utc>,
+    /// The IP address the session was last used from, if known
+    pub ip_address: Option<String>,
+    /// The user agent the session was last used with, if known
+    pub user_agent: Option<String>,
+    /// Whether the user asked to be remembered when logging in
+    pub remember_me: bool,
+    /// Whether this is the session the request was made with
+    pub current: bool,
+}
+
+/// The profile of a user along with their link statistics and settings
+#[derive(Debug, sqlx::FromRow, Serialize)]
+pub(crate) struct Profile {
+    /// Unique identifier for the user
+    pub id: Uuid,
+    /// Username used for login
+    pub username: String,
+    /// The number of links in the user's personal workspace, excluding the trash
+    pub links: i64,
+    /// The number of links in the trash of the user's personal workspace
+    pub trashed_links: i64,
+    /// The number of redirects of every link in the user's personal workspace
+    pub total_clicks: i64,
+    /// The expiration time in seconds of new links created without one, `None` if they never expire
+    pub default_expiration: Option<i64>,
+    /// The HTTP status code new links redirect with
+    pub default_redirect_type: i16,
+}
+
+/// A newly created API key along with the key itself
+#[derive(Serialize)]
+pub(crate) struct CreatedApiKey {
+    /// The created API key
+    #[serde(flatten)]
+    pub api_key: ApiKey,
+    /// The key to send as a bearer token, which can't be retrieved again
+    pub key: String,
+}
+
+/// A shortened URL whose destination matches the blocklist
+#[derive(Serialize)]
+pub(crate) struct BlocklistedUrl {
+    /// The matching shortened URL
+    pub link: ShortenedUrl,
+    /// The blocklist rule the destination matched
+    pub rule: String,
+}
+
+/// The outcome of a single row of a bulk request
+#[derive(Serialize)]
+pub(crate) struct BulkRowResult {
+    /// The 1-based number of the row in the request
+    pub row: usize,
+    /// The created URL, if the row succeeded
+    pub link: Option<ShortenedUrl>,
+    /// Why the row failed, if it did
+    pub error: Option<String>,
+    /// The input field that caused the row to fail, if known
+    pub target_field: Option<String>,
+}
+
+/// The outcome of a bulk request
+#[derive(Serialize)]
+pub(crate) struct BulkResult {
+    /// The number of rows that succeeded
+    pub succeeded: usize,
+    /// The number of rows that failed
+    pub failed: usize,
+    /// The outcome of every row, in request order
+    pub rows: Vec<BulkRowResult>,
+}
+
+/// A URL a bulk operation couldn't be applied to
+#[derive(Serialize)]
+pub(crate) struct BulkItemFailure {
+    /// The ID of the URL, as given in the request
+    pub id: String,
+    /// Why the operation failed for this URL
+    pub error: String,
+    /// The input field that caused the failure, if known
+    pub target_field: Option<String>,
+}
+
+/// The outcome of a bulk operation on existing URLs
+#[derive(Serialize)]
+pub(crate) struct BulkUpdateResult {
+    /// The number of URLs the operation was applied to
+    pub affected: usize,
+    /// The number of URLs the operation failed for
+    pub failed: usize,
+    /// The URLs the operation failed for
+    pub failures: Vec<BulkItemFailure>,
+}
+
+/// A tag along with the number of URLs using it
+#[derive(Debug, sqlx::FromRow, Serialize)]
+pub(crate) struct TagCount {
+    /// The name of the tag
+    pub name: String,
+    /// The number of URLs with this tag
+    pub links: i64,
+}
+
+/// Pagination metadata for responses containing a page of a list
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Pagination {
+    /// The total number of items matching the filters
+    pub total: i64,
+    /// The maximum number of items per page
+    pub limit: i64,
+    /// Whether there are more items after this page
+    pub has_more: bool,
+    /// The cursor to request the next page with, if there is one
+    pub next_cursor: Option<String>,
 }
 
 /// Standard API response format
@@ -61,6 +409,9 @@ pub struct APIResponse {
     pub error: Option<String>,
     /// Optional response data
     pub data: Option<serde_json::Value>,
+    /// Pagination metadata, only present for paginated lists
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pagination: Option<Pagination>,
 }
 
 impl APIResponse {
@@ -72,6 +423,7 @@ impl APIResponse {
         Self {
             error: Some(error),
             data: None,
+            pagination: None,
         }
     }
 
@@ -84,6 +436,7 @@ impl APIResponse {
         Self {
             error: Some(error),
             data: data.map(|d| serde_json::to_value(d).expect("Failed to serialize data")),
+            pagination: None,
         }
     }
 
@@ -95,6 +448,123 @@ impl APIResponse {
         Self {
             error: None,
             data: Some(serde_json::to_value(data).expect("Failed to serialize data")),
+            pagination: None,
         }
     }
+
+    /// Creates a successful response with a page of a list
+    /// 
+    /// # Arguments
+    /// * `data` - The items on the page
+    /// * `pagination` - Metadata describing the page
+    pub fn page<T: Serialize>(data: T, pagination: Pagination) -> Self {
+        Self {
+            pagination: Some(pagination),
+            ..Self::data(data)
+        }
+    }
+}
+
+/// Identifies which input field caused a request to be rejected
+/// 
+/// This struct is sent as the `data` of an error response so clients can
+/// highlight the offending field
+#[derive(Serialize, Deserialize)]
+pub struct InputTarget {
+    /// The name of the field that failed validation
+    pub target_field: String,
 }
+
+/// A validation error tied to a specific input field
+/// 
+/// This is carried inside a `std::io::Error` of kind `InvalidInput` so that
+/// service functions can keep returning `std::io::Error` while routes can
+/// still report which field was invalid
+#[derive(Debug)]
+pub struct FieldError {
+    /// The name of the field that failed validation
+    pub target_field: String,
+    /// Human readable description of the problem
+    pub message: String,
+}
+
+impl FieldError {
+    /// Creates an `InvalidInput` error targeting the given field
+    /// 
+    /// # Arguments
+    /// * `target_field` - The name of the invalid field
+    /// * `message` - The error message to report
+    pub fn invalid(target_field: &str, message: impl Into<String>) -> std::io::Error {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            FieldError {
+                target_field: target_field.to_string(),
+                message: message.into(),
+            },
+        )
+    }
+
+    /// Creates an `AlreadyExists` error targeting the given field
+    /// 
+    /// # Arguments
+    /// * `target_field` - The name of the field whose value is taken
+    /// * `message` - The error message to report
+    pub fn conflict(target_field: &str, message: impl Into<String>) -> std::io::Error {
+        std::io::Error::new(
+            std::io::ErrorKind::AlreadyExists,
+            FieldError {
+                target_field: target_field.to_string(),
+                message: message.into(),
+            },
+        )
+    }
+}
+
+impl std::fmt::Display for FieldError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for FieldError {}
+
+/// An edit based on an outdated version of a shortened URL
+/// 
+/// This is carried inside a `std::io::Error` so that routes can respond with
+/// the current state of the URL
+pub struct VersionConflict {
+    /// The current state of the URL
+    pub current: Box<ShortenedUrl>,
+}
+
+impl VersionConflict {
+    /// Creates an error reporting the current state of the URL
+    /// 
+    /// # Arguments
+    /// * `current` - The URL as it currently is
+    pub fn error(current: ShortenedUrl) -> std::io::Error {
+        std::io::Error::other(VersionConflict {
+            current: Box::new(current),
+        })
+    }
+}
+
+impl std::fmt::Debug for VersionConflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("VersionConflict")
+            .field("id", &self.current.id)
+            .field("version", &self.current.version)
+            .finish()
+    }
+}
+
+impl std::fmt::Display for VersionConflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "This URL was changed since you last loaded it, review the current version and try again"
+        )
+    }
+}
+
+impl std::error::Error for VersionConflict {}
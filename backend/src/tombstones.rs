@@ -0,0 +1,299 @@
+use chrono::{DateTime, Duration, Utc};
+use sqlx::{PgConnection, PgPool};
+use uuid::Uuid;
+
+use crate::constants::{TOMBSTONE_DAYS, TOMBSTONE_PERMANENT_CLICKS};
+
+/// A permanently deleted shortened URL whose short code is tombstoned
+#[derive(sqlx::FromRow)]
+pub(crate) struct DeletedCode {
+    /// The short code of the deleted URL
+    pub short_url: String,
+    /// Hostname of the custom domain the deleted URL was served on
+    pub domain: Option<String>,
+    /// ID of the workspace that owned the deleted URL
+    pub owner: Uuid,
+    /// Number of times the deleted URL was accessed
+    pub redirects: i64,
+}
+
+/// Tombstones the short codes of permanently deleted URLs
+/// 
+/// Codes are tombstoned on the domain the URL was served on. Tombstoned codes
+/// can only be claimed again by the workspace that owned them, for
+/// `TOMBSTONE_DAYS` or forever if the URL had more than
+/// `TOMBSTONE_PERMANENT_CLICKS` clicks. A code that is tombstoned again keeps
+/// the highest click count it ever had.
+/// 
+/// # Arguments
+/// * `deleted` - The URLs that were deleted
+/// * `conn` - Database connection, within the transaction deleting the URLs
+/// 
+/// # Returns
+/// Result indicating success or failure
+pub(crate) async fn tombstone_codes(
+    deleted: &[DeletedCode],
+    conn: &mut PgConnection,
+) -> Result<(), std::io::Error> {
+    let deleted_at = Utc::now();
+    let expires_at = deleted_at + Duration::days(*TOMBSTONE_DAYS);
+
+    for code in deleted {
+        sqlx::query(
+            r#"
+          INSERT INTO short_code_tombstones AS t (short_url, owner, redirects, deleted_at, expires_at, domain)
+          VALUES ($1, $2, $3, $4, CASE WHEN $6 > 0 AND $3 > $6 THEN NULL ELSE $5 END, $7)
+          ON CONFLICT (domain, short_url) DO UPDATE SET
+              owner = EXCLUDED.owner,
+              redirects = GREATEST(t.redirects, EXCLUDED.redirects),
+              deleted_at = EXCLUDED.deleted_at,
+              expires_at = CASE
+                  WHEN $6 > 0 AND GREATEST(t.redirects, EXCLUDED.redirects) > $6 THEN NULL
+                  ELSE $5
+              END
+          "#,
+        )
+        .bind(&code.short_url)
+        .bind(code.owner)
+        .bind(code.redirects)
+        .bind(deleted_at)
+        .bind(expires_at)
+        .bind(*TOMBSTONE_PERMANENT_CLICKS)
+        .bind(&code.domain)
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+    }
+
+    Ok(())
+}
+
+/// Checks that a short code isn't tombstoned for another workspace
+/// 
+/// # Arguments
+/// * `short_url` - The short code being claimed
+/// * `domain` - Hostname of the custom domain the code is claimed on, `None` for `APP_DOMAIN`
+/// * `owner` - The ID of the workspace claiming it
+/// * `conn` - Database connection
+/// 
+/// # Returns
+/// Result indicating whether the workspace may use the short code
+pub(crate) async fn ensure_claimable(
+    short_url: &str,
+    domain: Option<&str>,
+    owner: Uuid,
+    conn: &mut PgConnection,
+) -> Result<(), std::io::Error> {
+    let tombstone: Option<(Option<Uuid>,)> = sqlx::query_as(
+        "SELECT owner FROM short_code_tombstones WHERE short_url = $1 AND domain IS NOT DISTINCT FROM $2 AND (expires_at IS NULL OR expires_at > NOW())",
+    )
+    .bind(short_url)
+    .bind(domain)
+    .fetch_optional(conn)
+    .await
+    .map_err(|e| std::io::Error::other(e.to_string()))?;
+
+    match tombstone {
+        Some((previous,)) if previous != Some(owner) => Err(std::io::Error::new(
+            std::io::ErrorKind::AlreadyExists,
+            "This short URL belonged to a deleted link and is reserved for its previous owner",
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Removes the tombstones whose reservation period has ended
+/// 
+/// # Arguments
+/// * `now` - The current time
+/// * `pool` - Database connection pool
+/// 
+/// # Returns
+/// Result containing the number of removed tombstones
+pub(crate) async fn purge_expired_tombstones(
+    now: DateTime<Utc>,
+    pool: &PgPool,
+) -> Result<u64, std::io::Error> {
+    sqlx::query("DELETE FROM short_code_tombstones WHERE expires_at <= $1")
+        .bind(now)
+        .execute(pool)
+        .await
+        .map(|r| r.rows_affected())
+        .map_err(|e| std::io::Error::other(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        service::{create_url, delete_url},
+        structs::ShortenedUrl,
+        trash::purge_url,
+        utils::{create_test_user, delete_test_users, init_test_db},
+        workspaces::Member,
+    };
+
+    fn unique_slug() -> String {
+        format!("tomb_{}", &Uuid::new_v4().to_string()[..8])
+    }
+
+    async fn create(
+        member: &Member,
+        slug: &str,
+        pool: &PgPool,
+    ) -> Result<ShortenedUrl, std::io::Error> {
+        create_url(
+            member,
+            "https://example.com/",
+            Some(slug.to_string()),
+            None,
+            None,
+            &[],
+            None,
+            pool,
+        )
+        .await
+    }
+
+    /// Moves a URL to the trash and permanently deletes it
+    async fn purge(member: &Member, id: Uuid, pool: &PgPool) {
+        delete_url(member, &id.to_string(), pool).await.unwrap();
+        purge_url(member, &id.to_string(), pool).await.unwrap();
+    }
+
+    async fn tombstone_expiry(slug: &str, pool: &PgPool) -> Option<DateTime<Utc>> {
+        let (expires_at,): (Option<DateTime<Utc>>,) =
+            sqlx::query_as("SELECT expires_at FROM short_code_tombstones WHERE short_url = $1")
+                .bind(slug)
+                .fetch_one(pool)
+                .await
+                .unwrap();
+        expires_at
+    }
+
+    /// Tests that other workspaces can't claim the code of a permanently deleted URL
+    #[actix_rt::test]
+    async fn test_tombstone_blocks_other_workspaces() {
+        let pool = init_test_db().await;
+        let user = create_test_user(&pool, "tombstone").await;
+        let member = Member::personal(&user);
+        let other = create_test_user(&pool, "tombstone_other").await;
+        let slug = unique_slug();
+
+        let url = create(&member, &slug, &pool).await.unwrap();
+        purge(&member, url.id, &pool).await;
+
+        let result = create(&Member::personal(&other), &slug, &pool).await;
+        assert!(result.is_err_and(|e| e.kind() == std::io::ErrorKind::AlreadyExists));
+
+        delete_test_users(&pool, &[user.id, other.id]).await;
+    }
+
+    /// Tests that the owner can reclaim the code, which is then reserved for a limited time
+    #[actix_rt::test]
+    async fn test_owner_reclaims_tombstoned_code() {
+        let pool = init_test_db().await;
+        let user = create_test_user(&pool, "tombstone_reclaim").await;
+        let member = Member::personal(&user);
+        let slug = unique_slug();
+
+        let url = create(&member, &slug, &pool).await.unwrap();
+        purge(&member, url.id, &pool).await;
+
+        let url = create(&member, &slug, &pool).await.unwrap();
+        purge(&member, url.id, &pool).await;
+        assert!(tombstone_expiry(&slug, &pool).await.is_some());
+
+        delete_test_users(&pool, &[user.id]).await;
+    }
+
+    /// Tests that codes of URLs with more than `TOMBSTONE_PERMANENT_CLICKS` clicks are reserved forever
+    #[actix_rt::test]
+    async fn test_popular_code_reserved_forever() {
+        let pool = init_test_db().await;
+        let user = create_test_user(&pool, "tombstone_popular").await;
+        let member = Member::personal(&user);
+
+        for (clicks, forever) in [
+            (*TOMBSTONE_PERMANENT_CLICKS, false),
+            (*TOMBSTONE_PERMANENT_CLICKS + 1, true),
+        ] {
+            let slug = unique_slug();
+            let url = create(&member, &slug, &pool).await.unwrap();
+            sqlx::query("UPDATE shortened_urls SET redirects = $1 WHERE id = $2")
+                .bind(clicks)
+                .bind(url.id)
+                .execute(&pool)
+                .await
+                .unwrap();
+            purge(&member, url.id, &pool).await;
+
+            assert_eq!(tombstone_expiry(&slug, &pool).await.is_none(), forever);
+        }
+
+        delete_test_users(&pool, &[user.id]).await;
+    }
+
+    /// Tests that an expired tombstone frees the code for everyone
+    #[actix_rt::test]
+    async fn test_expired_tombstone_frees_code() {
+        let pool = init_test_db().await;
+        let user = create_test_user(&pool, "tombstone_expired").await;
+        let member = Member::personal(&user);
+        let other = create_test_user(&pool, "tombstone_claimer").await;
+        let slug = unique_slug();
+
+        let url = create(&member, &slug, &pool).await.unwrap();
+        purge(&member, url.id, &pool).await;
+        sqlx::query("UPDATE short_code_tombstones SET expires_at = NOW() WHERE short_url = $1")
+            .bind(&slug)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let claimed = create(&Member::personal(&other), &slug, &pool)
+            .await
+            .unwrap();
+        assert_eq!(claimed.owner, other.id);
+
+        delete_test_users(&pool, &[user.id, other.id]).await;
+    }
+
+    /// Tests that expired tombstones are removed and permanent ones kept
+    #[actix_rt::test]
+    async fn test_purge_expired_tombstones() {
+        let pool = init_test_db().await;
+        let user = create_test_user(&pool, "tombstone_purge").await;
+        let member = Member::personal(&user);
+        let expired = unique_slug();
+        let popular = unique_slug();
+
+        for slug in [&expired, &popular] {
+            let url = create(&member, slug, &pool).await.unwrap();
+            purge(&member, url.id, &pool).await;
+        }
+        sqlx::query("UPDATE short_code_tombstones SET expires_at = NOW() WHERE short_url = $1")
+            .bind(&expired)
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query("UPDATE short_code_tombstones SET expires_at = NULL WHERE short_url = $1")
+            .bind(&popular)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let purged = purge_expired_tombstones(Utc::now(), &pool).await.unwrap();
+        assert!(purged >= 1);
+        let remaining: Vec<String> = sqlx::query_scalar(
+            "SELECT short_url FROM short_code_tombstones WHERE short_url = ANY($1)",
+        )
+        .bind(vec![&expired, &popular])
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+        assert_eq!(remaining, vec![popular]);
+
+        delete_test_users(&pool, &[user.id]).await;
+    }
+}
@@ -0,0 +1,491 @@
+use chrono::Utc;
+use sqlx::{PgConnection, PgPool};
+use uuid::Uuid;
+
+use crate::{
+    history::{record_revision, RevisionAction},
+    service::{load_tags, lock_owned_url, parse_uuid, prune_unused_tags, set_url_tags},
+    structs::{FieldError, LinkTransfer, ShortenedUrl, User},
+    workspaces::{Member, WorkspaceRole},
+};
+
+/// Selects transfers along with the short code and the usernames involved
+const SELECT_TRANSFERS: &str = r#"
+      SELECT t.id, t.link_id, s.short_url, t.from_user, f.username AS from_username,
+          t.to_user, r.username AS to_username, t.created_at
+      FROM link_transfers t
+      JOIN shortened_urls s ON s.id = t.link_id
+      JOIN users f ON f.id = t.from_user
+      JOIN users r ON r.id = t.to_user
+      "#;
+
+/// Loads a transfer by its ID
+/// 
+/// # Arguments
+/// * `id` - The ID of the transfer
+/// * `conn` - Database connection
+/// 
+/// # Returns
+/// Result containing the LinkTransfer
+async fn fetch_transfer(id: Uuid, conn: &mut PgConnection) -> Result<LinkTransfer, std::io::Error> {
+    sqlx::query_as(&format!("{} WHERE t.id = $1", SELECT_TRANSFERS))
+        .bind(id)
+        .fetch_one(conn)
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))
+}
+
+/// Checks that a URL isn't on a custom domain, which can't be moved along with it
+/// 
+/// # Arguments
+/// * `url` - The URL to transfer
+/// 
+/// # Returns
+/// Result indicating whether the URL can be transferred
+fn ensure_transferable(url: &ShortenedUrl) -> Result<(), std::io::Error> {
+    if url.domain.is_some() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "URLs on a custom domain can't be transferred, move them to the default domain first",
+        ));
+    }
+
+    Ok(())
+}
+
+/// Offers one of a workspace's shortened URLs to another user
+/// 
+/// The URL stays in its workspace until the recipient accepts. Offering a URL
+/// that already has a pending transfer replaces that transfer. URLs on a
+/// custom domain can't be transferred, since the domain stays with its owner.
+/// 
+/// # Arguments
+/// * `member` - The member offering the URL, at least an admin of the workspace owning it
+/// * `id` - The ID of the URL to transfer
+/// * `username` - The username of the user to offer the URL to
+/// * `pool` - Database connection pool
+/// 
+/// # Returns
+/// Result containing the pending LinkTransfer
+pub async fn offer_transfer(
+    member: &Member,
+    id: &str,
+    username: &str,
+    pool: &PgPool,
+) -> Result<LinkTransfer, std::io::Error> {
+    member.require(WorkspaceRole::Admin)?;
+    let uuid = parse_uuid(id)?;
+
+    let recipient: (Uuid,) = sqlx::query_as("SELECT id FROM users WHERE username = $1")
+        .bind(username.trim())
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))?
+        .ok_or(FieldError::invalid("username", "User not found"))?;
+    if recipient.0 == member.user.id {
+        return Err(FieldError::invalid(
+            "username",
+            "You can't transfer a URL to yourself",
+        ));
+    }
+
+    let mut tx = pool
+        .begin()
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+    let url = lock_owned_url(member.workspace_id, uuid, &mut tx).await?;
+    ensure_transferable(&url)?;
+
+    let (transfer_id,): (Uuid,) = sqlx::query_as(
+        r#"
+      INSERT INTO link_transfers (id, link_id, from_user, to_user, created_at, from_workspace)
+      VALUES ($1, $2, $3, $4, $5, $6)
+      ON CONFLICT (link_id) DO UPDATE SET
+          id = EXCLUDED.id,
+          from_user = EXCLUDED.from_user,
+          to_user = EXCLUDED.to_user,
+          created_at = EXCLUDED.created_at,
+          from_workspace = EXCLUDED.from_workspace
+      RETURNING id
+      "#,
+    )
+    .bind(Uuid::new_v4())
+    .bind(uuid)
+    .bind(member.user.id)
+    .bind(recipient.0)
+    .bind(Utc::now())
+    .bind(member.workspace_id)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|e| std::io::Error::other(e.to_string()))?;
+
+    let transfer = fetch_transfer(transfer_id, &mut tx).await?;
+    tx.commit()
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+    Ok(transfer)
+}
+
+/// Withdraws the pending transfer of one of a workspace's shortened URLs
+/// 
+/// # Arguments
+/// * `member` - The member withdrawing the transfer, at least an admin of the workspace owning the URL
+/// * `id` - The ID of the URL
+/// * `pool` - Database connection pool
+/// 
+/// # Returns
+/// Result indicating success or failure
+pub async fn cancel_transfer(
+    member: &Member,
+    id: &str,
+    pool: &PgPool,
+) -> Result<(), std::io::Error> {
+    member.require(WorkspaceRole::Admin)?;
+    let uuid = parse_uuid(id)?;
+
+    let result =
+        sqlx::query("DELETE FROM link_transfers WHERE link_id = $1 AND from_workspace = $2")
+            .bind(uuid)
+            .bind(member.workspace_id)
+            .execute(pool)
+            .await
+            .map_err(|e| std::io::Error::other(e.to_string()))?;
+    if result.rows_affected() == 0 {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            "No pending transfer found for this URL",
+        ));
+    }
+
+    Ok(())
+}
+
+/// Lists the pending transfers offered by or to a user, newest first
+/// 
+/// # Arguments
+/// * `user` - The user whose transfers to list
+/// * `pool` - Database connection pool
+/// 
+/// # Returns
+/// Result containing the incoming and outgoing transfers
+pub async fn list_transfers(
+    user: &User,
+    pool: &PgPool,
+) -> Result<Vec<LinkTransfer>, std::io::Error> {
+    sqlx::query_as(&format!(
+        "{} WHERE t.from_user = $1 OR t.to_user = $1 ORDER BY t.created_at DESC, t.id",
+        SELECT_TRANSFERS
+    ))
+    .bind(user.id)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| std::io::Error::other(e.to_string()))
+}
+
+/// Accepts a transfer offered to a user, moving the URL into the workspace they selected
+/// 
+/// The URL keeps its tags, which are moved to the new workspace, and the change
+/// of ownership is recorded in the URL's revision history. URLs that were moved
+/// to a custom domain since they were offered can't be accepted.
+/// 
+/// # Arguments
+/// * `member` - The user the URL was offered to, at least an editor of the receiving workspace
+/// * `transfer_id` - The ID of the transfer
+/// * `pool` - Database connection pool
+/// 
+/// # Returns
+/// Result containing the transferred ShortenedUrl
+pub async fn accept_transfer(
+    member: &Member,
+    transfer_id: &str,
+    pool: &PgPool,
+) -> Result<ShortenedUrl, std::io::Error> {
+    member.require(WorkspaceRole::Editor)?;
+    let uuid = parse_uuid(transfer_id)?;
+
+    let mut tx = pool
+        .begin()
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+    let (link_id, from_workspace): (Uuid, Uuid) = sqlx::query_as(
+        "DELETE FROM link_transfers WHERE id = $1 AND to_user = $2 RETURNING link_id, from_workspace",
+    )
+    .bind(uuid)
+    .bind(member.user.id)
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|e| std::io::Error::other(e.to_string()))?
+    .ok_or(std::io::Error::new(
+        std::io::ErrorKind::NotFound,
+        "Transfer not found or it wasn't offered to you",
+    ))?;
+
+    // The URL may have been moved to the trash since it was offered
+    let previous = lock_owned_url(from_workspace, link_id, &mut tx)
+        .await
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                "The URL of this transfer is no longer available",
+            ),
+            _ => e,
+        })?;
+    // The URL may have been moved to a custom domain since it was offered
+    ensure_transferable(&previous)?;
+
+    let mut url: ShortenedUrl = sqlx::query_as(
+        "UPDATE shortened_urls SET owner = $1, updated_at = $2, version = version + 1 WHERE id = $3 RETURNING *",
+    )
+    .bind(member.workspace_id)
+    .bind(Utc::now())
+    .bind(link_id)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|e| std::io::Error::other(e.to_string()))?;
+
+    // Tags belong to their workspace, so the URL's tags are recreated in the receiving one
+    set_url_tags(member.workspace_id, link_id, &previous.tags, &mut tx).await?;
+    prune_unused_tags(from_workspace, &mut *tx).await?;
+    load_tags(std::slice::from_mut(&mut url), &mut *tx).await?;
+    record_revision(
+        member.user.id,
+        RevisionAction::Transfer,
+        Some(&previous),
+        &url,
+        &mut tx,
+    )
+    .await?;
+    tx.commit()
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+
+    Ok(url)
+}
+
+/// Declines a transfer offered to a user, leaving the URL with its owner
+/// 
+/// # Arguments
+/// * `user` - The user the URL was offered to
+/// * `transfer_id` - The ID of the transfer
+/// * `pool` - Database connection pool
+/// 
+/// # Returns
+/// Result indicating success or failure
+pub async fn decline_transfer(
+    user: &User,
+    transfer_id: &str,
+    pool: &PgPool,
+) -> Result<(), std::io::Error> {
+    let uuid = parse_uuid(transfer_id)?;
+
+    let result = sqlx::query("DELETE FROM link_transfers WHERE id = $1 AND to_user = $2")
+        .bind(uuid)
+        .bind(user.id)
+        .execute(pool)
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+    if result.rows_affected() == 0 {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            "Transfer not found or it wasn't offered to you",
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        history::list_revisions,
+        service::{create_url, update_url},
+        structs::{ShortenedUrl, User},
+        utils::{add_test_domain, create_test_user, delete_test_users, init_test_db},
+    };
+
+    /// Creates a sender and a recipient, the sender owning a URL tagged `handover`
+    /// 
+    /// # Arguments
+    /// * `prefix` - Prefix of the generated usernames
+    /// * `pool` - Database connection pool
+    /// 
+    /// # Returns
+    /// The sender, the recipient and the ID of the URL
+    async fn setup(prefix: &str, pool: &PgPool) -> (User, User, String) {
+        let user = create_test_user(pool, prefix).await;
+        let other = create_test_user(pool, &format!("{}_other", prefix)).await;
+        let url = create_url(
+            &Member::personal(&user),
+            "https://example.com/",
+            None,
+            None,
+            None,
+            &["handover".to_string()],
+            None,
+            pool,
+        )
+        .await
+        .unwrap();
+        (user, other, url.id.to_string())
+    }
+
+    async fn update_destination(
+        member: &Member,
+        id: &str,
+        original_url: &str,
+        pool: &PgPool,
+    ) -> Result<ShortenedUrl, std::io::Error> {
+        update_url(member, id, pool, original_url, None, None, None, None, None).await
+    }
+
+    /// Tests that other users can't update a URL or offer it to anyone
+    #[actix_rt::test]
+    async fn test_other_user_cannot_take_over() {
+        let pool = init_test_db().await;
+        let (user, other, id) = setup("transfer_takeover", &pool).await;
+        let other_member = Member::personal(&other);
+
+        let result =
+            update_destination(&other_member, &id, "https://example.com/taken", &pool).await;
+        assert!(result.is_err_and(|e| e.kind() == std::io::ErrorKind::NotFound));
+        let result = offer_transfer(&other_member, &id, &user.username, &pool).await;
+        assert!(result.is_err_and(|e| e.kind() == std::io::ErrorKind::NotFound));
+
+        delete_test_users(&pool, &[user.id, other.id]).await;
+    }
+
+    /// Tests that a URL can't be offered to its own owner
+    #[actix_rt::test]
+    async fn test_offer_transfer_to_self() {
+        let pool = init_test_db().await;
+        let (user, other, id) = setup("transfer_self", &pool).await;
+
+        let result = offer_transfer(&Member::personal(&user), &id, &user.username, &pool).await;
+        assert!(result.is_err_and(|e| e.kind() == std::io::ErrorKind::InvalidInput));
+
+        delete_test_users(&pool, &[user.id, other.id]).await;
+    }
+
+    /// Tests that URLs on a custom domain can't be offered
+    #[actix_rt::test]
+    async fn test_offer_transfer_custom_domain() {
+        let pool = init_test_db().await;
+        let (user, other, _) = setup("transfer_domain", &pool).await;
+        let member = Member::personal(&user);
+        let domain = add_test_domain(&pool, &user, true).await;
+        let url = create_url(
+            &member,
+            "https://example.com/",
+            None,
+            Some(domain.hostname.as_str()),
+            None,
+            &[],
+            None,
+            &pool,
+        )
+        .await
+        .unwrap();
+
+        let result = offer_transfer(&member, &url.id.to_string(), &other.username, &pool).await;
+        assert!(result.is_err_and(|e| e.kind() == std::io::ErrorKind::InvalidInput));
+
+        delete_test_users(&pool, &[user.id, other.id]).await;
+    }
+
+    /// Tests that only the recipient sees and can answer an offer, declining leaving the URL with its owner
+    #[actix_rt::test]
+    async fn test_decline_transfer() {
+        let pool = init_test_db().await;
+        let (user, other, id) = setup("transfer_decline", &pool).await;
+        let member = Member::personal(&user);
+
+        let transfer = offer_transfer(&member, &id, &other.username, &pool)
+            .await
+            .unwrap();
+        assert_eq!(transfer.to_username, other.username);
+        assert_eq!(list_transfers(&other, &pool).await.unwrap().len(), 1);
+        let result = accept_transfer(&member, &transfer.id.to_string(), &pool).await;
+        assert!(result.is_err_and(|e| e.kind() == std::io::ErrorKind::NotFound));
+
+        decline_transfer(&other, &transfer.id.to_string(), &pool)
+            .await
+            .unwrap();
+        assert!(list_transfers(&user, &pool).await.unwrap().is_empty());
+        update_destination(&member, &id, "https://example.com/kept", &pool)
+            .await
+            .unwrap();
+
+        delete_test_users(&pool, &[user.id, other.id]).await;
+    }
+
+    /// Tests that accepting moves the URL and its tags to the recipient
+    #[actix_rt::test]
+    async fn test_accept_transfer() {
+        let pool = init_test_db().await;
+        let (user, other, id) = setup("transfer_accept", &pool).await;
+
+        let transfer = offer_transfer(&Member::personal(&user), &id, &other.username, &pool)
+            .await
+            .unwrap();
+        let accepted = accept_transfer(&Member::personal(&other), &transfer.id.to_string(), &pool)
+            .await
+            .unwrap();
+        assert_eq!(accepted.owner, other.id);
+        assert_eq!(accepted.tags, vec!["handover"]);
+
+        let (user_tags,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM tags WHERE owner = $1")
+            .bind(user.id)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(user_tags, 0);
+
+        delete_test_users(&pool, &[user.id, other.id]).await;
+    }
+
+    /// Tests that an accepted transfer is recorded in the history of the URL
+    #[actix_rt::test]
+    async fn test_accept_transfer_recorded() {
+        let pool = init_test_db().await;
+        let (user, other, id) = setup("transfer_history", &pool).await;
+        let other_member = Member::personal(&other);
+
+        let transfer = offer_transfer(&Member::personal(&user), &id, &other.username, &pool)
+            .await
+            .unwrap();
+        accept_transfer(&other_member, &transfer.id.to_string(), &pool)
+            .await
+            .unwrap();
+
+        let revisions = list_revisions(&other_member, &id, &pool).await.unwrap();
+        assert_eq!(revisions[0].action, "transfer");
+        let old = revisions[0].old_values.as_ref().unwrap();
+        assert_eq!(old.owner, Some(user.id));
+        assert_eq!(revisions[0].new_values.owner, Some(other.id));
+
+        delete_test_users(&pool, &[user.id, other.id]).await;
+    }
+
+    /// Tests that the previous owner loses access once the transfer is accepted
+    #[actix_rt::test]
+    async fn test_previous_owner_loses_access() {
+        let pool = init_test_db().await;
+        let (user, other, id) = setup("transfer_previous", &pool).await;
+        let member = Member::personal(&user);
+
+        let transfer = offer_transfer(&member, &id, &other.username, &pool)
+            .await
+            .unwrap();
+        accept_transfer(&Member::personal(&other), &transfer.id.to_string(), &pool)
+            .await
+            .unwrap();
+
+        let result = update_destination(&member, &id, "https://example.com/back", &pool).await;
+        assert!(result.is_err_and(|e| e.kind() == std::io::ErrorKind::NotFound));
+        let result = cancel_transfer(&member, &id, &pool).await;
+        assert!(result.is_err_and(|e| e.kind() == std::io::ErrorKind::NotFound));
+
+        delete_test_users(&pool, &[user.id, other.id]).await;
+    }
+}
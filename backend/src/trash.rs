@@ -0,0 +1,373 @@
+use chrono::{Duration, Utc};
+use sqlx::{PgConnection, PgPool};
+use std::collections::HashSet;
+use uuid::Uuid;
+
+use crate::{
+    constants::{TRASH_PURGE_INTERVAL, TRASH_RETENTION_DAYS},
+    service::{load_tags, parse_uuid, prune_unused_tags},
+    structs::{ShortenedUrl, TrashedUrl},
+    tombstones::{purge_expired_tombstones, tombstone_codes, DeletedCode},
+    workspaces::{Member, WorkspaceRole},
+};
+
+/// Returns how long trashed URLs are kept before they are purged
+fn retention() -> Duration {
+    Duration::days(*TRASH_RETENTION_DAYS)
+}
+
+/// Lists the shortened URLs in a workspace's trash, most recently trashed first
+/// 
+/// # Arguments
+/// * `member` - A member of the workspace whose trash to list
+/// * `pool` - Database connection pool
+/// 
+/// # Returns
+/// Result containing the trashed URLs along with when they will be purged
+pub async fn list_trash(member: &Member, pool: &PgPool) -> Result<Vec<TrashedUrl>, std::io::Error> {
+    let mut urls: Vec<ShortenedUrl> = sqlx::query_as(
+        "SELECT * FROM shortened_urls WHERE owner = $1 AND deleted_at IS NOT NULL ORDER BY deleted_at DESC, id",
+    )
+    .bind(member.workspace_id)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| std::io::Error::other(e.to_string()))?;
+    load_tags(&mut urls, pool).await?;
+
+    Ok(urls
+        .into_iter()
+        .map(|link| TrashedUrl {
+            purge_at: link.deleted_at.unwrap_or_default() + retention(),
+            link,
+        })
+        .collect())
+}
+
+/// Restores a shortened URL from a workspace's trash
+/// 
+/// # Arguments
+/// * `member` - The member restoring the URL, at least an editor of the workspace owning it
+/// * `id` - The ID of the URL to restore
+/// * `pool` - Database connection pool
+/// 
+/// # Returns
+/// Result containing the restored ShortenedUrl
+pub async fn restore_from_trash(
+    member: &Member,
+    id: &str,
+    pool: &PgPool,
+) -> Result<ShortenedUrl, std::io::Error> {
+    member.require(WorkspaceRole::Editor)?;
+    let uuid = parse_uuid(id)?;
+
+    let mut url: ShortenedUrl = sqlx::query_as(
+        r#"
+      UPDATE shortened_urls
+      SET deleted_at = NULL, updated_at = $1, version = version + 1
+      WHERE id = $2 AND owner = $3 AND deleted_at IS NOT NULL
+      RETURNING *
+      "#,
+    )
+    .bind(Utc::now())
+    .bind(uuid)
+    .bind(member.workspace_id)
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| std::io::Error::other(e.to_string()))?
+    .ok_or(std::io::Error::new(
+        std::io::ErrorKind::NotFound,
+        "URL not found in the trash or you don't have permission to restore it",
+    ))?;
+
+    load_tags(std::slice::from_mut(&mut url), pool).await?;
+    Ok(url)
+}
+
+/// Permanently deletes a shortened URL from a workspace's trash and tombstones its short code
+/// 
+/// # Arguments
+/// * `owner` - The ID of the workspace owning the URL
+/// * `id` - The ID of the URL to purge
+/// * `conn` - Database connection
+/// 
+/// # Returns
+/// Result indicating success or failure
+pub(crate) async fn purge_owned_url(
+    owner: Uuid,
+    id: Uuid,
+    conn: &mut PgConnection,
+) -> Result<(), std::io::Error> {
+    let deleted: DeletedCode = sqlx::query_as(
+        r#"
+      DELETE FROM shortened_urls
+      WHERE id = $1 AND owner = $2 AND deleted_at IS NOT NULL
+      RETURNING short_url, domain, owner, redirects
+      "#,
+    )
+    .bind(id)
+    .bind(owner)
+    .fetch_optional(&mut *conn)
+    .await
+    .map_err(|e| std::io::Error::other(e.to_string()))?
+    .ok_or(std::io::Error::new(
+        std::io::ErrorKind::NotFound,
+        "URL not found in the trash or you don't have permission to delete it",
+    ))?;
+
+    // Keep the short code from being claimed by someone else right away
+    tombstone_codes(&[deleted], conn).await?;
+    prune_unused_tags(owner, conn).await
+}
+
+/// Permanently deletes a shortened URL from the trash before its retention ends
+/// 
+/// # Arguments
+/// * `member` - The member purging the URL, at least an editor of the workspace owning it
+/// * `id` - The ID of the URL to purge
+/// * `pool` - Database connection pool
+/// 
+/// # Returns
+/// Result indicating success or failure
+pub async fn purge_url(member: &Member, id: &str, pool: &PgPool) -> Result<(), std::io::Error> {
+    member.require(WorkspaceRole::Editor)?;
+    let uuid = parse_uuid(id)?;
+
+    let mut tx = pool
+        .begin()
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+    purge_owned_url(member.workspace_id, uuid, &mut tx).await?;
+    tx.commit()
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))
+}
+
+/// Permanently deletes every URL that has been in the trash longer than the retention period
+/// 
+/// Their short codes are tombstoned the same as when purging a single URL.
+/// 
+/// # Arguments
+/// * `retention` - How long trashed URLs are kept
+/// * `pool` - Database connection pool
+/// 
+/// # Returns
+/// Result containing the number of purged URLs
+pub(crate) async fn purge_expired_trash(
+    retention: Duration,
+    pool: &PgPool,
+) -> Result<usize, std::io::Error> {
+    let mut tx = pool
+        .begin()
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+
+    let deleted: Vec<DeletedCode> = sqlx::query_as(
+        "DELETE FROM shortened_urls WHERE deleted_at <= $1 RETURNING short_url, domain, owner, redirects",
+    )
+    .bind(Utc::now() - retention)
+    .fetch_all(&mut *tx)
+    .await
+    .map_err(|e| std::io::Error::other(e.to_string()))?;
+
+    tombstone_codes(&deleted, &mut tx).await?;
+    for owner in deleted.iter().map(|d| d.owner).collect::<HashSet<_>>() {
+        prune_unused_tags(owner, &mut *tx).await?;
+    }
+
+    tx.commit()
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+    Ok(deleted.len())
+}
+
+/// Starts the background task that periodically purges URLs past their trash retention
+/// 
+/// The same task removes the tombstones of short codes whose reservation ended.
+/// Nothing is started if `TRASH_PURGE_INTERVAL` is 0.
+/// 
+/// # Arguments
+/// * `pool` - Database connection pool
+pub(crate) fn spawn_trash_purger(pool: PgPool) {
+    if *TRASH_PURGE_INTERVAL == 0 {
+        return;
+    }
+
+    tokio::spawn(async move {
+        let mut interval =
+            tokio::time::interval(std::time::Duration::from_secs(*TRASH_PURGE_INTERVAL));
+        loop {
+            interval.tick().await;
+            match purge_expired_trash(retention(), &pool).await {
+                Ok(purged) => println!("Trash purge finished, {} links purged", purged),
+                Err(e) => println!("Trash purge failed: {}", e),
+            }
+            if let Err(e) = purge_expired_tombstones(Utc::now(), &pool).await {
+                println!("Tombstone purge failed: {}", e);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        service::{create_url, delete_url, list_urls, LinkFilter},
+        utils::{create_test_user, delete_test_users, init_test_db},
+    };
+
+    /// Creates a URL tagged `kept` and moves it to the trash
+    /// 
+    /// # Arguments
+    /// * `member` - The member creating and deleting the URL
+    /// * `pool` - Database connection pool
+    /// 
+    /// # Returns
+    /// The ID and custom path of the URL
+    async fn create_trashed_url(member: &Member, pool: &PgPool) -> (String, String) {
+        let slug = format!("trash_{}", &Uuid::new_v4().to_string()[..8]);
+        let url = create_url(
+            member,
+            "https://example.com/",
+            Some(slug.clone()),
+            None,
+            None,
+            &["kept".to_string()],
+            None,
+            pool,
+        )
+        .await
+        .unwrap();
+        let id = url.id.to_string();
+        delete_url(member, &id, pool).await.unwrap();
+        (id, slug)
+    }
+
+    /// Tests that a deleted URL leaves the listing for the trash
+    #[actix_rt::test]
+    async fn test_deleted_url_moves_to_trash() {
+        let pool = init_test_db().await;
+        let user = create_test_user(&pool, "trash").await;
+        let member = Member::personal(&user);
+        create_trashed_url(&member, &pool).await;
+
+        let (listed, pagination) = list_urls(&member, &LinkFilter::default(), &pool)
+            .await
+            .unwrap();
+        assert!(listed.is_empty());
+        assert_eq!(pagination.total, 0);
+
+        let trash = list_trash(&member, &pool).await.unwrap();
+        assert_eq!(trash.len(), 1);
+        assert_eq!(trash[0].link.tags, vec!["kept"]);
+        assert_eq!(
+            trash[0].purge_at,
+            trash[0].link.deleted_at.unwrap() + retention()
+        );
+
+        delete_test_users(&pool, &[user.id]).await;
+    }
+
+    /// Tests that the short code of a trashed URL stays reserved
+    #[actix_rt::test]
+    async fn test_trashed_short_code_reserved() {
+        let pool = init_test_db().await;
+        let user = create_test_user(&pool, "trash_reserved").await;
+        let other = create_test_user(&pool, "trash_other").await;
+        let (_, slug) = create_trashed_url(&Member::personal(&user), &pool).await;
+
+        let result = create_url(
+            &Member::personal(&other),
+            "https://example.com/",
+            Some(slug),
+            None,
+            None,
+            &[],
+            None,
+            &pool,
+        )
+        .await;
+        assert!(result.is_err());
+
+        delete_test_users(&pool, &[user.id, other.id]).await;
+    }
+
+    /// Tests that deleting a URL already in the trash fails
+    #[actix_rt::test]
+    async fn test_delete_trashed_url() {
+        let pool = init_test_db().await;
+        let user = create_test_user(&pool, "trash_twice").await;
+        let member = Member::personal(&user);
+        let (id, _) = create_trashed_url(&member, &pool).await;
+
+        let result = delete_url(&member, &id, &pool).await;
+        assert!(result.is_err_and(|e| e.kind() == std::io::ErrorKind::NotFound));
+
+        delete_test_users(&pool, &[user.id]).await;
+    }
+
+    /// Tests restoring a URL with its tags, which only its own workspace can do
+    #[actix_rt::test]
+    async fn test_restore_from_trash() {
+        let pool = init_test_db().await;
+        let user = create_test_user(&pool, "trash_restore").await;
+        let member = Member::personal(&user);
+        let other = create_test_user(&pool, "trash_restore_other").await;
+        let (id, _) = create_trashed_url(&member, &pool).await;
+
+        let result = restore_from_trash(&Member::personal(&other), &id, &pool).await;
+        assert!(result.is_err_and(|e| e.kind() == std::io::ErrorKind::NotFound));
+
+        let restored = restore_from_trash(&member, &id, &pool).await.unwrap();
+        assert_eq!(restored.deleted_at, None);
+        assert_eq!(restored.tags, vec!["kept"]);
+
+        delete_test_users(&pool, &[user.id, other.id]).await;
+    }
+
+    /// Tests purging a URL, which only works from the trash
+    #[actix_rt::test]
+    async fn test_purge_url() {
+        let pool = init_test_db().await;
+        let user = create_test_user(&pool, "trash_purge").await;
+        let member = Member::personal(&user);
+        let (id, _) = create_trashed_url(&member, &pool).await;
+
+        restore_from_trash(&member, &id, &pool).await.unwrap();
+        let result = purge_url(&member, &id, &pool).await;
+        assert!(result.is_err_and(|e| e.kind() == std::io::ErrorKind::NotFound));
+
+        delete_url(&member, &id, &pool).await.unwrap();
+        purge_url(&member, &id, &pool).await.unwrap();
+        assert!(list_trash(&member, &pool).await.unwrap().is_empty());
+
+        delete_test_users(&pool, &[user.id]).await;
+    }
+
+    /// Tests that only URLs past the retention period are purged in the background
+    #[actix_rt::test]
+    async fn test_purge_expired_trash() {
+        let pool = init_test_db().await;
+        let user = create_test_user(&pool, "trash_expired").await;
+        let member = Member::personal(&user);
+
+        let (old, _) = create_trashed_url(&member, &pool).await;
+        let (recent, _) = create_trashed_url(&member, &pool).await;
+        sqlx::query("UPDATE shortened_urls SET deleted_at = $1 WHERE id = $2")
+            .bind(Utc::now() - Duration::days(400))
+            .bind(Uuid::parse_str(&old).unwrap())
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let purged = purge_expired_trash(Duration::days(365), &pool)
+            .await
+            .unwrap();
+        assert!(purged >= 1);
+        let trash = list_trash(&member, &pool).await.unwrap();
+        let ids: Vec<String> = trash.iter().map(|t| t.link.id.to_string()).collect();
+        assert_eq!(ids, vec![recent]);
+
+        delete_test_users(&pool, &[user.id]).await;
+    }
+}
@@ -1,7 +1,15 @@
-use crate::constants::{DATABASE_URL, ENVIRONMENT, PRODUCTION_ENV};
+use crate::{
+    constants::{DATABASE_URL, ENVIRONMENT, PRODUCTION_ENV},
+    structs::{APIResponse, FieldError, InputTarget, ShortenedUrl, VersionConflict},
+};
+use actix_web::{
+    http::header::{ETag, EntityTag},
+    HttpResponse,
+};
+use sha2::{Digest, Sha256};
 use sqlx::{
     postgres::{PgPoolOptions, PgQueryResult},
-    Pool, Postgres,
+    PgConnection, Pool, Postgres,
 };
 
 /// Checks if the application is running in production environment
@@ -13,13 +21,111 @@ pub fn is_production() -> bool {
     *ENVIRONMENT == PRODUCTION_ENV
 }
 
+/// Converts an error returned by the service layer into an HTTP response
+/// 
+/// The status code is chosen from the error kind:
+/// - `InvalidInput` maps to 400 Bad Request, including the offending field
+///   as `target_field` when the error carries a `FieldError`
+/// - `NotFound` maps to 404 Not Found
+/// - `PermissionDenied` maps to 403 Forbidden
+/// - `AlreadyExists` maps to 409 Conflict
+/// - `InvalidData` maps to 422 Unprocessable Entity
+/// - a `VersionConflict` maps to 412 Precondition Failed, with the current
+///   state of the URL as data
+/// - anything else maps to 500 Internal Server Error
+/// 
+/// # Arguments
+/// * `e` - The error returned by the service layer
+/// 
+/// # Returns
+/// HTTP response with the error message in the body
+pub fn error_response(e: std::io::Error) -> HttpResponse {
+    if let Some(conflict) = e
+        .get_ref()
+        .and_then(|inner| inner.downcast_ref::<VersionConflict>())
+    {
+        return HttpResponse::PreconditionFailed()
+            .insert_header(link_etag(&conflict.current))
+            .json(APIResponse::error(e.to_string(), Some(&conflict.current)));
+    }
+
+    let target = e
+        .get_ref()
+        .and_then(|inner| inner.downcast_ref::<FieldError>())
+        .map(|f| InputTarget {
+            target_field: f.target_field.clone(),
+        });
+
+    let mut response = match e.kind() {
+        std::io::ErrorKind::InvalidInput => HttpResponse::BadRequest(),
+        std::io::ErrorKind::NotFound => HttpResponse::NotFound(),
+        std::io::ErrorKind::PermissionDenied => HttpResponse::Forbidden(),
+        std::io::ErrorKind::AlreadyExists => HttpResponse::Conflict(),
+        std::io::ErrorKind::InvalidData => HttpResponse::UnprocessableEntity(),
+        _ => HttpResponse::InternalServerError(),
+    };
+
+    response.json(APIResponse::error(e.to_string(), target))
+}
+
+/// Hashes a random secret, such as an API key or refresh token, for storage and lookup
+/// 
+/// The secrets are long and random, so a fast hash is enough to keep them
+/// from being usable if the database leaks.
+/// 
+/// # Arguments
+/// * `secret` - The secret to hash
+/// 
+/// # Returns
+/// The hex encoded SHA-256 hash of the secret
+pub(crate) fn hash_secret(secret: &str) -> String {
+    format!("{:x}", Sha256::digest(secret.as_bytes()))
+}
+
+/// Builds the ETag of a shortened URL from its version
+/// 
+/// # Arguments
+/// * `url` - The shortened URL
+/// 
+/// # Returns
+/// The ETag header to send along with the URL
+pub fn link_etag(url: &ShortenedUrl) -> ETag {
+    ETag(EntityTag::new_strong(url.version.to_string()))
+}
+
+/// Responds with a single shortened URL along with its ETag
+/// 
+/// # Arguments
+/// * `url` - The shortened URL to respond with
+/// 
+/// # Returns
+/// HTTP 200 response with the URL as data
+pub fn link_response(url: ShortenedUrl) -> HttpResponse {
+    HttpResponse::Ok()
+        .insert_header(link_etag(&url))
+        .json(APIResponse::data(url))
+}
+
+/// Advisory lock key held while the schema is being set up
+const SCHEMA_LOCK_ID: i64 = 0x6e75726c; // "nurl"
+
+/// The document shortened URLs are full-text searched by
+/// 
+/// Punctuation is replaced with spaces so that the parts of URLs can be
+/// searched individually. Queries have to use this exact expression for the
+/// search index to be used.
+pub(crate) const LINK_SEARCH_DOCUMENT: &str = "to_tsvector('simple', regexp_replace(short_url || ' ' || original_url || ' ' || COALESCE(title, '') || ' ' || COALESCE(notes, ''), '[^[:alnum:]]+', ' ', 'g'))";
+
 /// Initializes the database connection and sets up required tables
 /// 
 /// This function:
 /// 1. Establishes a connection to the PostgreSQL database
-/// 2. Creates the pgcrypto extension if it doesn't exist
-/// 3. Creates the users table if it doesn't exist
-/// 4. Creates the shortened_urls table if it doesn't exist
+/// 2. Takes an advisory lock so concurrent startups don't race each other,
+///    releasing it even if setting up the schema fails
+/// 3. Creates the pgcrypto extension if it doesn't exist
+/// 4. Creates the users table if it doesn't exist
+/// 5. Creates the shortened_urls table if it doesn't exist
+/// 6. Adds the link health columns if they don't exist
 /// 
 /// # Returns
 /// Result containing the database connection pool
@@ -41,13 +147,42 @@ pub async fn init_db() -> Result<Pool<Postgres>, std::io::Error> {
 
     println!("Successfully formed a DB connection");
 
-    let pool_ref = &pool;
+    let mut conn = pool
+        .acquire()
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
 
-    let query = async |q: &str| -> Result<PgQueryResult, std::io::Error> {
+    sqlx::query(&format!("SELECT pg_advisory_lock({})", SCHEMA_LOCK_ID))
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+    let result = create_schema(&mut conn).await;
+    let unlocked = sqlx::query(&format!("SELECT pg_advisory_unlock({})", SCHEMA_LOCK_ID))
+        .execute(&mut *conn)
+        .await;
+    if unlocked.is_err() {
+        // The lock is held by the session, closing it keeps the lock from going back into the pool
+        let _ = conn.close().await;
+    }
+    result?;
+    Ok(pool)
+}
+
+/// Creates the tables, columns and indexes nurl needs if they don't exist
+/// 
+/// Must be called while holding the schema advisory lock.
+/// 
+/// # Arguments
+/// * `conn` - Database connection holding the lock
+/// 
+/// # Returns
+/// Result indicating success or failure
+async fn create_schema(conn: &mut PgConnection) -> Result<(), std::io::Error> {
+    let mut query = async |q: &str| -> Result<PgQueryResult, std::io::Error> {
         let result = sqlx::query(q)
-            .execute(pool_ref)
+            .execute(&mut *conn)
             .await
-            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e.to_string()))?;
+            .map_err(|e| std::io::Error::other(e.to_string()))?;
         Ok(result)
     };
 
@@ -79,7 +214,325 @@ pub async fn init_db() -> Result<Pool<Postgres>, std::io::Error> {
     "#,
     )
     .await?;
-    Ok(pool)
+    query(
+        r#"
+    ALTER TABLE shortened_urls
+        ADD COLUMN IF NOT EXISTS last_status_code INTEGER,
+        ADD COLUMN IF NOT EXISTS final_url TEXT,
+        ADD COLUMN IF NOT EXISTS last_check_error TEXT,
+        ADD COLUMN IF NOT EXISTS last_checked_at TIMESTAMPTZ,
+        ADD COLUMN IF NOT EXISTS is_broken BOOLEAN NOT NULL DEFAULT FALSE;
+    "#,
+    )
+    .await?;
+    query(
+        r#"
+    ALTER TABLE shortened_urls
+        ADD COLUMN IF NOT EXISTS title TEXT,
+        ADD COLUMN IF NOT EXISTS description TEXT,
+        ADD COLUMN IF NOT EXISTS favicon_url TEXT,
+        ADD COLUMN IF NOT EXISTS og_image_url TEXT,
+        ADD COLUMN IF NOT EXISTS metadata_fetched_at TIMESTAMPTZ,
+        ADD COLUMN IF NOT EXISTS metadata_error TEXT;
+    "#,
+    )
+    .await?;
+    query(
+        r#"
+    CREATE TABLE IF NOT EXISTS tags (
+        id UUID PRIMARY KEY,
+        owner UUID NOT NULL REFERENCES users(id),
+        name TEXT NOT NULL,
+        UNIQUE (owner, name)
+    );
+    "#,
+    )
+    .await?;
+    query(
+        r#"
+    CREATE TABLE IF NOT EXISTS link_tags (
+        link_id UUID NOT NULL REFERENCES shortened_urls(id) ON DELETE CASCADE,
+        tag_id UUID NOT NULL REFERENCES tags(id) ON DELETE CASCADE,
+        PRIMARY KEY (link_id, tag_id)
+    );
+    "#,
+    )
+    .await?;
+    query("CREATE INDEX IF NOT EXISTS link_tags_tag_id_idx ON link_tags (tag_id);").await?;
+    query(
+        "ALTER TABLE shortened_urls ADD COLUMN IF NOT EXISTS enabled BOOLEAN NOT NULL DEFAULT TRUE;",
+    )
+    .await?;
+    query(
+        "CREATE INDEX IF NOT EXISTS shortened_urls_owner_created_idx ON shortened_urls (owner, created_at, id);",
+    )
+    .await?;
+    query("ALTER TABLE shortened_urls ADD COLUMN IF NOT EXISTS notes TEXT;").await?;
+    // The search document gained the notes, so the index of the previous document is replaced
+    query("DROP INDEX IF EXISTS shortened_urls_search_idx;").await?;
+    query(&format!(
+        "CREATE INDEX IF NOT EXISTS shortened_urls_search_notes_idx ON shortened_urls USING GIN ({});",
+        LINK_SEARCH_DOCUMENT
+    ))
+    .await?;
+    query(
+        r#"
+    CREATE TABLE IF NOT EXISTS link_revisions (
+        id UUID PRIMARY KEY,
+        link_id UUID NOT NULL REFERENCES shortened_urls(id) ON DELETE CASCADE,
+        changed_by UUID REFERENCES users(id) ON DELETE SET NULL,
+        changed_at TIMESTAMPTZ NOT NULL,
+        action TEXT NOT NULL,
+        old_values JSONB,
+        new_values JSONB NOT NULL
+    );
+    "#,
+    )
+    .await?;
+    query(
+        "CREATE INDEX IF NOT EXISTS link_revisions_link_idx ON link_revisions (link_id, changed_at);",
+    )
+    .await?;
+    query("ALTER TABLE shortened_urls ADD COLUMN IF NOT EXISTS deleted_at TIMESTAMPTZ;").await?;
+    query(
+        "CREATE INDEX IF NOT EXISTS shortened_urls_deleted_at_idx ON shortened_urls (deleted_at) WHERE deleted_at IS NOT NULL;",
+    )
+    .await?;
+    query(
+        r#"
+    CREATE TABLE IF NOT EXISTS short_code_tombstones (
+        short_url TEXT PRIMARY KEY,
+        owner UUID REFERENCES users(id) ON DELETE SET NULL,
+        redirects BIGINT NOT NULL,
+        deleted_at TIMESTAMPTZ NOT NULL,
+        expires_at TIMESTAMPTZ
+    );
+    "#,
+    )
+    .await?;
+    query(
+        r#"
+    CREATE TABLE IF NOT EXISTS link_transfers (
+        id UUID PRIMARY KEY,
+        link_id UUID NOT NULL UNIQUE REFERENCES shortened_urls(id) ON DELETE CASCADE,
+        from_user UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+        to_user UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+        created_at TIMESTAMPTZ NOT NULL
+    );
+    "#,
+    )
+    .await?;
+    query("CREATE INDEX IF NOT EXISTS link_transfers_to_user_idx ON link_transfers (to_user);")
+        .await?;
+    query("ALTER TABLE shortened_urls ADD COLUMN IF NOT EXISTS version BIGINT NOT NULL DEFAULT 1;")
+        .await?;
+    query(
+        r#"
+    CREATE TABLE IF NOT EXISTS idempotency_keys (
+        owner UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+        idempotency_key TEXT NOT NULL,
+        request JSONB NOT NULL,
+        response JSONB,
+        created_at TIMESTAMPTZ NOT NULL,
+        PRIMARY KEY (owner, idempotency_key)
+    );
+    "#,
+    )
+    .await?;
+    query("CREATE INDEX IF NOT EXISTS idempotency_keys_created_at_idx ON idempotency_keys (created_at);")
+        .await?;
+    query(
+        r#"
+    CREATE TABLE IF NOT EXISTS domains (
+        id UUID PRIMARY KEY,
+        hostname TEXT NOT NULL UNIQUE,
+        owner UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+        created_at TIMESTAMPTZ NOT NULL
+    );
+    "#,
+    )
+    .await?;
+    query("CREATE INDEX IF NOT EXISTS domains_owner_idx ON domains (owner);").await?;
+    // Short codes are unique per domain, with links on the default domain having no domain
+    query(
+        "ALTER TABLE shortened_urls ADD COLUMN IF NOT EXISTS domain TEXT REFERENCES domains(hostname);",
+    )
+    .await?;
+    query(
+        "CREATE UNIQUE INDEX IF NOT EXISTS shortened_urls_domain_short_url_idx ON shortened_urls (domain, short_url) NULLS NOT DISTINCT;",
+    )
+    .await?;
+    query("ALTER TABLE shortened_urls DROP CONSTRAINT IF EXISTS shortened_urls_short_url_key;")
+        .await?;
+    query(
+        "ALTER TABLE short_code_tombstones ADD COLUMN IF NOT EXISTS domain TEXT REFERENCES domains(hostname) ON DELETE CASCADE;",
+    )
+    .await?;
+    query(
+        "CREATE UNIQUE INDEX IF NOT EXISTS short_code_tombstones_domain_short_url_idx ON short_code_tombstones (domain, short_url) NULLS NOT DISTINCT;",
+    )
+    .await?;
+    query(
+        "ALTER TABLE short_code_tombstones DROP CONSTRAINT IF EXISTS short_code_tombstones_pkey;",
+    )
+    .await?;
+    query(
+        r#"
+    ALTER TABLE domains
+        ADD COLUMN IF NOT EXISTS verification_token TEXT NOT NULL DEFAULT encode(gen_random_bytes(16), 'hex'),
+        ADD COLUMN IF NOT EXISTS verified_at TIMESTAMPTZ,
+        ADD COLUMN IF NOT EXISTS verification_checked_at TIMESTAMPTZ,
+        ADD COLUMN IF NOT EXISTS verification_error TEXT;
+    "#,
+    )
+    .await?;
+    query(
+        r#"
+    CREATE TABLE IF NOT EXISTS workspaces (
+        id UUID PRIMARY KEY,
+        name TEXT NOT NULL,
+        personal_of UUID UNIQUE REFERENCES users(id) ON DELETE CASCADE,
+        created_at TIMESTAMPTZ NOT NULL
+    );
+    "#,
+    )
+    .await?;
+    query(
+        r#"
+    CREATE TABLE IF NOT EXISTS workspace_members (
+        workspace_id UUID NOT NULL REFERENCES workspaces(id) ON DELETE CASCADE,
+        user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+        role TEXT NOT NULL,
+        joined_at TIMESTAMPTZ NOT NULL,
+        PRIMARY KEY (workspace_id, user_id)
+    );
+    "#,
+    )
+    .await?;
+    query("CREATE INDEX IF NOT EXISTS workspace_members_user_idx ON workspace_members (user_id);")
+        .await?;
+    query(
+        r#"
+    CREATE TABLE IF NOT EXISTS workspace_invitations (
+        id UUID PRIMARY KEY,
+        workspace_id UUID NOT NULL REFERENCES workspaces(id) ON DELETE CASCADE,
+        user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+        role TEXT NOT NULL,
+        invited_by UUID REFERENCES users(id) ON DELETE SET NULL,
+        created_at TIMESTAMPTZ NOT NULL,
+        UNIQUE (workspace_id, user_id)
+    );
+    "#,
+    )
+    .await?;
+    query(
+        "CREATE INDEX IF NOT EXISTS workspace_invitations_user_idx ON workspace_invitations (user_id);",
+    )
+    .await?;
+    // Personal workspaces share the ID of their user, so the owner of existing links stays valid
+    query(
+        "INSERT INTO workspaces (id, name, personal_of, created_at) SELECT id, username, id, NOW() FROM users ON CONFLICT DO NOTHING;",
+    )
+    .await?;
+    query(
+        "INSERT INTO workspace_members (workspace_id, user_id, role, joined_at) SELECT id, id, 'owner', NOW() FROM users ON CONFLICT DO NOTHING;",
+    )
+    .await?;
+    // Links, tags and tombstoned codes are owned by workspaces instead of users
+    for (table, on_delete) in [
+        ("shortened_urls", ""),
+        ("tags", ""),
+        ("short_code_tombstones", " ON DELETE SET NULL"),
+    ] {
+        query(&format!(
+            "ALTER TABLE {table} DROP CONSTRAINT IF EXISTS {table}_owner_fkey;"
+        ))
+        .await?;
+        query(&format!(
+            r#"
+    DO $$ BEGIN
+        ALTER TABLE {table} ADD CONSTRAINT {table}_workspace_fkey FOREIGN KEY (owner) REFERENCES workspaces(id){on_delete};
+    EXCEPTION WHEN duplicate_object THEN NULL;
+    END $$;
+    "#
+        ))
+        .await?;
+    }
+    // Transfers remember the workspace the link is offered from
+    query(
+        "ALTER TABLE link_transfers ADD COLUMN IF NOT EXISTS from_workspace UUID REFERENCES workspaces(id) ON DELETE CASCADE;",
+    )
+    .await?;
+    query("UPDATE link_transfers SET from_workspace = from_user WHERE from_workspace IS NULL;")
+        .await?;
+    query(
+        r#"
+    CREATE TABLE IF NOT EXISTS api_keys (
+        id UUID PRIMARY KEY,
+        user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+        name TEXT NOT NULL,
+        prefix TEXT NOT NULL,
+        key_hash TEXT NOT NULL UNIQUE,
+        scopes TEXT[] NOT NULL,
+        expires_at TIMESTAMPTZ,
+        last_used_at TIMESTAMPTZ,
+        created_at TIMESTAMPTZ NOT NULL
+    );
+    "#,
+    )
+    .await?;
+    query("CREATE INDEX IF NOT EXISTS api_keys_user_idx ON api_keys (user_id);").await?;
+    query(
+        r#"
+    CREATE TABLE IF NOT EXISTS sessions (
+        id UUID PRIMARY KEY,
+        user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+        created_at TIMESTAMPTZ NOT NULL,
+        expires_at TIMESTAMPTZ NOT NULL,
+        last_used_at TIMESTAMPTZ NOT NULL,
+        revoked_at TIMESTAMPTZ
+    );
+    "#,
+    )
+    .await?;
+    query("CREATE INDEX IF NOT EXISTS sessions_user_idx ON sessions (user_id);").await?;
+    query(
+        r#"
+    ALTER TABLE sessions
+        ADD COLUMN IF NOT EXISTS remember_me BOOLEAN NOT NULL DEFAULT FALSE,
+        ADD COLUMN IF NOT EXISTS ip_address TEXT,
+        ADD COLUMN IF NOT EXISTS user_agent TEXT;
+    "#,
+    )
+    .await?;
+    // Refresh tokens are kept once exchanged, so reusing one can be detected
+    query(
+        r#"
+    CREATE TABLE IF NOT EXISTS refresh_tokens (
+        token_hash TEXT PRIMARY KEY,
+        session_id UUID NOT NULL REFERENCES sessions(id) ON DELETE CASCADE,
+        created_at TIMESTAMPTZ NOT NULL,
+        used_at TIMESTAMPTZ
+    );
+    "#,
+    )
+    .await?;
+    query("CREATE INDEX IF NOT EXISTS refresh_tokens_session_idx ON refresh_tokens (session_id);")
+        .await?;
+    // Defaults applied to the links a user creates
+    query(
+        r#"
+    ALTER TABLE users
+        ADD COLUMN IF NOT EXISTS default_expiration BIGINT,
+        ADD COLUMN IF NOT EXISTS default_redirect_type SMALLINT NOT NULL DEFAULT 307;
+    "#,
+    )
+    .await?;
+    query(
+        "ALTER TABLE shortened_urls ADD COLUMN IF NOT EXISTS redirect_type SMALLINT NOT NULL DEFAULT 307;",
+    )
+    .await?;
+    Ok(())
 }
 
 /// Initializes a test database with a test user
@@ -102,11 +555,19 @@ pub async fn init_test_db() -> Pool<Postgres> {
     .execute(pool_ref)
     .await
     .unwrap();
+    let user = get_test_user(pool_ref).await;
+    create_personal_workspace(user.id, &user.username, pool_ref)
+        .await
+        .unwrap();
 
     pool
 }
 #[cfg(test)]
-use crate::structs::User;
+use crate::{
+    domains::add_domain,
+    structs::{Domain, User},
+    workspaces::create_personal_workspace,
+};
 
 /// Retrieves the test user from the database
 /// 
@@ -125,3 +586,112 @@ pub async fn get_test_user(pool: &Pool<Postgres>) -> User {
         .await
         .unwrap()
 }
+
+/// Creates a user with no URLs, along with their personal workspace
+/// 
+/// Tests listing or counting a user's URLs use their own user so they aren't
+/// affected by other tests running at the same time. This function is only
+/// available in test builds
+/// 
+/// # Arguments
+/// * `pool` - Database connection pool
+/// * `prefix` - Prefix of the generated username
+/// 
+/// # Returns
+/// The created user
+#[cfg(test)]
+pub async fn create_test_user(pool: &Pool<Postgres>, prefix: &str) -> User {
+    let user = sqlx::query_as::<_, User>(
+        "INSERT INTO users (username, password) VALUES ($1, $2) RETURNING *",
+    )
+    .bind(format!("{}_{}", prefix, uuid::Uuid::new_v4()))
+    .bind("test_password")
+    .fetch_one(pool)
+    .await
+    .unwrap();
+    create_personal_workspace(user.id, &user.username, pool)
+        .await
+        .unwrap();
+    user
+}
+
+/// Adds a custom domain with a unique hostname for a test user
+/// 
+/// This function is only available in test builds
+/// 
+/// # Arguments
+/// * `pool` - Database connection pool
+/// * `user` - The user adding the domain
+/// * `verified` - Whether to mark the domain as verified, as if its token had been found
+/// 
+/// # Returns
+/// The added domain
+#[cfg(test)]
+pub async fn add_test_domain(pool: &Pool<Postgres>, user: &User, verified: bool) -> Domain {
+    let hostname = format!(
+        "go-{}.example.test",
+        &uuid::Uuid::new_v4().simple().to_string()[..8]
+    );
+    let domain = add_domain(user, &hostname, pool).await.unwrap();
+    if !verified {
+        return domain;
+    }
+    sqlx::query_as::<_, Domain>("UPDATE domains SET verified_at = NOW() WHERE id = $1 RETURNING *")
+        .bind(domain.id)
+        .fetch_one(pool)
+        .await
+        .unwrap()
+}
+
+/// Deletes test users along with everything they own
+/// 
+/// Tests call this at the end with the users they created, which removes their
+/// workspaces (and the shared ones they own), links, tags and domains. This
+/// function is only available in test builds
+/// 
+/// # Arguments
+/// * `pool` - Database connection pool
+/// * `users` - The IDs of the users to delete
+#[cfg(test)]
+pub async fn delete_test_users(pool: &Pool<Postgres>, users: &[uuid::Uuid]) {
+    let mut tx = pool.begin().await.unwrap();
+
+    // Shared workspaces created by the users go along with their personal ones
+    let workspaces: Vec<uuid::Uuid> = sqlx::query_scalar(
+        r#"
+      SELECT id FROM workspaces WHERE personal_of = ANY($1)
+      UNION
+      SELECT workspace_id FROM workspace_members WHERE user_id = ANY($1) AND role = 'owner'
+      "#,
+    )
+    .bind(users)
+    .fetch_all(&mut *tx)
+    .await
+    .unwrap();
+
+    for (statement, ids) in [
+        (
+            "DELETE FROM short_code_tombstones WHERE owner = ANY($1)",
+            &workspaces[..],
+        ),
+        (
+            "DELETE FROM shortened_urls WHERE owner = ANY($1)",
+            &workspaces[..],
+        ),
+        (
+            "DELETE FROM shortened_urls WHERE domain IN (SELECT hostname FROM domains WHERE owner = ANY($1))",
+            users,
+        ),
+        ("DELETE FROM tags WHERE owner = ANY($1)", &workspaces[..]),
+        ("DELETE FROM workspaces WHERE id = ANY($1)", &workspaces[..]),
+        ("DELETE FROM users WHERE id = ANY($1)", users),
+    ] {
+        sqlx::query(statement)
+            .bind(ids)
+            .execute(&mut *tx)
+            .await
+            .unwrap();
+    }
+
+    tx.commit().await.unwrap();
+}
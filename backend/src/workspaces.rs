@@ -0,0 +1,1129 @@
+use chrono::Utc;
+use serde::Deserialize;
+use sqlx::{PgConnection, PgExecutor, PgPool};
+use uuid::Uuid;
+
+use crate::{
+    service::parse_uuid,
+    structs::{FieldError, User, Workspace, WorkspaceInvitation, WorkspaceMember},
+};
+
+/// The maximum length (in characters) of a workspace name
+const MAX_WORKSPACE_NAME_LENGTH: usize = 100;
+
+/// What a member of a workspace may do, every role may also do what the roles below it may
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WorkspaceRole {
+    /// Can view the URLs, tags, history and trash of the workspace
+    Viewer,
+    /// Can create, edit, delete and restore URLs
+    Editor,
+    /// Can invite and manage members and transfer URLs out of the workspace
+    Admin,
+    /// Can manage admins and other owners and delete the workspace
+    Owner,
+}
+
+impl WorkspaceRole {
+    /// Returns the name the role is stored under
+    fn as_str(self) -> &'static str {
+        match self {
+            WorkspaceRole::Viewer => "viewer",
+            WorkspaceRole::Editor => "editor",
+            WorkspaceRole::Admin => "admin",
+            WorkspaceRole::Owner => "owner",
+        }
+    }
+
+    /// Parses a role as stored in the database
+    fn parse(role: &str) -> Result<Self, std::io::Error> {
+        match role {
+            "viewer" => Ok(WorkspaceRole::Viewer),
+            "editor" => Ok(WorkspaceRole::Editor),
+            "admin" => Ok(WorkspaceRole::Admin),
+            "owner" => Ok(WorkspaceRole::Owner),
+            _ => Err(std::io::Error::other(format!(
+                "Unknown workspace role {}",
+                role
+            ))),
+        }
+    }
+}
+
+/// A user acting within one of their workspaces
+/// 
+/// Service functions working with URLs take the member instead of the user, so
+/// that the URLs of the workspace are used and the member's role is checked.
+#[derive(Debug, Clone)]
+pub(crate) struct Member {
+    /// The acting user
+    pub user: User,
+    /// ID of the workspace the user is acting in
+    pub workspace_id: Uuid,
+    /// The role of the user in the workspace
+    pub role: WorkspaceRole,
+}
+
+impl Member {
+    /// Returns a user acting in their personal workspace, which they always own
+    /// 
+    /// # Arguments
+    /// * `user` - The acting user
+    pub fn personal(user: &User) -> Self {
+        Self {
+            user: user.clone(),
+            workspace_id: user.id,
+            role: WorkspaceRole::Owner,
+        }
+    }
+
+    /// Checks that the member has at least the given role
+    /// 
+    /// # Arguments
+    /// * `role` - The role required for the action
+    /// 
+    /// # Returns
+    /// Result indicating whether the member may take the action
+    pub fn require(&self, role: WorkspaceRole) -> Result<(), std::io::Error> {
+        if self.role >= role {
+            return Ok(());
+        }
+
+        Err(std::io::Error::new(
+            std::io::ErrorKind::PermissionDenied,
+            format!(
+                "This requires the {} role in the workspace, you are {}",
+                role.as_str(),
+                self.role.as_str()
+            ),
+        ))
+    }
+}
+
+/// Creates the personal workspace of a user if it doesn't exist yet
+/// 
+/// Personal workspaces share the ID of their user and can't be shared with
+/// other users.
+/// 
+/// # Arguments
+/// * `user_id` - The ID of the user
+/// * `username` - The username of the user, used as the name of the workspace
+/// * `executor` - Database connection or pool
+/// 
+/// # Returns
+/// Result indicating success or failure
+pub(crate) async fn create_personal_workspace<'e>(
+    user_id: Uuid,
+    username: &str,
+    executor: impl PgExecutor<'e>,
+) -> Result<(), std::io::Error> {
+    sqlx::query(
+        r#"
+      WITH workspace AS (
+          INSERT INTO workspaces (id, name, personal_of, created_at)
+          VALUES ($1, $2, $1, $3)
+          ON CONFLICT DO NOTHING
+          RETURNING id
+      )
+      INSERT INTO workspace_members (workspace_id, user_id, role, joined_at)
+      SELECT id, id, 'owner', $3 FROM workspace
+      "#,
+    )
+    .bind(user_id)
+    .bind(username)
+    .bind(Utc::now())
+    .execute(executor)
+    .await
+    .map_err(|e| std::io::Error::other(e.to_string()))?;
+
+    Ok(())
+}
+
+/// Loads a user as a member of the workspace they selected
+/// 
+/// # Arguments
+/// * `user` - The acting user
+/// * `workspace_id` - The ID of the selected workspace, the user's personal workspace if `None`
+/// * `pool` - Database connection pool
+/// 
+/// # Returns
+/// Result containing the member, `NotFound` if the user isn't a member of the workspace
+pub(crate) async fn load_member(
+    user: User,
+    workspace_id: Option<&str>,
+    pool: &PgPool,
+) -> Result<Member, std::io::Error> {
+    let Some(workspace_id) = workspace_id else {
+        return Ok(Member::personal(&user));
+    };
+    let uuid = parse_uuid(workspace_id)?;
+
+    let (role,): (String,) = sqlx::query_as(
+        "SELECT role FROM workspace_members WHERE workspace_id = $1 AND user_id = $2",
+    )
+    .bind(uuid)
+    .bind(user.id)
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| std::io::Error::other(e.to_string()))?
+    .ok_or(std::io::Error::new(
+        std::io::ErrorKind::NotFound,
+        "Workspace not found or you aren't a member of it",
+    ))?;
+
+    Ok(Member {
+        user,
+        workspace_id: uuid,
+        role: WorkspaceRole::parse(&role)?,
+    })
+}
+
+/// Selects workspaces along with the role of a user, bound as `$1`
+const SELECT_WORKSPACES: &str = r#"
+      SELECT w.id, w.name, w.personal_of IS NOT NULL AS personal, w.created_at, m.role
+      FROM workspaces w
+      JOIN workspace_members m ON m.workspace_id = w.id AND m.user_id = $1
+      "#;
+
+/// Selects invitations along with the workspace name and the usernames involved
+const SELECT_INVITATIONS: &str = r#"
+      SELECT i.id, i.workspace_id, w.name AS workspace_name, u.username, i.role,
+          b.username AS invited_by_username, i.created_at
+      FROM workspace_invitations i
+      JOIN workspaces w ON w.id = i.workspace_id
+      JOIN users u ON u.id = i.user_id
+      LEFT JOIN users b ON b.id = i.invited_by
+      "#;
+
+/// Loads a workspace along with the role of a user in it
+/// 
+/// # Arguments
+/// * `user_id` - The ID of the member
+/// * `workspace_id` - The ID of the workspace
+/// * `conn` - Database connection
+/// 
+/// # Returns
+/// Result containing the Workspace
+async fn fetch_workspace(
+    user_id: Uuid,
+    workspace_id: Uuid,
+    conn: &mut PgConnection,
+) -> Result<Workspace, std::io::Error> {
+    sqlx::query_as(&format!("{} WHERE w.id = $2", SELECT_WORKSPACES))
+        .bind(user_id)
+        .bind(workspace_id)
+        .fetch_one(conn)
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))
+}
+
+/// Locks a workspace for the transaction changing its members
+/// 
+/// # Arguments
+/// * `workspace_id` - The ID of the workspace
+/// * `conn` - Database connection, within a transaction
+/// 
+/// # Returns
+/// Result containing whether the workspace is a personal workspace
+async fn lock_workspace(
+    workspace_id: Uuid,
+    conn: &mut PgConnection,
+) -> Result<bool, std::io::Error> {
+    let (personal,): (bool,) =
+        sqlx::query_as("SELECT personal_of IS NOT NULL FROM workspaces WHERE id = $1 FOR UPDATE")
+            .bind(workspace_id)
+            .fetch_optional(conn)
+            .await
+            .map_err(|e| std::io::Error::other(e.to_string()))?
+            .ok_or(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                "Workspace not found or you aren't a member of it",
+            ))?;
+    Ok(personal)
+}
+
+/// Loads the role of a member of a workspace
+/// 
+/// # Arguments
+/// * `workspace_id` - The ID of the workspace
+/// * `user_id` - The ID of the member
+/// * `conn` - Database connection
+/// 
+/// # Returns
+/// Result containing the role, `NotFound` if the user isn't a member
+async fn member_role(
+    workspace_id: Uuid,
+    user_id: Uuid,
+    conn: &mut PgConnection,
+) -> Result<WorkspaceRole, std::io::Error> {
+    let (role,): (String,) = sqlx::query_as(
+        "SELECT role FROM workspace_members WHERE workspace_id = $1 AND user_id = $2",
+    )
+    .bind(workspace_id)
+    .bind(user_id)
+    .fetch_optional(conn)
+    .await
+    .map_err(|e| std::io::Error::other(e.to_string()))?
+    .ok_or(std::io::Error::new(
+        std::io::ErrorKind::NotFound,
+        "Member not found",
+    ))?;
+    WorkspaceRole::parse(&role)
+}
+
+/// Checks that a workspace keeps an owner besides the given member
+/// 
+/// # Arguments
+/// * `workspace_id` - The ID of the workspace
+/// * `user_id` - The ID of the owner who is leaving or losing the role
+/// * `conn` - Database connection, within the transaction locking the workspace
+/// 
+/// # Returns
+/// Result indicating whether another owner is left
+async fn ensure_other_owner(
+    workspace_id: Uuid,
+    user_id: Uuid,
+    conn: &mut PgConnection,
+) -> Result<(), std::io::Error> {
+    let (others,): (bool,) = sqlx::query_as(
+        "SELECT EXISTS(SELECT 1 FROM workspace_members WHERE workspace_id = $1 AND user_id <> $2 AND role = 'owner')",
+    )
+    .bind(workspace_id)
+    .bind(user_id)
+    .fetch_one(conn)
+    .await
+    .map_err(|e| std::io::Error::other(e.to_string()))?;
+    if !others {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "A workspace needs at least one owner, make another member an owner first",
+        ));
+    }
+
+    Ok(())
+}
+
+/// Creates a workspace owned by a user
+/// 
+/// # Arguments
+/// * `user` - The user creating the workspace, who becomes its owner
+/// * `name` - The name of the workspace
+/// * `pool` - Database connection pool
+/// 
+/// # Returns
+/// Result containing the created Workspace
+pub async fn create_workspace(
+    user: &User,
+    name: &str,
+    pool: &PgPool,
+) -> Result<Workspace, std::io::Error> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(FieldError::invalid("name", "Name cannot be empty"));
+    }
+    if name.chars().count() > MAX_WORKSPACE_NAME_LENGTH {
+        return Err(FieldError::invalid(
+            "name",
+            format!(
+                "Name cannot be longer than {} characters",
+                MAX_WORKSPACE_NAME_LENGTH
+            ),
+        ));
+    }
+
+    let id = Uuid::new_v4();
+    let now = Utc::now();
+    let mut tx = pool
+        .begin()
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+    sqlx::query("INSERT INTO workspaces (id, name, created_at) VALUES ($1, $2, $3)")
+        .bind(id)
+        .bind(name)
+        .bind(now)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+    sqlx::query(
+        "INSERT INTO workspace_members (workspace_id, user_id, role, joined_at) VALUES ($1, $2, $3, $4)",
+    )
+    .bind(id)
+    .bind(user.id)
+    .bind(WorkspaceRole::Owner.as_str())
+    .bind(now)
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| std::io::Error::other(e.to_string()))?;
+
+    let workspace = fetch_workspace(user.id, id, &mut tx).await?;
+    tx.commit()
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+    Ok(workspace)
+}
+
+/// Lists the workspaces a user is a member of, personal workspace first
+/// 
+/// # Arguments
+/// * `user` - The user whose workspaces to list
+/// * `pool` - Database connection pool
+/// 
+/// # Returns
+/// Result containing the workspaces along with the user's role in each
+pub async fn list_workspaces(user: &User, pool: &PgPool) -> Result<Vec<Workspace>, std::io::Error> {
+    sqlx::query_as(&format!(
+        "{} ORDER BY w.personal_of IS NULL, w.name, w.id",
+        SELECT_WORKSPACES
+    ))
+    .bind(user.id)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| std::io::Error::other(e.to_string()))
+}
+
+/// Deletes a workspace
+/// 
+/// Only workspaces without URLs, including URLs in the trash, can be deleted.
+/// Personal workspaces can't be deleted.
+/// 
+/// # Arguments
+/// * `member` - An owner of the workspace
+/// * `pool` - Database connection pool
+/// 
+/// # Returns
+/// Result indicating success or failure
+pub async fn delete_workspace(member: &Member, pool: &PgPool) -> Result<(), std::io::Error> {
+    member.require(WorkspaceRole::Owner)?;
+
+    let mut tx = pool
+        .begin()
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+    if lock_workspace(member.workspace_id, &mut tx).await? {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "Personal workspaces can't be deleted",
+        ));
+    }
+
+    let (in_use,): (bool,) =
+        sqlx::query_as("SELECT EXISTS(SELECT 1 FROM shortened_urls WHERE owner = $1)")
+            .bind(member.workspace_id)
+            .fetch_one(&mut *tx)
+            .await
+            .map_err(|e| std::io::Error::other(e.to_string()))?;
+    if in_use {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "This workspace still has links, delete, purge or transfer them before deleting it",
+        ));
+    }
+
+    sqlx::query("DELETE FROM tags WHERE owner = $1")
+        .bind(member.workspace_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+    sqlx::query("DELETE FROM workspaces WHERE id = $1")
+        .bind(member.workspace_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+    tx.commit()
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))
+}
+
+/// Lists the members of a workspace, owners first
+/// 
+/// # Arguments
+/// * `member` - A member of the workspace
+/// * `pool` - Database connection pool
+/// 
+/// # Returns
+/// Result containing the members of the workspace
+pub async fn list_members(
+    member: &Member,
+    pool: &PgPool,
+) -> Result<Vec<WorkspaceMember>, std::io::Error> {
+    sqlx::query_as(
+        r#"
+      SELECT m.user_id, u.username, m.role, m.joined_at
+      FROM workspace_members m
+      JOIN users u ON u.id = m.user_id
+      WHERE m.workspace_id = $1
+      ORDER BY array_position(ARRAY['owner', 'admin', 'editor', 'viewer'], m.role), u.username
+      "#,
+    )
+    .bind(member.workspace_id)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| std::io::Error::other(e.to_string()))
+}
+
+/// Invites a user to join a workspace
+/// 
+/// Inviting a user who already has a pending invitation replaces it. Members
+/// can't invite users with a role above their own, and nobody can be invited
+/// to a personal workspace.
+/// 
+/// # Arguments
+/// * `member` - An admin or owner of the workspace
+/// * `username` - The username of the user to invite
+/// * `role` - The role the user gets when accepting
+/// * `pool` - Database connection pool
+/// 
+/// # Returns
+/// Result containing the pending WorkspaceInvitation
+pub async fn invite_member(
+    member: &Member,
+    username: &str,
+    role: WorkspaceRole,
+    pool: &PgPool,
+) -> Result<WorkspaceInvitation, std::io::Error> {
+    member.require(WorkspaceRole::Admin)?;
+    if role > member.role {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::PermissionDenied,
+            "You can't invite members with a role above your own",
+        ));
+    }
+
+    let (invitee,): (Uuid,) = sqlx::query_as("SELECT id FROM users WHERE username = $1")
+        .bind(username.trim())
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))?
+        .ok_or(FieldError::invalid("username", "User not found"))?;
+
+    let mut tx = pool
+        .begin()
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+    if lock_workspace(member.workspace_id, &mut tx).await? {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "Personal workspaces can't be shared, create a workspace to invite members",
+        ));
+    }
+    if member_role(member.workspace_id, invitee, &mut tx)
+        .await
+        .is_ok()
+    {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::AlreadyExists,
+            "This user is already a member of the workspace",
+        ));
+    }
+
+    let (invitation_id,): (Uuid,) = sqlx::query_as(
+        r#"
+      INSERT INTO workspace_invitations (id, workspace_id, user_id, role, invited_by, created_at)
+      VALUES ($1, $2, $3, $4, $5, $6)
+      ON CONFLICT (workspace_id, user_id) DO UPDATE SET
+          role = EXCLUDED.role,
+          invited_by = EXCLUDED.invited_by,
+          created_at = EXCLUDED.created_at
+      RETURNING id
+      "#,
+    )
+    .bind(Uuid::new_v4())
+    .bind(member.workspace_id)
+    .bind(invitee)
+    .bind(role.as_str())
+    .bind(member.user.id)
+    .bind(Utc::now())
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|e| std::io::Error::other(e.to_string()))?;
+
+    let invitation = sqlx::query_as(&format!("{} WHERE i.id = $1", SELECT_INVITATIONS))
+        .bind(invitation_id)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+    tx.commit()
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+    Ok(invitation)
+}
+
+/// Lists the pending invitations of a user, newest first
+/// 
+/// # Arguments
+/// * `user` - The invited user
+/// * `pool` - Database connection pool
+/// 
+/// # Returns
+/// Result containing the pending invitations
+pub async fn list_invitations(
+    user: &User,
+    pool: &PgPool,
+) -> Result<Vec<WorkspaceInvitation>, std::io::Error> {
+    sqlx::query_as(&format!(
+        "{} WHERE i.user_id = $1 ORDER BY i.created_at DESC, i.id",
+        SELECT_INVITATIONS
+    ))
+    .bind(user.id)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| std::io::Error::other(e.to_string()))
+}
+
+/// Accepts an invitation, making the user a member of the workspace
+/// 
+/// # Arguments
+/// * `user` - The invited user
+/// * `invitation_id` - The ID of the invitation
+/// * `pool` - Database connection pool
+/// 
+/// # Returns
+/// Result containing the joined Workspace
+pub async fn accept_invitation(
+    user: &User,
+    invitation_id: &str,
+    pool: &PgPool,
+) -> Result<Workspace, std::io::Error> {
+    let uuid = parse_uuid(invitation_id)?;
+
+    let mut tx = pool
+        .begin()
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+    let (workspace_id, role): (Uuid, String) = sqlx::query_as(
+        "DELETE FROM workspace_invitations WHERE id = $1 AND user_id = $2 RETURNING workspace_id, role",
+    )
+    .bind(uuid)
+    .bind(user.id)
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|e| std::io::Error::other(e.to_string()))?
+    .ok_or(std::io::Error::new(
+        std::io::ErrorKind::NotFound,
+        "Invitation not found or it wasn't sent to you",
+    ))?;
+
+    sqlx::query(
+        r#"
+      INSERT INTO workspace_members (workspace_id, user_id, role, joined_at)
+      VALUES ($1, $2, $3, $4)
+      ON CONFLICT (workspace_id, user_id) DO NOTHING
+      "#,
+    )
+    .bind(workspace_id)
+    .bind(user.id)
+    .bind(role)
+    .bind(Utc::now())
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| std::io::Error::other(e.to_string()))?;
+
+    let workspace = fetch_workspace(user.id, workspace_id, &mut tx).await?;
+    tx.commit()
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+    Ok(workspace)
+}
+
+/// Declines an invitation
+/// 
+/// # Arguments
+/// * `user` - The invited user
+/// * `invitation_id` - The ID of the invitation
+/// * `pool` - Database connection pool
+/// 
+/// # Returns
+/// Result indicating success or failure
+pub async fn decline_invitation(
+    user: &User,
+    invitation_id: &str,
+    pool: &PgPool,
+) -> Result<(), std::io::Error> {
+    let uuid = parse_uuid(invitation_id)?;
+
+    let result = sqlx::query("DELETE FROM workspace_invitations WHERE id = $1 AND user_id = $2")
+        .bind(uuid)
+        .bind(user.id)
+        .execute(pool)
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+    if result.rows_affected() == 0 {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            "Invitation not found or it wasn't sent to you",
+        ));
+    }
+
+    Ok(())
+}
+
+/// Changes the role of a member of a workspace
+/// 
+/// Members can't change the role of members above them or grant a role above
+/// their own, and the last owner can't give up the role.
+/// 
+/// # Arguments
+/// * `member` - An admin or owner of the workspace
+/// * `user_id` - The ID of the member whose role to change
+/// * `role` - The new role
+/// * `pool` - Database connection pool
+/// 
+/// # Returns
+/// Result containing the updated WorkspaceMember
+pub async fn change_member_role(
+    member: &Member,
+    user_id: &str,
+    role: WorkspaceRole,
+    pool: &PgPool,
+) -> Result<WorkspaceMember, std::io::Error> {
+    member.require(WorkspaceRole::Admin)?;
+    let uuid = parse_uuid(user_id)?;
+
+    let mut tx = pool
+        .begin()
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+    lock_workspace(member.workspace_id, &mut tx).await?;
+    let current = member_role(member.workspace_id, uuid, &mut tx).await?;
+    if current > member.role || role > member.role {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::PermissionDenied,
+            "You can't change roles above your own",
+        ));
+    }
+    if current == WorkspaceRole::Owner && role != WorkspaceRole::Owner {
+        ensure_other_owner(member.workspace_id, uuid, &mut tx).await?;
+    }
+
+    let updated = sqlx::query_as(
+        r#"
+      UPDATE workspace_members m SET role = $1
+      FROM users u
+      WHERE m.workspace_id = $2 AND m.user_id = $3 AND u.id = m.user_id
+      RETURNING m.user_id, u.username, m.role, m.joined_at
+      "#,
+    )
+    .bind(role.as_str())
+    .bind(member.workspace_id)
+    .bind(uuid)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|e| std::io::Error::other(e.to_string()))?;
+    tx.commit()
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+    Ok(updated)
+}
+
+/// Removes a member from a workspace
+/// 
+/// Every member can leave a workspace, while removing someone else requires
+/// being an admin with a role at least as high as theirs. The last owner
+/// can't leave.
+/// 
+/// # Arguments
+/// * `member` - The member removing someone, or leaving
+/// * `user_id` - The ID of the member to remove
+/// * `pool` - Database connection pool
+/// 
+/// # Returns
+/// Result indicating success or failure
+pub async fn remove_member(
+    member: &Member,
+    user_id: &str,
+    pool: &PgPool,
+) -> Result<(), std::io::Error> {
+    let uuid = parse_uuid(user_id)?;
+    if uuid != member.user.id {
+        member.require(WorkspaceRole::Admin)?;
+    }
+
+    let mut tx = pool
+        .begin()
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+    lock_workspace(member.workspace_id, &mut tx).await?;
+    let current = member_role(member.workspace_id, uuid, &mut tx).await?;
+    if current > member.role {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::PermissionDenied,
+            "You can't remove members with a role above your own",
+        ));
+    }
+    if current == WorkspaceRole::Owner {
+        ensure_other_owner(member.workspace_id, uuid, &mut tx).await?;
+    }
+
+    sqlx::query("DELETE FROM workspace_members WHERE workspace_id = $1 AND user_id = $2")
+        .bind(member.workspace_id)
+        .bind(uuid)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+    tx.commit()
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        service::{create_url, delete_url, list_urls, LinkFilter},
+        structs::ShortenedUrl,
+        trash::purge_url,
+        utils::{create_test_user, delete_test_users, init_test_db},
+    };
+
+    /// Creates a workspace owned by a user
+    /// 
+    /// # Arguments
+    /// * `owner` - The user creating the workspace
+    /// * `pool` - Database connection pool
+    /// 
+    /// # Returns
+    /// The owner acting in the new workspace
+    async fn create_team(owner: &User, pool: &PgPool) -> Member {
+        let workspace = create_workspace(owner, "Team", pool).await.unwrap();
+        load_member(owner.clone(), Some(&workspace.id.to_string()), pool)
+            .await
+            .unwrap()
+    }
+
+    /// Invites a user to a workspace and accepts the invitation
+    /// 
+    /// # Arguments
+    /// * `inviter` - The member sending the invitation
+    /// * `user` - The user joining the workspace
+    /// * `role` - The role of the user in the workspace
+    /// * `pool` - Database connection pool
+    /// 
+    /// # Returns
+    /// The user acting in the workspace
+    async fn add_member(
+        inviter: &Member,
+        user: &User,
+        role: WorkspaceRole,
+        pool: &PgPool,
+    ) -> Member {
+        let invitation = invite_member(inviter, &user.username, role, pool)
+            .await
+            .unwrap();
+        accept_invitation(user, &invitation.id.to_string(), pool)
+            .await
+            .unwrap();
+        let id = inviter.workspace_id.to_string();
+        load_member(user.clone(), Some(&id), pool).await.unwrap()
+    }
+
+    /// Creates a URL in the workspace of a member
+    /// 
+    /// # Arguments
+    /// * `member` - The member creating the URL
+    /// * `pool` - Database connection pool
+    /// 
+    /// # Returns
+    /// Result containing the created URL
+    async fn create_link(member: &Member, pool: &PgPool) -> Result<ShortenedUrl, std::io::Error> {
+        create_url(
+            member,
+            "https://example.com/",
+            None,
+            None,
+            None,
+            &[],
+            None,
+            pool,
+        )
+        .await
+    }
+
+    /// Tests that created workspaces are owned by their creator and listed after the personal one
+    #[actix_rt::test]
+    async fn test_create_workspace() {
+        let pool = init_test_db().await;
+        let owner = create_test_user(&pool, "ws_create").await;
+
+        let workspace = create_workspace(&owner, "  Team  ", &pool).await.unwrap();
+        assert_eq!(workspace.name, "Team");
+        assert_eq!(workspace.role, "owner");
+        assert!(!workspace.personal);
+        let workspaces = list_workspaces(&owner, &pool).await.unwrap();
+        assert_eq!(workspaces.len(), 2);
+        assert!(workspaces[0].personal);
+        assert_eq!(workspaces[1].id, workspace.id);
+
+        delete_test_users(&pool, &[owner.id]).await;
+    }
+
+    /// Tests that workspaces need a name
+    #[actix_rt::test]
+    async fn test_create_workspace_empty_name() {
+        let pool = init_test_db().await;
+        let owner = create_test_user(&pool, "ws_empty").await;
+
+        let result = create_workspace(&owner, " ", &pool).await;
+        assert!(result.is_err_and(|e| e.kind() == std::io::ErrorKind::InvalidInput));
+
+        delete_test_users(&pool, &[owner.id]).await;
+    }
+
+    /// Tests that invited users only join once they accept the invitation themselves
+    #[actix_rt::test]
+    async fn test_accept_invitation() {
+        let pool = init_test_db().await;
+        let owner = create_test_user(&pool, "ws_inviter").await;
+        let invitee = create_test_user(&pool, "ws_invitee").await;
+        let other = create_test_user(&pool, "ws_bystander").await;
+        let owner_member = create_team(&owner, &pool).await;
+        let id = owner_member.workspace_id.to_string();
+
+        let invitation = invite_member(
+            &owner_member,
+            &invitee.username,
+            WorkspaceRole::Viewer,
+            &pool,
+        )
+        .await
+        .unwrap();
+        assert_eq!(invitation.workspace_name, "Team");
+        assert_eq!(list_invitations(&invitee, &pool).await.unwrap().len(), 1);
+        let result = load_member(invitee.clone(), Some(&id), &pool).await;
+        assert!(result.is_err_and(|e| e.kind() == std::io::ErrorKind::NotFound));
+        let result = accept_invitation(&other, &invitation.id.to_string(), &pool).await;
+        assert!(result.is_err_and(|e| e.kind() == std::io::ErrorKind::NotFound));
+
+        accept_invitation(&invitee, &invitation.id.to_string(), &pool)
+            .await
+            .unwrap();
+        assert!(list_invitations(&invitee, &pool).await.unwrap().is_empty());
+        let member = load_member(invitee.clone(), Some(&id), &pool)
+            .await
+            .unwrap();
+        assert_eq!(member.role, WorkspaceRole::Viewer);
+
+        delete_test_users(&pool, &[owner.id, invitee.id, other.id]).await;
+    }
+
+    /// Tests that members can't be invited again
+    #[actix_rt::test]
+    async fn test_invite_existing_member() {
+        let pool = init_test_db().await;
+        let owner = create_test_user(&pool, "ws_reinviter").await;
+        let viewer = create_test_user(&pool, "ws_reinvited").await;
+        let owner_member = create_team(&owner, &pool).await;
+        add_member(&owner_member, &viewer, WorkspaceRole::Viewer, &pool).await;
+
+        let result = invite_member(
+            &owner_member,
+            &viewer.username,
+            WorkspaceRole::Editor,
+            &pool,
+        )
+        .await;
+        assert!(result.is_err_and(|e| e.kind() == std::io::ErrorKind::AlreadyExists));
+
+        delete_test_users(&pool, &[owner.id, viewer.id]).await;
+    }
+
+    /// Tests that members are listed by role, highest first
+    #[actix_rt::test]
+    async fn test_list_members() {
+        let pool = init_test_db().await;
+        let owner = create_test_user(&pool, "ws_list_owner").await;
+        let viewer = create_test_user(&pool, "ws_list_viewer").await;
+        let editor = create_test_user(&pool, "ws_list_editor").await;
+        let owner_member = create_team(&owner, &pool).await;
+        add_member(&owner_member, &viewer, WorkspaceRole::Viewer, &pool).await;
+        add_member(&owner_member, &editor, WorkspaceRole::Editor, &pool).await;
+
+        let members = list_members(&owner_member, &pool).await.unwrap();
+        let roles: Vec<&str> = members.iter().map(|m| m.role.as_str()).collect();
+        assert_eq!(roles, vec!["owner", "editor", "viewer"]);
+
+        delete_test_users(&pool, &[owner.id, viewer.id, editor.id]).await;
+    }
+
+    /// Tests that viewers can read the links of the workspace but not create them
+    #[actix_rt::test]
+    async fn test_viewer_is_read_only() {
+        let pool = init_test_db().await;
+        let owner = create_test_user(&pool, "ws_ro_owner").await;
+        let viewer = create_test_user(&pool, "ws_ro_viewer").await;
+        let owner_member = create_team(&owner, &pool).await;
+        let viewer_member = add_member(&owner_member, &viewer, WorkspaceRole::Viewer, &pool).await;
+        create_link(&owner_member, &pool).await.unwrap();
+
+        let result = create_link(&viewer_member, &pool).await;
+        assert!(result.is_err_and(|e| e.kind() == std::io::ErrorKind::PermissionDenied));
+        let (listed, _) = list_urls(&viewer_member, &LinkFilter::default(), &pool)
+            .await
+            .unwrap();
+        assert_eq!(listed.len(), 1);
+
+        delete_test_users(&pool, &[owner.id, viewer.id]).await;
+    }
+
+    /// Tests that links created by editors belong to the workspace rather than the editor
+    #[actix_rt::test]
+    async fn test_editor_creates_workspace_links() {
+        let pool = init_test_db().await;
+        let owner = create_test_user(&pool, "ws_ed_owner").await;
+        let editor = create_test_user(&pool, "ws_ed_editor").await;
+        let owner_member = create_team(&owner, &pool).await;
+        let editor_member = add_member(&owner_member, &editor, WorkspaceRole::Editor, &pool).await;
+
+        let url = create_link(&editor_member, &pool).await.unwrap();
+        assert_eq!(url.owner, owner_member.workspace_id);
+        let (listed, _) = list_urls(&Member::personal(&editor), &LinkFilter::default(), &pool)
+            .await
+            .unwrap();
+        assert!(listed.is_empty());
+
+        delete_test_users(&pool, &[owner.id, editor.id]).await;
+    }
+
+    /// Tests that editors can't invite members
+    #[actix_rt::test]
+    async fn test_editor_cannot_invite() {
+        let pool = init_test_db().await;
+        let owner = create_test_user(&pool, "ws_inv_owner").await;
+        let editor = create_test_user(&pool, "ws_inv_editor").await;
+        let other = create_test_user(&pool, "ws_inv_other").await;
+        let owner_member = create_team(&owner, &pool).await;
+        let editor_member = add_member(&owner_member, &editor, WorkspaceRole::Editor, &pool).await;
+
+        let result = invite_member(
+            &editor_member,
+            &other.username,
+            WorkspaceRole::Viewer,
+            &pool,
+        )
+        .await;
+        assert!(result.is_err_and(|e| e.kind() == std::io::ErrorKind::PermissionDenied));
+
+        delete_test_users(&pool, &[owner.id, editor.id, other.id]).await;
+    }
+
+    /// Tests that the last owner can neither give up the role nor leave
+    #[actix_rt::test]
+    async fn test_last_owner_stays() {
+        let pool = init_test_db().await;
+        let owner = create_test_user(&pool, "ws_last_owner").await;
+        let owner_member = create_team(&owner, &pool).await;
+
+        let result = change_member_role(
+            &owner_member,
+            &owner.id.to_string(),
+            WorkspaceRole::Admin,
+            &pool,
+        )
+        .await;
+        assert!(result.is_err_and(|e| e.kind() == std::io::ErrorKind::InvalidInput));
+        let result = remove_member(&owner_member, &owner.id.to_string(), &pool).await;
+        assert!(result.is_err_and(|e| e.kind() == std::io::ErrorKind::InvalidInput));
+
+        delete_test_users(&pool, &[owner.id]).await;
+    }
+
+    /// Tests that owners can change roles but admins can't make anyone an owner
+    #[actix_rt::test]
+    async fn test_change_member_role() {
+        let pool = init_test_db().await;
+        let owner = create_test_user(&pool, "ws_role_owner").await;
+        let member = create_test_user(&pool, "ws_role_member").await;
+        let owner_member = create_team(&owner, &pool).await;
+        add_member(&owner_member, &member, WorkspaceRole::Viewer, &pool).await;
+
+        let updated = change_member_role(
+            &owner_member,
+            &member.id.to_string(),
+            WorkspaceRole::Admin,
+            &pool,
+        )
+        .await
+        .unwrap();
+        assert_eq!(updated.role, "admin");
+        let id = owner_member.workspace_id.to_string();
+        let admin_member = load_member(member.clone(), Some(&id), &pool).await.unwrap();
+        let result = change_member_role(
+            &admin_member,
+            &member.id.to_string(),
+            WorkspaceRole::Owner,
+            &pool,
+        )
+        .await;
+        assert!(result.is_err_and(|e| e.kind() == std::io::ErrorKind::PermissionDenied));
+
+        delete_test_users(&pool, &[owner.id, member.id]).await;
+    }
+
+    /// Tests that admins can remove members, who lose access, but not owners
+    #[actix_rt::test]
+    async fn test_remove_member() {
+        let pool = init_test_db().await;
+        let owner = create_test_user(&pool, "ws_rm_owner").await;
+        let admin = create_test_user(&pool, "ws_rm_admin").await;
+        let editor = create_test_user(&pool, "ws_rm_editor").await;
+        let owner_member = create_team(&owner, &pool).await;
+        let admin_member = add_member(&owner_member, &admin, WorkspaceRole::Admin, &pool).await;
+        add_member(&owner_member, &editor, WorkspaceRole::Editor, &pool).await;
+
+        remove_member(&admin_member, &editor.id.to_string(), &pool)
+            .await
+            .unwrap();
+        let id = owner_member.workspace_id.to_string();
+        let result = load_member(editor.clone(), Some(&id), &pool).await;
+        assert!(result.is_err_and(|e| e.kind() == std::io::ErrorKind::NotFound));
+        let result = remove_member(&admin_member, &owner.id.to_string(), &pool).await;
+        assert!(result.is_err_and(|e| e.kind() == std::io::ErrorKind::PermissionDenied));
+
+        delete_test_users(&pool, &[owner.id, admin.id, editor.id]).await;
+    }
+
+    /// Tests that personal workspaces can't be shared or deleted
+    #[actix_rt::test]
+    async fn test_personal_workspace_not_shared() {
+        let pool = init_test_db().await;
+        let owner = create_test_user(&pool, "ws_personal").await;
+        let other = create_test_user(&pool, "ws_personal_other").await;
+
+        let personal = Member::personal(&owner);
+        let result = invite_member(&personal, &other.username, WorkspaceRole::Viewer, &pool).await;
+        assert!(result.is_err_and(|e| e.kind() == std::io::ErrorKind::InvalidInput));
+        let result = delete_workspace(&personal, &pool).await;
+        assert!(result.is_err_and(|e| e.kind() == std::io::ErrorKind::InvalidInput));
+
+        delete_test_users(&pool, &[owner.id, other.id]).await;
+    }
+
+    /// Tests that only owners delete workspaces, once their links are purged
+    #[actix_rt::test]
+    async fn test_delete_workspace() {
+        let pool = init_test_db().await;
+        let owner = create_test_user(&pool, "ws_del_owner").await;
+        let admin = create_test_user(&pool, "ws_del_admin").await;
+        let owner_member = create_team(&owner, &pool).await;
+        let admin_member = add_member(&owner_member, &admin, WorkspaceRole::Admin, &pool).await;
+        let url = create_link(&owner_member, &pool).await.unwrap();
+
+        let result = delete_workspace(&admin_member, &pool).await;
+        assert!(result.is_err_and(|e| e.kind() == std::io::ErrorKind::PermissionDenied));
+        let result = delete_workspace(&owner_member, &pool).await;
+        assert!(result.is_err_and(|e| e.kind() == std::io::ErrorKind::InvalidInput));
+
+        delete_url(&owner_member, &url.id.to_string(), &pool)
+            .await
+            .unwrap();
+        purge_url(&owner_member, &url.id.to_string(), &pool)
+            .await
+            .unwrap();
+        delete_workspace(&owner_member, &pool).await.unwrap();
+        let id = owner_member.workspace_id.to_string();
+        let result = load_member(admin.clone(), Some(&id), &pool).await;
+        assert!(result.is_err_and(|e| e.kind() == std::io::ErrorKind::NotFound));
+
+        delete_test_users(&pool, &[owner.id, admin.id]).await;
+    }
+}
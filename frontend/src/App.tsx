@@ -49,6 +49,9 @@ type ShortenedUrlResponse = {
   updated_at: string;
   owner: string;
   redirects: number;
+  version: number;
+  domain?: string;
+  link: string;
 };
 
 // Front-end representation of a URL item
@@ -60,6 +63,7 @@ type UrlItem = {
   createdAt: Date;
   expiresAt?: Date;
   clicks: number;
+  version: number;
 };
 
 // Type for POST/PUT request to /api/shorten
@@ -75,7 +79,6 @@ export default function App() {
   const [editForm] = Form.useForm();
   const [isEditModalVisible, setIsEditModalVisible] = React.useState(false);
   const [isReplaceModalVisible, setIsReplaceModalVisible] = React.useState(false);
-  const setToken = useAuthStore((s) => s.setToken);
   const token = useAuthStore((s) => s.token);
   const [editingUrl, setEditingUrl] = React.useState<UrlItem | null>(null);
   const [duplicateUrl, setDuplicateUrl] = React.useState<UrlItem | null>(null);
@@ -110,11 +113,12 @@ export default function App() {
   const mapResponseToUrlItem = (url: ShortenedUrlResponse): UrlItem => ({
     id: url.id,
     original: url.original_url,
-    shortened: `${FRONTEND_URL}/${url.short_url}`,
+    shortened: url.link,
     customPath: url.short_url,
     createdAt: new Date(url.created_at),
     expiresAt: url.expiry_date ? new Date(url.expiry_date) : undefined,
     clicks: url.redirects,
+    version: url.version,
   });
 
   // Fetch all shortened URLs
@@ -165,6 +169,8 @@ export default function App() {
     },
     onError: (error) => {
       message.error('Failed to update URL: ' + (error instanceof Error ? error.message : 'Unknown error'));
+      // The URL may have been changed by someone else, so show its current state
+      queryClient.invalidateQueries({ queryKey: ['shortenedUrls'] });
     },
   });
 
@@ -265,6 +271,7 @@ export default function App() {
       updateUrlMutation.mutate(
         {
           id: duplicateUrl.id,
+          version: duplicateUrl.version,
           ...pendingUrlData,
         },
         {
@@ -289,9 +296,8 @@ export default function App() {
     message.info('Copied to clipboard!');
   };
 
-  const handleLogout = () => {
-    // This would contain actual logout logic in a real app
-    setToken(null);
+  const handleLogout = async () => {
+    await api.logout();
     message.success('Successfully logged out');
   };
 
@@ -344,6 +350,7 @@ export default function App() {
         updateUrlMutation.mutate(
           {
             id: editingUrl.id,
+            version: editingUrl.version,
             original_url: values.original,
             custom_path: values.customPath || undefined,
             expiration,
@@ -15,7 +15,7 @@ type AuthForm = {
 
 export default function Auth() {
   const [loading, setLoading] = React.useState(false);
-  const setToken = useAuthStore((s) => s.setToken);
+  const setSession = useAuthStore((s) => s.setSession);
   const [loginError, setLoginError] = React.useState(false);
   const [passwordVisible, setPasswordVisible] = React.useState(false);
   const [form] = Form.useForm();
@@ -29,7 +29,7 @@ export default function Auth() {
         if (response.error != null) {
           setLoginError(true);
         } else {
-          setToken(response.data.token);
+          setSession(response.data);
           m.success('Successfully logged in');
         }
       } catch {
@@ -1,9 +1,22 @@
 import { create } from 'zustand';
 import { createJSONStorage, persist } from 'zustand/middleware';
 
+export type Session = {
+  token: string;
+  refresh_token: string;
+  // The number of seconds the access token is valid for
+  expires_in: number;
+};
+
 interface AuthState {
   token: string | null;
+  // Exchanged for the next access token, usable once
+  refreshToken: string | null;
+  // When the access token expires, in milliseconds since the epoch
+  expiresAt: number | null;
+  // Clears the whole session when set to null
   setToken: (token: string | null) => void;
+  setSession: (session: Session) => void;
   isHydrated: boolean;
 }
 
@@ -11,7 +24,16 @@ export const useAuthStore = create<AuthState>()(
   persist(
     (set) => ({
       token: null,
-      setToken: (token: string | null) => set({ token }),
+      refreshToken: null,
+      expiresAt: null,
+      setToken: (token: string | null) =>
+        set(token == null ? { token, refreshToken: null, expiresAt: null } : { token }),
+      setSession: (session: Session) =>
+        set({
+          token: session.token,
+          refreshToken: session.refresh_token,
+          expiresAt: Date.now() + session.expires_in * 1000,
+        }),
       isHydrated: false,
     }),
     {
@@ -29,3 +51,13 @@ export const useAuthStore = create<AuthState>()(
     }
   )
 );
+
+// Another tab rotated the session or logged out, so pick up its tokens instead of spending a used refresh token.
+// Routes are also prerendered outside the browser, where there is no window to listen on
+if (typeof window !== 'undefined') {
+  window.addEventListener('storage', (e) => {
+    if (e.key === 'auth') {
+      void useAuthStore.persist.rehydrate();
+    }
+  });
+}
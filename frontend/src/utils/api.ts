@@ -1,15 +1,33 @@
 import { BACKEND_URL } from './constants';
 import { StatusCodes } from 'http-status-codes';
-import axios from 'axios';
-import { useAuthStore } from '$/store/auth';
+import axios, { type InternalAxiosRequestConfig } from 'axios';
+import { type Session, useAuthStore } from '$/store/auth';
+
+type Pagination = {
+  total: number;
+  limit: number;
+  has_more: boolean;
+  next_cursor: string | null;
+};
 
 type APIResponse<T = unknown> = {
   error: string | null;
   data: T;
+  pagination?: Pagination;
 };
 
 type RegisterAPIResponse = APIResponse<{ target_field: string } | null>;
-type LoginAPIResponse = APIResponse<{ token: string }>;
+type LoginAPIResponse = APIResponse<Session>;
+
+// Access tokens expiring sooner than this (in milliseconds) are refreshed before a request is sent
+const REFRESH_MARGIN_MS = 30 * 1000;
+
+type RetriableRequestConfig = InternalAxiosRequestConfig & { retried?: boolean };
+
+// Runs the callback while holding a lock shared by every tab, or right away where Web Locks aren't supported
+async function withRefreshLock<T>(callback: () => Promise<T>): Promise<T> {
+  return 'locks' in navigator ? navigator.locks.request('auth-refresh', callback) : callback();
+}
 
 export type ShortenedURL = {
   id: string;
@@ -20,6 +38,11 @@ export type ShortenedURL = {
   updated_at: string;
   owner: string;
   redirects: number;
+  version: number;
+  // Hostname of the custom domain, absent for the default domain
+  domain?: string;
+  // The full short URL on the link's domain
+  link: string;
 };
 
 export type ShortenURLData = {
@@ -30,6 +53,8 @@ export type ShortenURLData = {
 
 export type UpdateURLRequest = ShortenURLData & {
   id: string;
+  // The version of the URL the update is based on, sent as If-Match
+  version: number;
 };
 
 class API {
@@ -37,6 +62,86 @@ class API {
     baseURL: BACKEND_URL,
   });
 
+  // The refresh in flight, shared so that a used refresh token is never sent twice
+  private refreshing: Promise<string | null> | null = null;
+
+  constructor() {
+    // Only authenticated requests carry an Authorization header, so logging in and
+    // refreshing itself never trigger a refresh
+    this.api.interceptors.request.use(async (config) => {
+      const { refreshToken, expiresAt } = useAuthStore.getState();
+      const expiresSoon = expiresAt != null && expiresAt - Date.now() < REFRESH_MARGIN_MS;
+      if (config.headers.Authorization && refreshToken && expiresSoon) {
+        const token = await this.refreshSession();
+        if (token) {
+          config.headers.Authorization = `Bearer ${token}`;
+        }
+      }
+
+      return config;
+    });
+
+    // Retry requests rejected with 401 once with a refreshed access token
+    this.api.interceptors.response.use(undefined, async (e) => {
+      const config = axios.isAxiosError(e) ? (e.config as RetriableRequestConfig | undefined) : undefined;
+      if (
+        e.response?.status !== StatusCodes.UNAUTHORIZED ||
+        !config?.headers.Authorization ||
+        config.retried ||
+        !useAuthStore.getState().refreshToken
+      ) {
+        throw e;
+      }
+
+      const token = await this.refreshSession();
+      if (!token) {
+        throw e;
+      }
+
+      config.retried = true;
+      config.headers.Authorization = `Bearer ${token}`;
+      return this.api.request(config);
+    });
+  }
+
+  // Exchanges the refresh token for a new access token, clearing the session if it was rejected
+  private refreshSession(): Promise<string | null> {
+    if (!this.refreshing) {
+      const staleToken = useAuthStore.getState().token;
+      // Tabs share the session, so they refresh one at a time with the refresh token last stored by any of them
+      this.refreshing = withRefreshLock(async () => {
+        await useAuthStore.persist.rehydrate();
+        const { token, refreshToken, expiresAt } = useAuthStore.getState();
+        // Another tab already refreshed while this one waited for the lock
+        if (token && token !== staleToken && expiresAt != null && expiresAt - Date.now() >= REFRESH_MARGIN_MS) {
+          return token;
+        }
+        if (!refreshToken) {
+          return null;
+        }
+
+        return this.api
+          .post('/api/auth/refresh', { refresh_token: refreshToken })
+          .then((response) => {
+            const session: Session = response.data.data;
+            useAuthStore.getState().setSession(session);
+            return session.token;
+          })
+          .catch((e) => {
+            // The session is only over once the backend rejects the refresh token
+            if (axios.isAxiosError(e) && e.response?.status === StatusCodes.UNAUTHORIZED) {
+              useAuthStore.getState().setToken(null);
+            }
+            return null;
+          });
+      }).finally(() => {
+        this.refreshing = null;
+      });
+    }
+
+    return this.refreshing;
+  }
+
   public async isAlive(signal?: AbortSignal): Promise<boolean> {
     try {
       const response = await this.api.get(`/health`, { signal });
@@ -76,11 +181,24 @@ class API {
 
       return {
         error: 'An unexpected error occurred. Please try again later.',
-        data: { token: '' },
+        data: { token: '', refresh_token: '', expires_in: 0 },
       } as LoginAPIResponse;
     }
   }
 
+  public async logout(): Promise<void> {
+    const refreshToken = useAuthStore.getState().refreshToken;
+    try {
+      if (refreshToken) {
+        await this.api.post('/api/auth/logout', { refresh_token: refreshToken });
+      }
+    } catch {
+      // The session is forgotten locally even if the backend can't be reached
+    } finally {
+      useAuthStore.getState().setToken(null);
+    }
+  }
+
   public async register(username: string, password: string, confirmPassword: string): Promise<RegisterAPIResponse> {
     try {
       await this.api.post('/api/register', {
@@ -107,10 +225,19 @@ class API {
   // New methods for URL shortening API
   public async getShortenedURLs(): Promise<APIResponse<ShortenedURL[]>> {
     try {
-      const response = await this.api.get('/api/shorten', {
-        headers: { Authorization: `Bearer ${useAuthStore.getState().token}` },
-      });
-      return response.data;
+      // The list is paginated, so follow the cursors until every page is fetched
+      const urls: ShortenedURL[] = [];
+      let cursor: string | null = null;
+      do {
+        const response: { data: APIResponse<ShortenedURL[]> } = await this.api.get('/api/shorten', {
+          headers: { Authorization: `Bearer ${useAuthStore.getState().token}` },
+          params: { limit: 200, cursor: cursor ?? undefined },
+        });
+        urls.push(...response.data.data);
+        cursor = response.data.pagination?.next_cursor ?? null;
+      } while (cursor);
+
+      return { error: null, data: urls };
     } catch (e) {
       if (axios.isAxiosError(e)) {
         return e.response?.data as APIResponse<ShortenedURL[]>;
@@ -141,10 +268,13 @@ class API {
     }
   }
 
-  public async updateShortenedURL(urlData: UpdateURLRequest): Promise<APIResponse<ShortenedURL>> {
+  public async updateShortenedURL({ version, ...urlData }: UpdateURLRequest): Promise<APIResponse<ShortenedURL>> {
     try {
       const response = await this.api.put('/api/shorten', urlData, {
-        headers: { Authorization: `Bearer ${useAuthStore.getState().token}` },
+        headers: {
+          Authorization: `Bearer ${useAuthStore.getState().token}`,
+          'If-Match': `"${version}"`,
+        },
       });
       return response.data;
     } catch (e) {